@@ -0,0 +1,372 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::{Url, WebsiteStatus};
+
+/// How long a flapping target's state changes are remembered, unless configured otherwise.
+pub const DEFAULT_FLAP_WINDOW: Duration = Duration::from_secs(600);
+
+/// Whether a target is considered reachable.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Up,
+    Down,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            State::Up => f.write_str("up"),
+            State::Down => f.write_str("down"),
+        }
+    }
+}
+
+/// A target changing state, as passed to notifiers.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Transition {
+    pub name: String,
+    pub url: Url,
+    pub from: State,
+    pub to: State,
+    /// Why the check that caused the transition failed, for UP→DOWN.
+    pub reason: Option<String>,
+    /// RFC 3339 timestamp of that check.
+    pub timestamp: String,
+}
+
+/// When a state change is worth announcing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertPolicy {
+    /// Consecutive failed checks before a target counts as down.
+    pub failure_threshold: u32,
+    /// Once a target changes state more than this many times within `flap_window`,
+    /// notifications are held back until it settles. `None` disables flap suppression.
+    pub flap_limit: Option<usize>,
+    pub flap_window: Duration,
+}
+
+impl Default for AlertPolicy {
+    fn default() -> Self {
+        AlertPolicy {
+            failure_threshold: 1,
+            flap_limit: None,
+            flap_window: DEFAULT_FLAP_WINDOW,
+        }
+    }
+}
+
+struct TargetState {
+    state: State,
+    /// The last state notifiers were told about.
+    notified: State,
+    consecutive_failures: u32,
+    changes: VecDeque<Instant>,
+}
+
+/// Follows the state of every URL and reports the transitions that should be announced.
+///
+/// Targets start out as up, so a site that is down on the first check is reported.
+pub struct StateTracker {
+    policy: AlertPolicy,
+    targets: HashMap<Url, TargetState>,
+}
+
+impl StateTracker {
+    pub fn new(policy: AlertPolicy) -> Self {
+        StateTracker { policy, targets: HashMap::new() }
+    }
+
+    /// Records a check result seen at `now`, returning the transition to announce, if any.
+    pub fn observe(&mut self, status: &WebsiteStatus, now: Instant) -> Option<Transition> {
+        let policy = &self.policy;
+        let entry = self.targets.entry(status.url.clone()).or_insert_with(|| TargetState {
+            state: State::Up,
+            notified: State::Up,
+            consecutive_failures: 0,
+            changes: VecDeque::new(),
+        });
+
+        let next = if status.verdict.is_pass() {
+            entry.consecutive_failures = 0;
+            State::Up
+        } else {
+            entry.consecutive_failures = entry.consecutive_failures.saturating_add(1);
+            if entry.consecutive_failures >= policy.failure_threshold {
+                State::Down
+            } else {
+                entry.state
+            }
+        };
+
+        if next != entry.state {
+            entry.state = next;
+            entry.changes.push_back(now);
+        }
+        while entry.changes.front().is_some_and(|&change| now.duration_since(change) > policy.flap_window) {
+            entry.changes.pop_front();
+        }
+
+        // A target that settles after flapping is announced in the state it settled in
+        let flapping = policy.flap_limit.is_some_and(|limit| entry.changes.len() > limit);
+        if flapping || entry.state == entry.notified {
+            return None;
+        }
+
+        let from = entry.notified;
+        entry.notified = entry.state;
+        Some(Transition {
+            name: status.name.clone(),
+            url: status.url.clone(),
+            from,
+            to: entry.state,
+            reason: status.verdict.failure().map(|reason| reason.to_string()),
+            timestamp: humantime::format_rfc3339_millis(status.timestamp).to_string(),
+        })
+    }
+}
+
+/// Something that wants to hear about state transitions.
+pub trait Notifier: Send {
+    fn notify(&self, transition: &Transition) -> Result<(), String>;
+}
+
+/// POSTs each transition as JSON to a URL.
+pub struct WebhookNotifier {
+    pub url: Url,
+    pub timeout: Duration,
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, transition: &Transition) -> Result<(), String> {
+        let payload = serde_json::to_string(transition).map_err(|err| err.to_string())?;
+        ureq::post(&self.url)
+            .timeout(self.timeout)
+            .set("Content-Type", "application/json")
+            .send_string(&payload)
+            .map_err(|err| format!("webhook {}: {}", self.url, err))?;
+        Ok(())
+    }
+}
+
+/// Runs a shell command with the transition in `ALERT_*` environment variables.
+pub struct CommandNotifier {
+    pub command: String,
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&self, transition: &Transition) -> Result<(), String> {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+
+        let status = command
+            .arg(&self.command)
+            .env("ALERT_NAME", &transition.name)
+            .env("ALERT_URL", &transition.url)
+            .env("ALERT_FROM", transition.from.to_string())
+            .env("ALERT_STATE", transition.to.to_string())
+            .env("ALERT_REASON", transition.reason.as_deref().unwrap_or_default())
+            .env("ALERT_TIMESTAMP", &transition.timestamp)
+            .status()
+            .map_err(|err| format!("command `{}`: {}", self.command, err))?;
+
+        if !status.success() {
+            return Err(format!("command `{}` exited with {}", self.command, status));
+        }
+        Ok(())
+    }
+}
+
+/// Appends each transition to a file as a line of JSON.
+pub struct LogNotifier {
+    pub path: PathBuf,
+}
+
+impl Notifier for LogNotifier {
+    fn notify(&self, transition: &Transition) -> Result<(), String> {
+        let line = serde_json::to_string(transition).map_err(|err| err.to_string())?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", line))
+            .map_err(|err| format!("alert log {}: {}", self.path.display(), err))
+    }
+}
+
+/// Feeds check results through a `StateTracker` and fans transitions out to notifiers.
+pub struct Alerter {
+    tracker: StateTracker,
+    notifiers: Vec<Box<dyn Notifier>>,
+}
+
+impl Alerter {
+    pub fn new(policy: AlertPolicy, notifiers: Vec<Box<dyn Notifier>>) -> Self {
+        Alerter { tracker: StateTracker::new(policy), notifiers }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.notifiers.is_empty()
+    }
+
+    /// Tracks a result and notifies every notifier of a transition. A failing notifier
+    /// is reported but does not stop the others.
+    pub fn handle(&mut self, status: &WebsiteStatus) {
+        if let Some(transition) = self.tracker.observe(status, Instant::now()) {
+            for notifier in &self.notifiers {
+                if let Err(err) = notifier.notify(&transition) {
+                    eprintln!("Failed to send alert: {}", err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, temp_path, MockResponse, MockServer};
+    use std::time::UNIX_EPOCH;
+
+    fn status(url: &str, up: bool) -> WebsiteStatus {
+        WebsiteStatus {
+            name: "Homepage".to_string(),
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_700_000_000_250),
+            ..testing::status(url, Ok(if up { 200 } else { 503 }))
+        }
+    }
+
+    fn transition(to: State) -> Transition {
+        Transition {
+            name: "Homepage".to_string(),
+            url: "https://example.com".to_string(),
+            from: if to == State::Up { State::Down } else { State::Up },
+            to,
+            reason: (to == State::Down).then(|| "unexpected status code 503".to_string()),
+            timestamp: "2023-11-14T22:13:20.250Z".to_string(),
+        }
+    }
+
+    /// Feeds up/down results one second apart and returns the announced states.
+    fn replay(tracker: &mut StateTracker, results: &[bool]) -> Vec<Option<State>> {
+        let start = Instant::now();
+        results
+            .iter()
+            .enumerate()
+            .map(|(i, &up)| {
+                let now = start + Duration::from_secs(i as u64);
+                tracker.observe(&status("https://example.com", up), now).map(|t| t.to)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detects_transitions() {
+        let mut tracker = StateTracker::new(AlertPolicy::default());
+        assert_eq!(
+            replay(&mut tracker, &[true, false, false, true, true]),
+            vec![None, Some(State::Down), None, Some(State::Up), None]
+        );
+
+        let first = tracker.observe(&status("https://other.example.com", false), Instant::now()).unwrap();
+        assert_eq!(first.from, State::Up);
+        assert_eq!(first.reason.as_deref(), Some("unexpected status code 503"));
+        assert_eq!(first.timestamp, "2023-11-14T22:13:20.250Z");
+    }
+
+    #[test]
+    fn test_failure_threshold() {
+        let mut tracker = StateTracker::new(AlertPolicy { failure_threshold: 3, ..AlertPolicy::default() });
+        assert_eq!(
+            replay(&mut tracker, &[false, false, true, false, false, false, true]),
+            vec![None, None, None, None, None, Some(State::Down), Some(State::Up)]
+        );
+    }
+
+    #[test]
+    fn test_flap_suppression() {
+        let mut tracker = StateTracker::new(AlertPolicy {
+            flap_limit: Some(2),
+            flap_window: Duration::from_secs(5),
+            ..AlertPolicy::default()
+        });
+
+        // The third change in the window is held back, and the settled state is announced
+        // once the earlier changes fall out of the window
+        assert_eq!(
+            replay(&mut tracker, &[false, true, false, false, false, false, false, false]),
+            vec![Some(State::Down), Some(State::Up), None, None, None, None, Some(State::Down), None]
+        );
+    }
+
+    #[test]
+    fn test_webhook_notifier() {
+        let server = MockServer::start(vec![MockResponse::new(204)]);
+        let notifier = WebhookNotifier { url: format!("{}alerts", server.url()), timeout: Duration::from_secs(5) };
+        notifier.notify(&transition(State::Down)).unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/alerts");
+        assert_eq!(request.header("Content-Type"), Some("application/json"));
+        let payload: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(payload["to"], "down");
+        assert_eq!(payload["from"], "up");
+        assert_eq!(payload["url"], "https://example.com");
+    }
+
+    #[test]
+    fn test_webhook_error_status() {
+        let server = MockServer::start(vec![MockResponse::new(500)]);
+        let notifier = WebhookNotifier { url: server.url(), timeout: Duration::from_secs(5) };
+        assert!(notifier.notify(&transition(State::Up)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_notifier() {
+        let path = temp_path("command");
+        let notifier = CommandNotifier {
+            command: format!(r#"echo "$ALERT_NAME $ALERT_FROM->$ALERT_STATE $ALERT_REASON" > {}"#, path.display()),
+        };
+        notifier.notify(&transition(State::Down)).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, "Homepage up->down unexpected status code 503\n");
+
+        let failing = CommandNotifier { command: "exit 3".to_string() };
+        assert!(failing.notify(&transition(State::Up)).is_err());
+    }
+
+    #[test]
+    fn test_log_notifier_appends() {
+        let path = temp_path("alerts.log");
+        let notifier = LogNotifier { path: path.clone() };
+        notifier.notify(&transition(State::Down)).unwrap();
+        notifier.notify(&transition(State::Up)).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let states: Vec<String> = written
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["to"].to_string())
+            .collect();
+        assert_eq!(states, vec![r#""down""#, r#""up""#]);
+    }
+}
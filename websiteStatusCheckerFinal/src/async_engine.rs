@@ -0,0 +1,587 @@
+//! A tokio-based engine for target lists too large for a pool of threads. Enabled
+//! with the `async` cargo feature.
+//!
+//! Every target becomes a task; a semaphore bounds how many requests are in flight
+//! at once, and a second one per host keeps any single origin from taking them all.
+//! One `reqwest::Client` is shared by every task, so connections to a host are kept
+//! alive and reused; targets with their own proxy, pins or local address share one
+//! per distinct set of options, and per port when they pin addresses. Responses are
+//! judged by the same code as the threaded engine, so both produce the same
+//! `WebsiteStatus` for the same server.
+//! reqwest does not expose its connections, so of the phase timings only the
+//! download is measured; connection reuse would make the others meaningless anyway.
+
+use std::collections::HashMap;
+use std::error::Error as _;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::http::{body_error, HttpError, HttpResponse, LimitedBody, Timings};
+use crate::metrics::Metrics;
+use crate::network::NetworkOptions;
+use crate::ratelimit;
+use crate::redirect;
+use crate::request::RequestSpec;
+use crate::{rejected, tls, tls_policy, CheckRun, Target, WebsiteStatus};
+
+/// Default bound on requests in flight across all hosts.
+pub const DEFAULT_CONCURRENCY: u32 = 500;
+/// Default bound on requests in flight to a single host, as browsers use.
+pub const DEFAULT_PER_HOST: u32 = 6;
+/// Unread response bodies up to this size are drained so the connection can be reused.
+const DRAIN_LIMIT: usize = 64 * 1024;
+
+/// Network options, and the port a client's pins were chosen for when they pin addresses.
+type Route = (NetworkOptions, Option<u16>);
+
+/// Checks targets concurrently on a tokio runtime, with bounded concurrency overall
+/// and per host.
+pub struct AsyncEngine {
+    client: reqwest::Client,
+    /// Clients for targets whose network options are not direct.
+    routed: Mutex<Vec<(Route, reqwest::Client)>>,
+    in_flight: Arc<Semaphore>,
+    per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+    metrics: Arc<Metrics>,
+}
+
+impl AsyncEngine {
+    /// An engine that makes at most `concurrency` requests at once, and at most
+    /// `per_host` of them to any one host. Both are raised to at least one.
+    pub fn new(concurrency: usize, per_host: usize) -> Result<Self, reqwest::Error> {
+        let per_host = per_host.max(1);
+        let client = client_builder(per_host).build()?;
+
+        Ok(AsyncEngine {
+            client,
+            routed: Mutex::new(Vec::new()),
+            in_flight: Arc::new(Semaphore::new(concurrency.max(1))),
+            per_host,
+            hosts: Mutex::new(HashMap::new()),
+            metrics: Arc::new(Metrics::new()),
+        })
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Checks every target once on a new multi-threaded runtime, calling `on_result`
+    /// as each check completes.
+    pub fn run(self: Arc<Self>, targets: Vec<Target>, on_result: impl FnMut(WebsiteStatus)) -> io::Result<()> {
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+        runtime.block_on(self.check_all(targets, on_result));
+        Ok(())
+    }
+
+    /// Checks every target once, calling `on_result` as each check completes.
+    pub async fn check_all(self: Arc<Self>, targets: Vec<Target>, mut on_result: impl FnMut(WebsiteStatus)) {
+        let mut checks = JoinSet::new();
+        for target in targets {
+            self.metrics.enqueued();
+            let engine = Arc::clone(&self);
+            checks.spawn(async move { engine.check(&target).await });
+        }
+
+        while let Some(joined) = checks.join_next().await {
+            match joined {
+                Ok(status) => {
+                    self.metrics.record(&status);
+                    on_result(status);
+                }
+                Err(err) => {
+                    self.metrics.discarded();
+                    eprintln!("A check task failed: {}", err);
+                }
+            }
+        }
+    }
+
+    /// Checks a target, retrying as its policy allows, and returns the final result.
+    ///
+    /// The concurrency limits apply to each attempt, so a target waiting out its
+    /// retry backoff does not hold up others.
+    pub async fn check(&self, target: &Target) -> WebsiteStatus {
+        // As in `Monitor`, a target that fails its checks is reported, not requested
+        if let Err(err) = target.validate() {
+            return rejected(target, &err);
+        }
+        let host = self.host_limit(&target.request.url);
+        let mut check = CheckRun::new(target);
+        loop {
+            // The host's permit is taken first, so a busy host does not tie up global permits
+            let host_permit = host.acquire().await.expect("host semaphores are never closed");
+            let permit = self.in_flight.acquire().await.expect("the engine semaphore is never closed");
+
+            let attempt_start = Instant::now();
+            let delay = match &target.probe {
+                Some(probe) => {
+                    // Probes use blocking sockets, so they run off the runtime's worker threads
+                    let (probe, timeout) = (probe.clone(), target.timeout);
+                    let observed = tokio::task::spawn_blocking(move || probe.attempt(timeout)).await.unwrap_or_else(|err| {
+                        Err(HttpError { message: format!("Probe failed to run: {}", err), retryable: false })
+                    });
+                    check.record_probe(observed, attempt_start.elapsed())
+                }
+                None => {
+                    let observed = self.execute(&target.request, target.timeout, check.body_limit()).await;
+                    check.record(observed, attempt_start.elapsed())
+                }
+            };
+            drop((permit, host_permit));
+
+            match delay {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => break,
+            }
+        }
+
+        let mut status = check.finish();
+        if let Some(policy) = tls_policy(target) {
+            // The handshake is made with blocking sockets, off the runtime's worker threads
+            let (request, deadline) = (target.request.clone(), Instant::now() + target.timeout);
+            let inspection = tokio::task::spawn_blocking(move || tls::inspect(&request.url, &request.network, deadline))
+                .await
+                .unwrap_or_else(|err| Err(format!("TLS inspection failed to run: {}", err)));
+            status.set_tls(inspection, policy);
+        }
+        status
+    }
+
+    /// The semaphore limiting requests to the origin of `url`, created on first use.
+    fn host_limit(&self, url: &str) -> Arc<Semaphore> {
+        let origin = ratelimit::origin(url);
+        let mut hosts = self.hosts.lock().unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(hosts.entry(origin).or_insert_with(|| Arc::new(Semaphore::new(self.per_host))))
+    }
+
+    /// The client for requests to `url` made with `network`, built on first use.
+    fn client(&self, network: &NetworkOptions, url: &str) -> Result<reqwest::Client, HttpError> {
+        if network.is_direct() {
+            return Ok(self.client.clone());
+        }
+        // reqwest pins a host on every port, where a pin applies to its own host and
+        // port only, so clients with pins are kept per port and given that port's pins
+        let port = if network.resolve.is_empty() {
+            None
+        } else {
+            url::Url::parse(url).ok().and_then(|url| url.port_or_known_default())
+        };
+        let key = (network.clone(), port);
+        let mut routed = self.routed.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, client)) = routed.iter().find(|(existing, _)| *existing == key) {
+            return Ok(client.clone());
+        }
+
+        let mut builder = client_builder(self.per_host).local_address(network.local_address);
+        if let Some(proxy) = &network.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy.url()).map_err(http_error)?);
+        }
+        let mut pins: HashMap<&str, Vec<SocketAddr>> = HashMap::new();
+        for pin in network.resolve.iter().filter(|pin| Some(pin.port) == port) {
+            pins.entry(&pin.host).or_default().push(SocketAddr::new(pin.address, pin.port));
+        }
+        for (host, addresses) in pins {
+            builder = builder.resolve_to_addrs(host, &addresses);
+        }
+        let client = builder.build().map_err(http_error)?;
+        routed.push((key, client.clone()));
+        Ok(client)
+    }
+
+    /// The async counterpart of `HttpClient::execute`. Redirects are followed here
+    /// rather than by reqwest, so the chain is recorded and the request's policy applies.
+    async fn execute(&self, request: &RequestSpec, timeout: Duration, body_limit: Option<u64>) -> Result<HttpResponse, HttpError> {
+        let deadline = Instant::now() + timeout;
+        let mut chain = Vec::new();
+        let mut request = request.clone();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(HttpError { message: "timed out following redirects".to_string(), retryable: true });
+            }
+            let mut response = self.send(&request, remaining, body_limit).await?;
+            match redirect::next_request(&request, &response, &request.redirects, &mut chain)? {
+                Some(next) => request = next,
+                None => {
+                    response.redirects = chain;
+                    return Ok(response);
+                }
+            }
+        }
+    }
+
+    async fn send(&self, request: &RequestSpec, timeout: Duration, body_limit: Option<u64>) -> Result<HttpResponse, HttpError> {
+        let method = reqwest::Method::from_bytes(request.method.as_str().as_bytes()).expect("methods are valid tokens");
+        let mut builder = self.client(&request.network, &request.url)?.request(method, &request.url).timeout(timeout);
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        if let Some(body) = &request.body {
+            if let (Some(content_type), None) = (body.content_type(), request.header("Content-Type")) {
+                builder = builder.header("Content-Type", content_type);
+            }
+            builder = builder.body(body.to_string());
+        }
+
+        let mut response = builder.send().await.map_err(http_error)?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str().to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+
+        let mut timings = Timings::default();
+        let body = match body_limit {
+            Some(limit) => {
+                let mut body = LimitedBody::new(limit);
+                while body.wants_more() {
+                    match response.chunk().await {
+                        Ok(Some(chunk)) => body.extend(&chunk),
+                        Ok(None) => break,
+                        Err(err) => return Err(body_error(error_chain(&err), true)),
+                    }
+                }
+                Some(body.finish(&mut timings))
+            }
+            None => {
+                tokio::spawn(drain(response));
+                None
+            }
+        };
+
+        Ok(HttpResponse { status, headers, body, timings, redirects: Vec::new() })
+    }
+}
+
+fn client_builder(per_host: usize) -> reqwest::ClientBuilder {
+    reqwest::Client::builder().redirect(reqwest::redirect::Policy::none()).pool_max_idle_per_host(per_host)
+}
+
+/// Reads a small unread body to the end off the check's critical path, which returns
+/// the connection to the pool; larger bodies are dropped along with their connection.
+async fn drain(mut response: reqwest::Response) {
+    let mut drained = 0;
+    while drained <= DRAIN_LIMIT {
+        match response.chunk().await {
+            Ok(Some(chunk)) => drained += chunk.len(),
+            Ok(None) | Err(_) => return,
+        }
+    }
+}
+
+fn http_error(err: reqwest::Error) -> HttpError {
+    // Malformed requests fail the same way every time
+    let retryable = !err.is_builder();
+    HttpError { message: error_chain(&err), retryable }
+}
+
+/// reqwest keeps the useful detail, such as "connection refused", in the error's sources.
+fn error_chain(err: &reqwest::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    use crate::criteria::{FailureReason, Verdict};
+    use crate::monitor::Monitor;
+    use crate::request::{Body, Method};
+    use crate::testing::{quick_retries, MockProxy, MockResponse, MockServer};
+    use crate::transport::TimedClient;
+    use crate::{check_website, Config};
+
+    fn run(engine: AsyncEngine, targets: Vec<Target>) -> Vec<WebsiteStatus> {
+        let mut results = Vec::new();
+        Arc::new(engine).run(targets, |status| results.push(status)).unwrap();
+        results
+    }
+
+    /// Checks `target` with each engine against its own server scripted with `responses`.
+    fn compare(responses: Vec<MockResponse>, target: impl Fn(&str) -> Target) -> (WebsiteStatus, WebsiteStatus) {
+        let server = MockServer::start(responses.clone());
+        let threaded = check_website(&TimedClient::default(), &target(&server.url()));
+
+        let server = MockServer::start(responses);
+        let mut results = run(AsyncEngine::new(10, 2).unwrap(), vec![target(&server.url())]);
+        (threaded, results.remove(0))
+    }
+
+    fn outcomes(status: &WebsiteStatus) -> Vec<Result<Option<u16>, bool>> {
+        status.attempts.iter().map(|attempt| attempt.outcome.clone().map_err(|_| true)).collect()
+    }
+
+    #[test]
+    fn test_engines_agree_on_invalid_targets() {
+        let config = Config::default();
+        let server = MockServer::start(Vec::new());
+        let mut head = Target::new(&server.url(), &config);
+        head.request.method = Method::Head;
+        head.request.body = Some(Body::Text("x".to_string()));
+        let invalid = vec![Target::new("tcp://db.internal", &config), head];
+
+        let mut threaded: Vec<_> = Monitor::start(&config, invalid.clone()).results().collect();
+        let mut evented = run(AsyncEngine::new(10, 2).unwrap(), invalid);
+        threaded.sort_by(|a, b| a.url.cmp(&b.url));
+        evented.sort_by(|a, b| a.url.cmp(&b.url));
+
+        assert_eq!(evented.len(), 2);
+        for (threaded, evented) in threaded.iter().zip(&evented) {
+            assert!(evented.status.as_ref().unwrap_err().starts_with("Invalid target: "));
+            assert_eq!(evented.status, threaded.status);
+            assert_eq!(evented.verdict, threaded.verdict);
+            assert_eq!(outcomes(evented), outcomes(threaded));
+        }
+        assert!(server.requests().is_empty());
+    }
+
+    #[test]
+    fn test_engines_agree_on_retries() {
+        let responses = vec![MockResponse::new(503), MockResponse::new(502), MockResponse::new(200)];
+        let (threaded, evented) =
+            compare(responses, |url| Target { retry: quick_retries(3), ..Target::new(url, &Config::default()) });
+
+        assert_eq!(evented.status, Ok(Some(200)));
+        assert_eq!(evented.verdict, threaded.verdict);
+        assert_eq!(outcomes(&evented), outcomes(&threaded));
+    }
+
+    #[test]
+    fn test_engines_agree_on_criteria() {
+        let responses = vec![MockResponse::new(200).header("X-Build", "42").body("Service error")];
+        let (threaded, evented) = compare(responses, |url| {
+            let mut target = Target::new(url, &Config::default());
+            target.criteria.body_contains = Some("All systems operational".to_string());
+            target
+        });
+        assert!(!evented.verdict.is_pass());
+        assert_eq!(evented.verdict, threaded.verdict);
+
+        let (threaded, evented) = compare(vec![MockResponse::new(404)], |url| Target::new(url, &Config::default()));
+        assert_eq!(evented.status, Ok(Some(404)));
+        assert_eq!(evented.verdict, threaded.verdict);
+    }
+
+    #[test]
+    fn test_engines_agree_on_redirects() {
+        let responses = vec![MockResponse::new(302).header("Location", "/loop"); 2];
+        let (threaded, evented) = compare(responses, |url| {
+            let mut target = Target::new(url, &Config::default());
+            target.request.redirects.max_redirects = 1;
+            target
+        });
+        assert_eq!(evented.verdict, Verdict::Fail(FailureReason::Redirect("more than 1 redirects".to_string())));
+        assert_eq!(evented.verdict, threaded.verdict);
+        assert_eq!(evented.redirects.len(), 2);
+        assert!(evented.redirects[1].location.ends_with("/loop"));
+        assert_eq!(threaded.redirects.len(), 2);
+    }
+
+    #[test]
+    fn test_engines_agree_on_request_errors() {
+        let refused = MockServer::refused_url();
+        let target = Target { retry: quick_retries(1), ..Target::new(&refused, &Config::default()) };
+        let threaded = check_website(&TimedClient::default(), &target);
+        let evented = run(AsyncEngine::new(10, 2).unwrap(), vec![target]).remove(0);
+        assert_eq!(outcomes(&evented), outcomes(&threaded));
+        assert_eq!(outcomes(&evented), vec![Err(true), Err(true)]);
+
+        let target = Target { retry: quick_retries(3), ..Target::new("not a url", &Config::default()) };
+        let evented = run(AsyncEngine::new(10, 2).unwrap(), vec![target]);
+        assert_eq!(evented[0].attempt_count(), 1);
+    }
+
+    #[test]
+    fn test_engines_agree_on_content_hash() {
+        let responses = vec![MockResponse::new(200).body("<h1>Welcome</h1>\n<p>Rendered 12:00</p>")];
+        let (threaded, evented) = compare(responses, |url| Target {
+            content: Some(crate::content::ContentPolicy {
+                strip_whitespace: true,
+                ignore: vec![regex::Regex::new("Rendered [0-9:]+").unwrap()],
+            }),
+            ..Target::new(url, &Config::default())
+        });
+
+        assert!(evented.content_hash.is_some());
+        assert_eq!(evented.content_hash, threaded.content_hash);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let server = MockServer::start(vec![MockResponse::new(200).delay(Duration::from_millis(500))]);
+        let target = Target {
+            timeout: Duration::from_millis(100),
+            retry: quick_retries(0),
+            ..Target::new(&server.url(), &Config::default())
+        };
+
+        let status = run(AsyncEngine::new(10, 2).unwrap(), vec![target]).remove(0);
+        assert!(status.status.is_err());
+        assert!(status.response_time < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_sends_method_headers_and_body() {
+        let server = MockServer::start(vec![MockResponse::new(201)]);
+        let config = Config {
+            method: crate::request::Method::Post,
+            headers: vec![("X-Token".to_string(), "secret".to_string())],
+            body: Some(crate::request::Body::Json(serde_json::json!({ "ping": true }))),
+            ..Config::default()
+        };
+
+        let status = run(AsyncEngine::new(10, 2).unwrap(), vec![Target::new(&server.url(), &config)]).remove(0);
+        assert_eq!(status.status, Ok(Some(201)));
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.header("x-token"), Some("secret"));
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        assert_eq!(request.body, br#"{"ping":true}"#);
+    }
+
+    #[test]
+    fn test_network_options() {
+        let server = MockServer::start(vec![MockResponse::new(200), MockResponse::new(204), MockResponse::new(202)]);
+        let proxy = MockProxy::socks5(server.addr(), Some(("user", "secret")));
+        let socks = NetworkOptions {
+            proxy: Some(proxy.url().replace("socks5://", "socks5h://user:secret@").parse().unwrap()),
+            ..NetworkOptions::default()
+        };
+        let pinned = NetworkOptions {
+            resolve: vec![format!("service.test:{}:127.0.0.1", server.addr().port()).parse().unwrap()],
+            ..NetworkOptions::default()
+        };
+        let target = |url: &str, network: &NetworkOptions| {
+            let mut target = Target::new(url, &Config::default());
+            target.request.network = network.clone();
+            target
+        };
+
+        let engine = Arc::new(AsyncEngine::new(1, 1).unwrap());
+        let mut results = Vec::new();
+        let targets = vec![
+            target("http://service.test/", &socks),
+            target("http://other.test/", &socks),
+            target(&format!("http://service.test:{}/", server.addr().port()), &pinned),
+        ];
+        Arc::clone(&engine).run(targets, |status| results.push(status.status)).unwrap();
+
+        assert!(results.iter().all(Result::is_ok), "{:?}", results);
+        assert_eq!(proxy.destinations(), vec!["service.test:80", "other.test:80"]);
+        assert_eq!(engine.routed.lock().unwrap().len(), 2);
+
+        // As with the threaded client, the pin does not carry over to another port
+        let other = MockServer::start(vec![MockResponse::new(200)]);
+        let status = run(AsyncEngine::new(1, 1).unwrap(), vec![target(&format!("http://service.test:{}/", other.addr().port()), &pinned)]);
+        assert!(status[0].status.is_err());
+        assert!(other.requests().is_empty());
+    }
+
+    /// A keep-alive server that answers every request on a connection after `delay`,
+    /// counting connections and the most requests it handled at once.
+    struct CountingServer {
+        url: String,
+        connections: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    impl CountingServer {
+        fn start(delay: Duration) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/", listener.local_addr().unwrap());
+            let connections = Arc::new(AtomicUsize::new(0));
+            let peak = Arc::new(AtomicUsize::new(0));
+            let active = Arc::new(AtomicUsize::new(0));
+
+            let (counted, peaked) = (Arc::clone(&connections), Arc::clone(&peak));
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(mut stream) = stream else { return };
+                    counted.fetch_add(1, Ordering::SeqCst);
+                    let (peak, active) = (Arc::clone(&peaked), Arc::clone(&active));
+                    thread::spawn(move || {
+                        let mut reader = BufReader::new(stream.try_clone().unwrap());
+                        let mut line = String::new();
+                        loop {
+                            // Read one request head; the checks send no bodies
+                            line.clear();
+                            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                                return;
+                            }
+                            while reader.read_line(&mut line).unwrap_or(0) > 2 {}
+
+                            let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                            peak.fetch_max(now, Ordering::SeqCst);
+                            thread::sleep(delay);
+                            active.fetch_sub(1, Ordering::SeqCst);
+                            if stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok").is_err() {
+                                return;
+                            }
+                        }
+                    });
+                }
+            });
+
+            CountingServer { url, connections, peak }
+        }
+
+        fn targets(&self, count: usize) -> Vec<Target> {
+            (0..count).map(|i| Target::new(&format!("{}{}", self.url, i), &Config::default())).collect()
+        }
+    }
+
+    #[test]
+    fn test_per_host_limit() {
+        let server = CountingServer::start(Duration::from_millis(50));
+        let results = run(AsyncEngine::new(100, 2).unwrap(), server.targets(8));
+
+        assert_eq!(results.len(), 8);
+        assert!(results.iter().all(|status| status.status == Ok(Some(200))));
+        assert_eq!(server.peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_global_limit() {
+        let servers: Vec<_> = (0..3).map(|_| CountingServer::start(Duration::from_millis(50))).collect();
+        let targets = servers.iter().flat_map(|server| server.targets(4)).collect();
+
+        let start = Instant::now();
+        let results = run(AsyncEngine::new(2, 4).unwrap(), targets);
+        assert_eq!(results.len(), 12);
+
+        // Twelve 50ms requests, two at a time, take at least six rounds
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(servers.iter().all(|server| server.peak.load(Ordering::SeqCst) <= 2));
+    }
+
+    #[test]
+    fn test_reuses_connections() {
+        let server = CountingServer::start(Duration::ZERO);
+        let engine = AsyncEngine::new(10, 1).unwrap();
+        let metrics = engine.metrics();
+
+        let results = run(engine, server.targets(5));
+        assert_eq!(results.len(), 5);
+        assert_eq!(server.connections.load(Ordering::SeqCst), 1);
+        assert_eq!(metrics.queue_depth(), 0);
+    }
+}
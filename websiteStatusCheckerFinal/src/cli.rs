@@ -0,0 +1,715 @@
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+#[cfg(feature = "async")]
+use clap::ValueEnum;
+
+use website_status_checker::alert::{
+    AlertPolicy, Alerter, CommandNotifier, LogNotifier, Notifier, WebhookNotifier, DEFAULT_FLAP_WINDOW,
+};
+#[cfg(feature = "async")]
+use website_status_checker::async_engine::{DEFAULT_CONCURRENCY, DEFAULT_PER_HOST};
+use website_status_checker::content::ContentPolicy;
+use website_status_checker::criteria::{HeaderRule, StatusRange, SuccessCriteria};
+use website_status_checker::history::HistoryStore;
+use website_status_checker::network::{NetworkOptions, Proxy, ResolveOverride};
+use website_status_checker::output::OutputFormat;
+use website_status_checker::ratelimit::HostLimits;
+use website_status_checker::redirect::{RedirectPolicy, DEFAULT_MAX_REDIRECTS};
+use website_status_checker::request::{self, Body, Method};
+use website_status_checker::retry::{Backoff, RetryPolicy, DEFAULT_MAX_DELAY, DEFAULT_RETRY_DELAY};
+use website_status_checker::tls::{TlsPolicy, DEFAULT_EXPIRY_WARNING_DAYS};
+use website_status_checker::{Config, ShutdownMode, DEFAULT_TIMEOUT, DEFAULT_WORKERS, MAX_RETRIES};
+
+/// Command-line arguments for the website status checker.
+#[derive(Parser, Debug)]
+#[command(version, about = "Check the status of a list of websites concurrently")]
+pub struct Cli {
+    /// Target list: a TOML file of `[[target]]` tables or one URL per line; `-` reads stdin
+    #[arg(value_name = "FILE", default_value = "urls.txt")]
+    pub input: PathBuf,
+
+    /// Number of worker threads
+    #[arg(short, long, value_name = "N", default_value_t = DEFAULT_WORKERS,
+          value_parser = clap::value_parser!(u16).range(1..))]
+    pub workers: u16,
+
+    /// Which engine runs the checks
+    #[cfg(feature = "async")]
+    #[arg(long, value_enum, default_value_t = Engine::Threads)]
+    pub engine: Engine,
+
+    /// Most requests the async engine makes at once
+    #[cfg(feature = "async")]
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_CONCURRENCY,
+          value_parser = clap::value_parser!(u32).range(1..))]
+    pub concurrency: u32,
+
+    /// Most requests the async engine makes to one host at once
+    #[cfg(feature = "async")]
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_PER_HOST,
+          value_parser = clap::value_parser!(u32).range(1..))]
+    pub per_host: u32,
+
+    /// Most checks running against one host at once
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_per_host: Option<u32>,
+
+    /// Sustained checks per second to one host
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    pub host_rate: Option<f64>,
+
+    /// Checks a host can receive back to back before --host-rate applies
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "host_rate",
+          value_parser = clap::value_parser!(u32).range(1..))]
+    pub host_burst: u32,
+
+    /// Least time between the starts of two checks to one host, in milliseconds
+    #[arg(long, value_name = "MS")]
+    pub crawl_delay: Option<u64>,
+
+    /// Request timeout in seconds
+    #[arg(short, long, value_name = "SECS", default_value_t = DEFAULT_TIMEOUT,
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: u64,
+
+    /// Number of retries after a failed first attempt
+    #[arg(short, long, value_name = "N", default_value_t = MAX_RETRIES)]
+    pub retries: u8,
+
+    /// How the delay between retries grows
+    #[arg(long, value_enum, default_value_t = Backoff::Jitter)]
+    pub backoff: Backoff,
+
+    /// Delay before the first retry, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = DEFAULT_RETRY_DELAY.as_millis() as u64)]
+    pub retry_delay: u64,
+
+    /// Upper bound on the delay between retries, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = DEFAULT_MAX_DELAY.as_millis() as u64)]
+    pub max_delay: u64,
+
+    /// HTTP method for targets that do not set their own (GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS)
+    #[arg(short = 'X', long, value_name = "METHOD", default_value = "GET")]
+    pub method: Method,
+
+    /// Request header as `Name: value` (repeatable)
+    #[arg(short = 'H', long, value_name = "HEADER", value_parser = request::parse_header)]
+    pub header: Vec<(String, String)>,
+
+    /// Request body to send
+    #[arg(short, long, value_name = "BODY", conflicts_with = "json")]
+    pub data: Option<String>,
+
+    /// JSON request body, sent with `Content-Type: application/json`
+    #[arg(long, value_name = "JSON", value_parser = parse_json)]
+    pub json: Option<serde_json::Value>,
+
+    /// User-Agent header to send
+    #[arg(short = 'A', long, value_name = "AGENT")]
+    pub user_agent: Option<String>,
+
+    /// Status codes that count as up, e.g. `200,301-302` or `2xx` [default: 200-399]
+    #[arg(long, value_name = "CODES", value_delimiter = ',')]
+    pub expect_status: Vec<StatusRange>,
+
+    /// Text the response body must contain
+    #[arg(long, value_name = "TEXT")]
+    pub expect_body: Option<String>,
+
+    /// Regular expression the response body must match
+    #[arg(long, value_name = "REGEX")]
+    pub expect_regex: Option<regex::Regex>,
+
+    /// Largest acceptable response body, in bytes
+    #[arg(long, value_name = "BYTES")]
+    pub max_body_bytes: Option<u64>,
+
+    /// Header the response must carry, as `Name: value` (repeatable)
+    #[arg(long, value_name = "HEADER")]
+    pub expect_header: Vec<HeaderRule>,
+
+    /// Slowest acceptable response, in milliseconds
+    #[arg(long, value_name = "MS")]
+    pub max_latency: Option<u64>,
+
+    /// For HTTPS URLs, also report the TLS version, cipher and certificate chain
+    #[arg(long)]
+    pub inspect_tls: bool,
+
+    /// Warn when a certificate expires within N days
+    #[arg(long, value_name = "DAYS", default_value_t = DEFAULT_EXPIRY_WARNING_DAYS, requires = "inspect_tls")]
+    pub cert_warning_days: u32,
+
+    /// Fail a check after following more than N redirects
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_REDIRECTS, conflicts_with = "no_follow_redirects")]
+    pub max_redirects: u32,
+
+    /// Judge the first response even when it is a redirect
+    #[arg(long)]
+    pub no_follow_redirects: bool,
+
+    /// Fail a check that is redirected from HTTPS to plain HTTP
+    #[arg(long)]
+    pub deny_https_downgrade: bool,
+
+    /// Hash each response body and report when it changes
+    #[arg(long)]
+    pub hash_body: bool,
+
+    /// Leave whitespace out of the body hash
+    #[arg(long, requires = "hash_body")]
+    pub hash_strip_whitespace: bool,
+
+    /// Leave regions matching REGEX out of the body hash (repeatable)
+    #[arg(long, value_name = "REGEX", requires = "hash_body")]
+    pub hash_ignore: Vec<regex::Regex>,
+
+    /// Send HTTP checks through a proxy: `http://`, `socks5://` or `socks5h://` URL
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<Proxy>,
+
+    /// Connect to HOST:PORT at ADDRESS instead of looking HOST up (repeatable)
+    #[arg(long, value_name = "HOST:PORT:ADDRESS")]
+    pub resolve: Vec<ResolveOverride>,
+
+    /// Make HTTP checks from this local IP address
+    #[arg(long, value_name = "IP")]
+    pub local_address: Option<IpAddr>,
+
+    /// Keep running and re-check every URL every SECS seconds until SIGINT/SIGTERM
+    #[arg(short, long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: Option<u64>,
+
+    /// On SIGINT/SIGTERM, finish queued checks (drain) or drop them (abort)
+    #[arg(long, value_enum, default_value_t = ShutdownMode::Drain)]
+    pub on_shutdown: ShutdownMode,
+
+    /// After SIGINT/SIGTERM, stop waiting for in-flight checks after SECS seconds
+    #[arg(long, value_name = "SECS")]
+    pub shutdown_timeout: Option<u64>,
+
+    /// Output format for the results
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// POST a JSON payload to URL when a target goes down or recovers (repeatable)
+    #[arg(long, value_name = "URL")]
+    pub webhook: Vec<String>,
+
+    /// Shell command to run when a target goes down or recovers, with details in `ALERT_*` variables
+    #[arg(long, value_name = "CMD")]
+    pub alert_command: Option<String>,
+
+    /// File to append a JSON line to when a target goes down or recovers
+    #[arg(long, value_name = "FILE")]
+    pub alert_log: Option<PathBuf>,
+
+    /// Consecutive failed checks before a target counts as down
+    #[arg(long, value_name = "N", default_value_t = 1,
+          value_parser = clap::value_parser!(u32).range(1..))]
+    pub alert_threshold: u32,
+
+    /// Hold back alerts for a target that changes state more than N times within --flap-window
+    #[arg(long, value_name = "N")]
+    pub flap_limit: Option<usize>,
+
+    /// Window for counting state changes towards --flap-limit, in seconds
+    #[arg(long, value_name = "SECS", default_value_t = DEFAULT_FLAP_WINDOW.as_secs(),
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub flap_window: u64,
+
+    /// Append every result to FILE as JSON Lines, keeping history across restarts
+    #[arg(long, value_name = "FILE")]
+    pub history: Option<PathBuf>,
+
+    /// Drop history older than this, e.g. `30d` or `12h`
+    #[arg(long, value_name = "AGE", requires = "history", value_parser = humantime::parse_duration)]
+    pub retention: Option<Duration>,
+
+    /// Print uptime and latency per URL over this window of history (e.g. `24h`) and exit
+    #[arg(long, value_name = "WINDOW", requires = "history", value_parser = humantime::parse_duration)]
+    pub report: Option<Duration>,
+
+    /// Serve Prometheus metrics at `http://ADDR/metrics`, e.g. `127.0.0.1:9898`
+    #[arg(long, value_name = "ADDR")]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Reload the target list whenever FILE changes; SIGHUP reloads it regardless
+    #[arg(long, requires = "interval")]
+    pub watch: bool,
+
+    /// Serve an HTML status page at `http://ADDR/` and its JSON API at `/api/status`
+    #[arg(long, value_name = "ADDR")]
+    pub status_addr: Option<SocketAddr>,
+
+    /// Show a live dashboard instead of printing results; `q` quits
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    pub tui: bool,
+}
+
+/// The engines that can run checks.
+#[cfg(feature = "async")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Engine {
+    /// A pool of --workers threads; supports monitoring mode
+    Threads,
+    /// Tasks on a tokio runtime, for thousands of URLs; checks each URL once
+    Async,
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!("expected a positive number of checks per second, got `{}`", s)),
+    }
+}
+
+fn parse_json(s: &str) -> Result<serde_json::Value, String> {
+    serde_json::from_str(s).map_err(|err| format!("invalid JSON: {}", err))
+}
+
+impl Cli {
+    /// Validates the arguments that depend on each other and builds the `Config`.
+    pub fn config(&self) -> Result<Config, clap::Error> {
+        if self.retry_delay > self.max_delay {
+            return Err(Cli::command().error(
+                ErrorKind::ArgumentConflict,
+                format!("--retry-delay ({} ms) must not exceed --max-delay ({} ms)", self.retry_delay, self.max_delay),
+            ));
+        }
+
+        #[cfg(feature = "async")]
+        if self.engine == Engine::Async && self.interval.is_some() {
+            return Err(Cli::command().error(
+                ErrorKind::ArgumentConflict,
+                "--engine async checks each URL once and cannot be combined with --interval",
+            ));
+        }
+
+        #[cfg(feature = "async")]
+        if self.engine == Engine::Async && (self.max_per_host.is_some() || self.host_rate.is_some() || self.crawl_delay.is_some()) {
+            return Err(Cli::command().error(
+                ErrorKind::ArgumentConflict,
+                "--engine async limits hosts with --per-host only; --max-per-host, --host-rate and --crawl-delay need --engine threads",
+            ));
+        }
+
+        if self.watch && self.input.as_os_str() == "-" {
+            return Err(Cli::command().error(ErrorKind::ArgumentConflict, "--watch needs a target file, not stdin"));
+        }
+
+        #[cfg(all(feature = "tui", feature = "async"))]
+        if self.tui && self.engine == Engine::Async {
+            return Err(Cli::command().error(
+                ErrorKind::ArgumentConflict,
+                "--tui needs --engine threads",
+            ));
+        }
+
+        let retry = RetryPolicy {
+            backoff: self.backoff,
+            base_delay: Duration::from_millis(self.retry_delay),
+            max_delay: Duration::from_millis(self.max_delay),
+            ..RetryPolicy::new(self.retries)
+        };
+
+        let mut criteria = SuccessCriteria {
+            body_contains: self.expect_body.clone(),
+            body_regex: self.expect_regex.clone(),
+            max_body_bytes: self.max_body_bytes,
+            headers: self.expect_header.clone(),
+            max_latency: self.max_latency.map(Duration::from_millis),
+            ..SuccessCriteria::default()
+        };
+        if !self.expect_status.is_empty() {
+            criteria.expected_status = self.expect_status.clone();
+        }
+
+        let body = match (&self.data, &self.json) {
+            (Some(data), _) => Some(Body::Text(data.clone())),
+            (None, Some(json)) => Some(Body::Json(json.clone())),
+            (None, None) => None,
+        };
+        if !self.method.has_response_body() && (body.is_some() || criteria.needs_body() || self.hash_body) {
+            return Err(Cli::command().error(
+                ErrorKind::ArgumentConflict,
+                format!("{} requests cannot send a body or check the response body", self.method),
+            ));
+        }
+
+        let mut headers = self.header.clone();
+        if let Some(agent) = &self.user_agent {
+            headers.retain(|(name, _)| !name.eq_ignore_ascii_case("User-Agent"));
+            headers.push(("User-Agent".to_string(), agent.clone()));
+        }
+
+        Ok(Config {
+            method: self.method,
+            headers,
+            body,
+            interval: self.interval(),
+            shutdown_mode: self.on_shutdown,
+            shutdown_timeout: self.shutdown_timeout.map(Duration::from_secs),
+            tls: self.inspect_tls.then_some(TlsPolicy { expiry_warning_days: self.cert_warning_days }),
+            redirects: RedirectPolicy {
+                max_redirects: if self.no_follow_redirects { 0 } else { self.max_redirects },
+                deny_downgrade: self.deny_https_downgrade,
+            },
+            network: NetworkOptions {
+                proxy: self.proxy.clone(),
+                resolve: self.resolve.clone(),
+                local_address: self.local_address,
+            },
+            content: self.hash_body.then(|| ContentPolicy {
+                strip_whitespace: self.hash_strip_whitespace,
+                ignore: self.hash_ignore.clone(),
+            }),
+            host_limits: HostLimits {
+                rate: self.host_rate,
+                burst: self.host_burst,
+                max_in_flight: self.max_per_host.map(|max| max as usize),
+                crawl_delay: self.crawl_delay.map(Duration::from_millis),
+            },
+            ..Config::new(
+                self.workers as usize,
+                Duration::from_secs(self.timeout),
+                retry,
+                criteria,
+            )
+        })
+    }
+
+    /// Builds the alerter from the alerting options; it has no notifiers when none were given.
+    pub fn alerter(&self) -> Alerter {
+        let policy = AlertPolicy {
+            failure_threshold: self.alert_threshold,
+            flap_limit: self.flap_limit,
+            flap_window: Duration::from_secs(self.flap_window),
+        };
+
+        let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+        for url in &self.webhook {
+            notifiers.push(Box::new(WebhookNotifier { url: url.clone(), timeout: Duration::from_secs(self.timeout) }));
+        }
+        if let Some(command) = &self.alert_command {
+            notifiers.push(Box::new(CommandNotifier { command: command.clone() }));
+        }
+        if let Some(path) = &self.alert_log {
+            notifiers.push(Box::new(LogNotifier { path: path.clone() }));
+        }
+
+        Alerter::new(policy, notifiers)
+    }
+
+    pub fn history(&self) -> Option<HistoryStore> {
+        self.history.as_ref().map(HistoryStore::new)
+    }
+
+    /// The check interval when running in monitoring mode.
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval.map(Duration::from_secs)
+    }
+
+    /// Reads the target list from the input file, or from stdin when the path is `-`.
+    pub fn read_input(&self) -> io::Result<String> {
+        if self.input.as_os_str() == "-" {
+            return io::read_to_string(io::stdin());
+        }
+
+        fs::read_to_string(&self.input).map_err(|e| {
+            io::Error::new(e.kind(), format!("Failed to open {}: {}", self.input.display(), e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let cli = Cli::try_parse_from(["checker"]).unwrap();
+        assert_eq!(cli.input, PathBuf::from("urls.txt"));
+        assert_eq!(cli.format, OutputFormat::Text);
+        assert_eq!(cli.interval(), None);
+
+        let config = cli.config().unwrap();
+        assert_eq!(config.worker_threads, DEFAULT_WORKERS as usize);
+        assert_eq!(config.timeout, Duration::from_secs(DEFAULT_TIMEOUT));
+        assert_eq!(config.retry, RetryPolicy::new(MAX_RETRIES));
+        assert_eq!(config.shutdown_mode, ShutdownMode::Drain);
+        assert_eq!(config.shutdown_timeout, None);
+
+        let cli = Cli::try_parse_from(["checker", "--on-shutdown", "abort", "--shutdown-timeout", "10"]).unwrap();
+        let config = cli.config().unwrap();
+        assert_eq!(config.shutdown_mode, ShutdownMode::Abort);
+        assert_eq!(config.shutdown_timeout, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_custom_values() {
+        let cli = Cli::try_parse_from([
+            "checker", "--workers", "4", "-t", "2", "--retries", "1", "--format", "text",
+            "--interval", "30", "--backoff", "fixed", "--retry-delay", "50", "--max-delay", "50", "-",
+        ])
+        .unwrap();
+        assert_eq!(cli.input, PathBuf::from("-"));
+        assert_eq!(cli.interval(), Some(Duration::from_secs(30)));
+
+        let config = cli.config().unwrap();
+        assert_eq!(config.interval, Some(Duration::from_secs(30)));
+        assert_eq!(config.worker_threads, 4);
+        assert_eq!(config.timeout, Duration::from_secs(2));
+        assert_eq!(config.retry.max_retries, 1);
+        assert_eq!(config.retry.backoff, Backoff::Fixed);
+        assert_eq!(config.retry.delay(3), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_rejects_invalid_values() {
+        assert!(Cli::try_parse_from(["checker", "--workers", "0"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--timeout", "0"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--retries", "-1"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--backoff", "linear"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--timeout", "soon"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--format", "xml"]).is_err());
+        assert_eq!(Cli::try_parse_from(["checker", "-f", "json"]).unwrap().format, OutputFormat::Json);
+        assert_eq!(Cli::try_parse_from(["checker", "-f", "csv"]).unwrap().format, OutputFormat::Csv);
+        assert!(Cli::try_parse_from(["checker", "--interval", "0"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--metrics-addr", "localhost"]).is_err());
+        assert_eq!(
+            Cli::try_parse_from(["checker", "--metrics-addr", "127.0.0.1:9898"]).unwrap().metrics_addr,
+            Some("127.0.0.1:9898".parse().unwrap())
+        );
+        assert!(Cli::try_parse_from(["checker", "--watch", "--interval", "5"]).unwrap().config().is_ok());
+        // A single pass over the targets has nothing to reload
+        assert!(Cli::try_parse_from(["checker", "--watch"]).is_err());
+        let cli = Cli::try_parse_from(["checker", "--watch", "--interval", "5", "-"]).unwrap();
+        assert_eq!(cli.config().err().map(|err| err.kind()), Some(ErrorKind::ArgumentConflict));
+        assert_eq!(
+            Cli::try_parse_from(["checker", "--status-addr", "0.0.0.0:8080"]).unwrap().status_addr,
+            Some("0.0.0.0:8080".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_success_criteria() {
+        let cli = Cli::try_parse_from([
+            "checker", "--expect-status", "200,3xx", "--expect-body", "Welcome", "--expect-regex", "v[0-9]+",
+            "--max-body-bytes", "1024", "--expect-header", "X-Env: prod", "--max-latency", "500",
+        ])
+        .unwrap();
+
+        let criteria = cli.config().unwrap().criteria;
+        assert_eq!(criteria.expected_status, vec![StatusRange { start: 200, end: 200 }, StatusRange { start: 300, end: 399 }]);
+        assert_eq!(criteria.body_contains.as_deref(), Some("Welcome"));
+        assert_eq!(criteria.body_regex.unwrap().as_str(), "v[0-9]+");
+        assert_eq!(criteria.max_body_bytes, Some(1024));
+        assert_eq!(criteria.headers, vec![HeaderRule { name: "X-Env".to_string(), value: "prod".to_string() }]);
+        assert_eq!(criteria.max_latency, Some(Duration::from_millis(500)));
+
+        let defaults = Cli::try_parse_from(["checker"]).unwrap().config().unwrap().criteria;
+        assert_eq!(defaults.expected_status, SuccessCriteria::default().expected_status);
+
+        assert!(Cli::try_parse_from(["checker", "--expect-status", "20x"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--expect-regex", "("]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--expect-header", "nocolon"]).is_err());
+    }
+
+    #[test]
+    fn test_request_options() {
+        let cli = Cli::try_parse_from([
+            "checker", "-X", "post", "-H", "Authorization: Bearer t", "--json", r#"{"ping": 1}"#, "-A", "probe/1.0",
+        ])
+        .unwrap();
+
+        let config = cli.config().unwrap();
+        assert_eq!(config.method, Method::Post);
+        assert_eq!(
+            config.headers,
+            vec![
+                ("Authorization".to_string(), "Bearer t".to_string()),
+                ("User-Agent".to_string(), "probe/1.0".to_string()),
+            ]
+        );
+        assert_eq!(config.body, Some(Body::Json(serde_json::json!({ "ping": 1 }))));
+
+        let defaults = Cli::try_parse_from(["checker"]).unwrap().config().unwrap();
+        assert_eq!(defaults.method, Method::Get);
+        assert!(defaults.headers.is_empty());
+        assert!(defaults.body.is_none());
+
+        assert!(Cli::try_parse_from(["checker", "-X", "FETCH"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--json", "{nope"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "-d", "a", "--json", "1"]).is_err());
+        let injected = Cli::try_parse_from(["checker", "-H", "X-Trace: a\r\nHost: evil"]).unwrap_err();
+        assert_eq!(injected.kind(), clap::error::ErrorKind::ValueValidation);
+
+        let head = Cli::try_parse_from(["checker", "-X", "HEAD", "--expect-body", "ok"]).unwrap();
+        assert!(head.config().is_err());
+    }
+
+    #[test]
+    fn test_alert_options() {
+        assert!(!Cli::try_parse_from(["checker"]).unwrap().alerter().is_enabled());
+
+        let cli = Cli::try_parse_from([
+            "checker", "--webhook", "http://localhost:9000/hook", "--alert-log", "alerts.log",
+            "--alert-threshold", "3", "--flap-limit", "4", "--flap-window", "120",
+        ])
+        .unwrap();
+        assert_eq!(cli.alert_threshold, 3);
+        assert_eq!(cli.flap_limit, Some(4));
+        assert_eq!(cli.flap_window, 120);
+        assert!(cli.alerter().is_enabled());
+
+        assert!(Cli::try_parse_from(["checker", "--alert-threshold", "0"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--flap-window", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_history_options() {
+        let cli = Cli::try_parse_from(["checker", "--history", "history.jsonl", "--retention", "30d", "--report", "24h"]).unwrap();
+        assert_eq!(cli.history().unwrap().path(), std::path::Path::new("history.jsonl"));
+        assert_eq!(cli.retention, Some(Duration::from_secs(30 * 24 * 60 * 60)));
+        assert_eq!(cli.report, Some(Duration::from_secs(24 * 60 * 60)));
+
+        assert!(Cli::try_parse_from(["checker"]).unwrap().history().is_none());
+        assert!(Cli::try_parse_from(["checker", "--report", "24h"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--history", "h.jsonl", "--retention", "soon"]).is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_engine_options() {
+        let cli = Cli::try_parse_from(["checker", "--engine", "async", "--concurrency", "2000", "--per-host", "4"]).unwrap();
+        assert_eq!(cli.engine, Engine::Async);
+        assert_eq!((cli.concurrency, cli.per_host), (2000, 4));
+        assert_eq!(Cli::try_parse_from(["checker"]).unwrap().engine, Engine::Threads);
+        assert!(Cli::try_parse_from(["checker", "--per-host", "0"]).is_err());
+
+        let cli = Cli::try_parse_from(["checker", "--engine", "async", "--interval", "30"]).unwrap();
+        assert_eq!(cli.config().err().map(|err| err.kind()), Some(ErrorKind::ArgumentConflict));
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_tui_option() {
+        assert!(Cli::try_parse_from(["checker", "--tui"]).unwrap().tui);
+        assert!(!Cli::try_parse_from(["checker"]).unwrap().tui);
+
+        #[cfg(feature = "async")]
+        {
+            let cli = Cli::try_parse_from(["checker", "--tui", "--engine", "async"]).unwrap();
+            assert_eq!(cli.config().err().map(|err| err.kind()), Some(ErrorKind::ArgumentConflict));
+        }
+    }
+
+    #[test]
+    fn test_host_limit_options() {
+        let cli = Cli::try_parse_from([
+            "checker", "--max-per-host", "2", "--host-rate", "0.5", "--host-burst", "3", "--crawl-delay", "250",
+        ])
+        .unwrap();
+        let limits = cli.config().unwrap().host_limits;
+        assert_eq!(limits.max_in_flight, Some(2));
+        assert_eq!(limits.rate, Some(0.5));
+        assert_eq!(limits.burst, 3);
+        assert_eq!(limits.crawl_delay, Some(Duration::from_millis(250)));
+
+        assert!(Cli::try_parse_from(["checker"]).unwrap().config().unwrap().host_limits.is_unlimited());
+        assert!(Cli::try_parse_from(["checker", "--host-rate", "0"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--host-burst", "5"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--max-per-host", "0"]).is_err());
+
+        #[cfg(feature = "async")]
+        for flag in [&["--max-per-host", "2"][..], &["--host-rate", "1"], &["--crawl-delay", "250"]] {
+            let cli = Cli::try_parse_from(["checker", "--engine", "async"].iter().chain(flag)).unwrap();
+            assert_eq!(cli.config().err().map(|err| err.kind()), Some(ErrorKind::ArgumentConflict));
+        }
+    }
+
+    #[test]
+    fn test_tls_options() {
+        let cli = Cli::try_parse_from(["checker", "--inspect-tls", "--cert-warning-days", "30"]).unwrap();
+        assert_eq!(cli.config().unwrap().tls, Some(TlsPolicy { expiry_warning_days: 30 }));
+
+        let cli = Cli::try_parse_from(["checker", "--inspect-tls"]).unwrap();
+        assert_eq!(cli.config().unwrap().tls, Some(TlsPolicy::default()));
+        assert_eq!(Cli::try_parse_from(["checker"]).unwrap().config().unwrap().tls, None);
+        assert!(Cli::try_parse_from(["checker", "--cert-warning-days", "30"]).is_err());
+    }
+
+    #[test]
+    fn test_redirect_options() {
+        let redirects = |args: &[&str]| Cli::try_parse_from(["checker"].iter().chain(args)).unwrap().config().unwrap().redirects;
+        assert_eq!(redirects(&[]), RedirectPolicy::default());
+        assert_eq!(redirects(&["--max-redirects", "2"]), RedirectPolicy { max_redirects: 2, deny_downgrade: false });
+        assert_eq!(redirects(&["--no-follow-redirects"]).max_redirects, 0);
+        assert!(redirects(&["--deny-https-downgrade"]).deny_downgrade);
+        assert!(Cli::try_parse_from(["checker", "--no-follow-redirects", "--max-redirects", "2"]).is_err());
+    }
+
+    #[test]
+    fn test_content_options() {
+        let content = |args: &[&str]| Cli::try_parse_from(["checker"].iter().chain(args)).unwrap().config().unwrap().content;
+        assert_eq!(content(&[]), None);
+        assert_eq!(content(&["--hash-body"]), Some(ContentPolicy::default()));
+
+        let policy = content(&["--hash-body", "--hash-strip-whitespace", "--hash-ignore", "[0-9]{2}:[0-9]{2}"]).unwrap();
+        assert!(policy.strip_whitespace);
+        assert_eq!(policy.ignore[0].as_str(), "[0-9]{2}:[0-9]{2}");
+
+        assert!(Cli::try_parse_from(["checker", "--hash-ignore", "x"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--hash-body", "--hash-ignore", "("]).is_err());
+        let cli = Cli::try_parse_from(["checker", "--hash-body", "--method", "HEAD"]).unwrap();
+        assert!(cli.config().is_err_and(|err| err.kind() == ErrorKind::ArgumentConflict));
+    }
+
+    #[test]
+    fn test_network_options() {
+        let network = |args: &[&str]| Cli::try_parse_from(["checker"].iter().chain(args)).unwrap().config().unwrap().network;
+        assert!(network(&[]).is_direct());
+
+        let options = network(&[
+            "--proxy",
+            "socks5://127.0.0.1:9050",
+            "--resolve",
+            "example.com:443:10.0.0.7",
+            "--resolve",
+            "example.com:80:10.0.0.8",
+            "--local-address",
+            "10.0.0.2",
+        ]);
+        assert_eq!(options.proxy.unwrap().to_string(), "socks5://127.0.0.1:9050");
+        assert_eq!(options.resolve.len(), 2);
+        assert_eq!(options.local_address, Some(IpAddr::from([10, 0, 0, 2])));
+
+        assert!(Cli::try_parse_from(["checker", "--proxy", "ftp://proxy"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--resolve", "example.com:10.0.0.7"]).is_err());
+        assert!(Cli::try_parse_from(["checker", "--local-address", "eth0"]).is_err());
+    }
+
+    #[test]
+    fn test_retry_delay_above_max_delay() {
+        let cli = Cli::try_parse_from(["checker", "--retry-delay", "2000", "--max-delay", "1000"]).unwrap();
+        match cli.config() {
+            Err(err) => assert_eq!(err.kind(), ErrorKind::ArgumentConflict),
+            Ok(_) => panic!("expected a usage error"),
+        }
+    }
+
+    #[test]
+    fn test_missing_input_file() {
+        let cli = Cli::try_parse_from(["checker", "does-not-exist.txt"]).unwrap();
+        let err = cli.read_input().unwrap_err();
+        assert!(err.to_string().contains("does-not-exist.txt"));
+    }
+}
@@ -0,0 +1,182 @@
+//! Noticing when a page's content changes, such as after a defacement or a bad deploy.
+//!
+//! Targets with a `ContentPolicy` have their response body normalized and hashed on
+//! every check; `ContentTracker` remembers the last hash of each URL and reports a
+//! `ContentChange` when a new one differs.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use regex::Regex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::output::StatusRecord;
+use crate::{Url, WebsiteStatus};
+
+/// How a response body is normalized before it is hashed.
+#[derive(Clone, Debug, Default)]
+pub struct ContentPolicy {
+    /// Leave out all whitespace, so reindenting a page is not a change.
+    pub strip_whitespace: bool,
+    /// Regions to leave out, such as timestamps or CSRF tokens. They are removed
+    /// before whitespace is stripped.
+    pub ignore: Vec<Regex>,
+}
+
+impl PartialEq for ContentPolicy {
+    /// Regexes compare by their pattern.
+    fn eq(&self, other: &Self) -> bool {
+        self.strip_whitespace == other.strip_whitespace
+            && self.ignore.iter().map(Regex::as_str).eq(other.ignore.iter().map(Regex::as_str))
+    }
+}
+
+impl ContentPolicy {
+    /// The body as it is hashed. Invalid UTF-8 is replaced, as it is when matching.
+    pub fn normalize<'a>(&self, body: &'a [u8]) -> Cow<'a, str> {
+        let mut text = String::from_utf8_lossy(body);
+        for regex in &self.ignore {
+            if let Cow::Owned(replaced) = regex.replace_all(&text, "") {
+                text = Cow::Owned(replaced);
+            }
+        }
+        if self.strip_whitespace {
+            text = Cow::Owned(text.chars().filter(|c| !c.is_whitespace()).collect());
+        }
+        text
+    }
+
+    /// The SHA-256 of the normalized body, in lowercase hex.
+    pub fn hash(&self, body: &[u8]) -> String {
+        let digest = Sha256::digest(self.normalize(body).as_bytes());
+        digest.iter().fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+    }
+}
+
+/// A URL whose content hash differs from the one last seen.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename = "content_change")]
+pub struct ContentChange {
+    pub name: String,
+    pub url: Url,
+    pub previous_hash: String,
+    pub hash: String,
+    /// RFC 3339 timestamp of the check that saw the new content.
+    pub timestamp: String,
+}
+
+/// The last content hash of every URL.
+///
+/// Only passing checks count, so an outage page is reported as the site being down
+/// rather than as a content change. The first hash of a URL is its baseline.
+#[derive(Default)]
+pub struct ContentTracker {
+    hashes: HashMap<Url, String>,
+}
+
+impl ContentTracker {
+    pub fn new() -> Self {
+        ContentTracker::default()
+    }
+
+    /// Takes the hash of a result read back from history as the URL's baseline, so
+    /// a change made while the checker was stopped is still reported.
+    pub fn record_history(&mut self, record: &StatusRecord) {
+        if let (true, Some(hash)) = (record.ok, &record.content_hash) {
+            self.hashes.insert(record.url.clone(), hash.clone());
+        }
+    }
+
+    /// Records a check result, returning the change to report, if any.
+    pub fn observe(&mut self, status: &WebsiteStatus) -> Option<ContentChange> {
+        let hash = status.content_hash.as_ref().filter(|_| status.verdict.is_pass())?;
+        let previous = self.hashes.insert(status.url.clone(), hash.clone())?;
+        (previous != *hash).then(|| ContentChange {
+            name: status.name.clone(),
+            url: status.url.clone(),
+            previous_hash: previous,
+            hash: hash.clone(),
+            timestamp: humantime::format_rfc3339_millis(status.timestamp).to_string(),
+        })
+    }
+
+    /// The last hash seen for `url`.
+    pub fn hash(&self, url: &str) -> Option<&str> {
+        self.hashes.get(url).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::Attempt;
+    use std::time::Duration;
+
+    fn status(body: &str, policy: &ContentPolicy, up: bool) -> WebsiteStatus {
+        WebsiteStatus {
+            name: "Home".to_string(),
+            attempts: vec![Attempt { latency: Duration::from_millis(10), outcome: Ok(Some(200)) }],
+            content_hash: Some(policy.hash(body.as_bytes())),
+            ..testing::status("https://example.com", Ok(if up { 200 } else { 503 }))
+        }
+    }
+
+    #[test]
+    fn test_hash_and_normalize() {
+        let plain = ContentPolicy::default();
+        assert_eq!(plain.hash(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_ne!(plain.hash(b"<p>Hello</p>"), plain.hash(b"<p>\n  Hello\n</p>"));
+
+        let policy = ContentPolicy {
+            strip_whitespace: true,
+            ignore: vec![Regex::new(r"Generated at [0-9:]+").unwrap(), Regex::new(r#"name="csrf" value="\w+""#).unwrap()],
+        };
+        assert_eq!(policy.normalize(b"<p>\n  Hello world\n</p> Generated at 12:00:01"), "<p>Helloworld</p>");
+        assert_eq!(
+            policy.hash(br#"<input name="csrf" value="abc123"> Generated at 09:15"#),
+            policy.hash(br#"<input  name="csrf" value="zz9"> Generated at 23:59"#)
+        );
+        assert_ne!(policy.hash(b"<p>Hello</p>"), policy.hash(b"<p>Hacked</p>"));
+        assert_eq!(policy, policy.clone());
+        assert_ne!(policy, ContentPolicy { strip_whitespace: true, ..ContentPolicy::default() });
+    }
+
+    #[test]
+    fn test_tracker_reports_changes() {
+        let policy = ContentPolicy::default();
+        let mut tracker = ContentTracker::new();
+        assert_eq!(tracker.observe(&status("v1", &policy, true)), None);
+        assert_eq!(tracker.observe(&status("v1", &policy, true)), None);
+        // An error page while the site is down is not a content change
+        assert_eq!(tracker.observe(&status("Service Unavailable", &policy, false)), None);
+
+        let change = tracker.observe(&status("defaced", &policy, true)).unwrap();
+        assert_eq!(change.previous_hash, policy.hash(b"v1"));
+        assert_eq!(change.hash, policy.hash(b"defaced"));
+        assert_eq!(tracker.hash("https://example.com"), Some(change.hash.as_str()));
+
+        let json = serde_json::to_value(&change).unwrap();
+        assert_eq!(json["event"], "content_change");
+        assert_eq!(json["url"], "https://example.com");
+    }
+
+    #[test]
+    fn test_tracker_resumes_from_history() {
+        let policy = ContentPolicy::default();
+        let mut tracker = ContentTracker::new();
+        let mut record = StatusRecord::from(&status("v1", &policy, true));
+        tracker.record_history(&record);
+        record.content_hash = Some(policy.hash(b"outage"));
+        record.ok = false;
+        tracker.record_history(&record);
+
+        assert_eq!(tracker.observe(&status("v1", &policy, true)), None);
+        assert!(tracker.observe(&status("v2", &policy, true)).is_some());
+    }
+}
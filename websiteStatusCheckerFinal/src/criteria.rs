@@ -0,0 +1,351 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use regex::Regex;
+
+/// Bodies are never read past this size, even without an explicit limit.
+pub const DEFAULT_BODY_LIMIT: u64 = 10 * 1024 * 1024;
+
+/// An inclusive range of HTTP status codes, written as `200`, `200-299` or `2xx`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StatusRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl StatusRange {
+    pub fn contains(&self, code: u16) -> bool {
+        (self.start..=self.end).contains(&code)
+    }
+}
+
+impl FromStr for StatusRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let parse = |code: &str| {
+            code.trim()
+                .parse::<u16>()
+                .ok()
+                .filter(|code| (100..=599).contains(code))
+                .ok_or_else(|| format!("invalid status code `{}`", code.trim()))
+        };
+
+        let range = if let Some(class) = s.strip_suffix("xx").or_else(|| s.strip_suffix("XX")) {
+            let start = parse(&format!("{}00", class))?;
+            StatusRange { start, end: start + 99 }
+        } else if let Some((start, end)) = s.split_once('-') {
+            StatusRange { start: parse(start)?, end: parse(end)? }
+        } else {
+            let code = parse(s)?;
+            StatusRange { start: code, end: code }
+        };
+
+        if range.start > range.end {
+            return Err(format!("empty status range `{}`", s));
+        }
+
+        Ok(range)
+    }
+}
+
+impl fmt::Display for StatusRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+/// A header that must be present with an exact value, written as `Name: value`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeaderRule {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for HeaderRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = crate::request::parse_header(s)?;
+        Ok(HeaderRule { name, value })
+    }
+}
+
+/// Rules a response has to satisfy for a check to pass.
+#[derive(Clone, Debug)]
+pub struct SuccessCriteria {
+    pub expected_status: Vec<StatusRange>,
+    pub body_contains: Option<String>,
+    pub body_regex: Option<Regex>,
+    pub max_body_bytes: Option<u64>,
+    pub headers: Vec<HeaderRule>,
+    pub max_latency: Option<Duration>,
+}
+
+impl Default for SuccessCriteria {
+    /// Accepts any 2xx or 3xx response, which is what the checker always treated as up.
+    fn default() -> Self {
+        SuccessCriteria {
+            expected_status: vec![StatusRange { start: 200, end: 399 }],
+            body_contains: None,
+            body_regex: None,
+            max_body_bytes: None,
+            headers: Vec::new(),
+            max_latency: None,
+        }
+    }
+}
+
+impl PartialEq for SuccessCriteria {
+    /// Regexes compare by their pattern.
+    fn eq(&self, other: &Self) -> bool {
+        self.expected_status == other.expected_status
+            && self.body_contains == other.body_contains
+            && self.body_regex.as_ref().map(Regex::as_str) == other.body_regex.as_ref().map(Regex::as_str)
+            && self.max_body_bytes == other.max_body_bytes
+            && self.headers == other.headers
+            && self.max_latency == other.max_latency
+    }
+}
+
+/// What was received for a single request.
+pub struct Observation<'a> {
+    pub status: u16,
+    pub latency: Duration,
+    pub headers: &'a [(String, String)],
+    /// The body, when the criteria needed it. At most `body_limit() + 1` bytes are read.
+    pub body: Option<&'a [u8]>,
+}
+
+impl SuccessCriteria {
+    /// Whether the body has to be downloaded to evaluate these criteria.
+    pub fn needs_body(&self) -> bool {
+        self.body_contains.is_some() || self.body_regex.is_some() || self.max_body_bytes.is_some()
+    }
+
+    pub fn body_limit(&self) -> u64 {
+        self.max_body_bytes.unwrap_or(DEFAULT_BODY_LIMIT)
+    }
+
+    pub fn evaluate(&self, observed: &Observation) -> Verdict {
+        if !self.expected_status.iter().any(|range| range.contains(observed.status)) {
+            return Verdict::Fail(FailureReason::UnexpectedStatus(observed.status));
+        }
+
+        if let Some(limit) = self.max_latency {
+            if observed.latency > limit {
+                return Verdict::Fail(FailureReason::TooSlow { latency: observed.latency, limit });
+            }
+        }
+
+        for rule in &self.headers {
+            let actual = observed
+                .headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(&rule.name))
+                .map(|(_, value)| value.clone());
+            if actual.as_deref() != Some(rule.value.as_str()) {
+                return Verdict::Fail(FailureReason::HeaderMismatch { rule: rule.clone(), actual });
+            }
+        }
+
+        let body = observed.body.unwrap_or_default();
+
+        if let Some(limit) = self.max_body_bytes {
+            if body.len() as u64 > limit {
+                return Verdict::Fail(FailureReason::BodyTooLarge { limit });
+            }
+        }
+
+        let text = String::from_utf8_lossy(body);
+
+        if let Some(needle) = &self.body_contains {
+            if !text.contains(needle.as_str()) {
+                return Verdict::Fail(FailureReason::BodyMissing(needle.clone()));
+            }
+        }
+
+        if let Some(regex) = &self.body_regex {
+            if !regex.is_match(&text) {
+                return Verdict::Fail(FailureReason::BodyMismatch(regex.as_str().to_string()));
+            }
+        }
+
+        Verdict::Pass
+    }
+}
+
+/// Why a check did not pass.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FailureReason {
+    /// No usable response was received.
+    Request(String),
+    UnexpectedStatus(u16),
+    TooSlow { latency: Duration, limit: Duration },
+    HeaderMismatch { rule: HeaderRule, actual: Option<String> },
+    BodyTooLarge { limit: u64 },
+    BodyMissing(String),
+    BodyMismatch(String),
+    /// The redirects broke the target's redirect policy.
+    Redirect(String),
+    /// A probe reached the service, which did not answer as expected.
+    Probe(String),
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FailureReason::Request(err) => write!(f, "{}", err),
+            FailureReason::UnexpectedStatus(code) => write!(f, "unexpected status code {}", code),
+            FailureReason::TooSlow { latency, limit } => {
+                write!(f, "response took {} ms, limit is {} ms", latency.as_millis(), limit.as_millis())
+            }
+            FailureReason::HeaderMismatch { rule, actual: Some(actual) } => {
+                write!(f, "header {} is `{}`, expected `{}`", rule.name, actual, rule.value)
+            }
+            FailureReason::HeaderMismatch { rule, actual: None } => write!(f, "header {} is missing", rule.name),
+            FailureReason::BodyTooLarge { limit } => write!(f, "body is larger than {} bytes", limit),
+            FailureReason::BodyMissing(needle) => write!(f, "body does not contain `{}`", needle),
+            FailureReason::BodyMismatch(pattern) => write!(f, "body does not match /{}/", pattern),
+            FailureReason::Redirect(violation) => write!(f, "{}", violation),
+            FailureReason::Probe(mismatch) => write!(f, "{}", mismatch),
+        }
+    }
+}
+
+/// The pass/fail result of a check.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    Pass,
+    Fail(FailureReason),
+}
+
+impl Verdict {
+    pub fn is_pass(&self) -> bool {
+        matches!(self, Verdict::Pass)
+    }
+
+    pub fn failure(&self) -> Option<&FailureReason> {
+        match self {
+            Verdict::Pass => None,
+            Verdict::Fail(reason) => Some(reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observe<'a>(status: u16, headers: &'a [(String, String)], body: &'a str) -> Observation<'a> {
+        Observation {
+            status,
+            latency: Duration::from_millis(100),
+            headers,
+            body: Some(body.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_parse_status_range() {
+        assert_eq!("200".parse(), Ok(StatusRange { start: 200, end: 200 }));
+        assert_eq!("200-204".parse(), Ok(StatusRange { start: 200, end: 204 }));
+        assert_eq!("3xx".parse(), Ok(StatusRange { start: 300, end: 399 }));
+        assert!("abc".parse::<StatusRange>().is_err());
+        assert!("700".parse::<StatusRange>().is_err());
+        assert!("299-200".parse::<StatusRange>().is_err());
+    }
+
+    #[test]
+    fn test_parse_header_rule() {
+        let rule: HeaderRule = "Content-Type: text/html; charset=utf-8".parse().unwrap();
+        assert_eq!(rule.name, "Content-Type");
+        assert_eq!(rule.value, "text/html; charset=utf-8");
+        assert!("no colon".parse::<HeaderRule>().is_err());
+        assert!(": value".parse::<HeaderRule>().is_err());
+    }
+
+    #[test]
+    fn test_default_accepts_2xx_and_3xx() {
+        let criteria = SuccessCriteria::default();
+        assert!(criteria.evaluate(&observe(204, &[], "")).is_pass());
+        assert!(criteria.evaluate(&observe(301, &[], "")).is_pass());
+        assert_eq!(
+            criteria.evaluate(&observe(404, &[], "")),
+            Verdict::Fail(FailureReason::UnexpectedStatus(404))
+        );
+        assert!(!criteria.needs_body());
+    }
+
+    #[test]
+    fn test_expected_status() {
+        let criteria = SuccessCriteria {
+            expected_status: vec!["404".parse().unwrap()],
+            ..SuccessCriteria::default()
+        };
+        assert!(criteria.evaluate(&observe(404, &[], "")).is_pass());
+        assert!(!criteria.evaluate(&observe(200, &[], "")).is_pass());
+    }
+
+    #[test]
+    fn test_body_assertions() {
+        let criteria = SuccessCriteria {
+            body_contains: Some("Welcome".to_string()),
+            body_regex: Some(Regex::new(r"version \d+").unwrap()),
+            ..SuccessCriteria::default()
+        };
+        assert!(criteria.needs_body());
+        assert!(criteria.evaluate(&observe(200, &[], "Welcome, version 42")).is_pass());
+        assert_eq!(
+            criteria.evaluate(&observe(200, &[], "Database error")),
+            Verdict::Fail(FailureReason::BodyMissing("Welcome".to_string()))
+        );
+        assert_eq!(
+            criteria.evaluate(&observe(200, &[], "Welcome, version unknown")),
+            Verdict::Fail(FailureReason::BodyMismatch(r"version \d+".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_body_size_limit() {
+        let criteria = SuccessCriteria {
+            max_body_bytes: Some(4),
+            ..SuccessCriteria::default()
+        };
+        assert_eq!(criteria.body_limit(), 4);
+        assert!(criteria.evaluate(&observe(200, &[], "tiny")).is_pass());
+        assert_eq!(
+            criteria.evaluate(&observe(200, &[], "bigger")),
+            Verdict::Fail(FailureReason::BodyTooLarge { limit: 4 })
+        );
+    }
+
+    #[test]
+    fn test_header_rule() {
+        let criteria = SuccessCriteria {
+            headers: vec!["x-version: 2".parse().unwrap()],
+            ..SuccessCriteria::default()
+        };
+        let headers = [("X-Version".to_string(), "2".to_string())];
+        assert!(criteria.evaluate(&observe(200, &headers, "")).is_pass());
+
+        let verdict = criteria.evaluate(&observe(200, &[], ""));
+        assert_eq!(verdict.failure().unwrap().to_string(), "header x-version is missing");
+    }
+
+    #[test]
+    fn test_latency_sla() {
+        let criteria = SuccessCriteria {
+            max_latency: Some(Duration::from_millis(50)),
+            ..SuccessCriteria::default()
+        };
+        let verdict = criteria.evaluate(&observe(200, &[], ""));
+        assert_eq!(verdict.failure().unwrap().to_string(), "response took 100 ms, limit is 50 ms");
+    }
+}
@@ -0,0 +1,554 @@
+//! A live terminal view with one row per target, enabled with the `tui` cargo feature.
+//!
+//! `Dashboard` holds what the view shows and is updated with each `WebsiteStatus` as
+//! it arrives; `run` draws it with ratatui while a `Monitor` is checking.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, VecDeque};
+use std::io;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::{Monitor, Target, WebsiteStatus};
+
+/// Latencies kept per row for its sparkline.
+pub const SPARKLINE_WIDTH: usize = 20;
+/// How long the view waits for a key before looking for new results.
+const REFRESH: Duration = Duration::from_millis(100);
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The column rows are ordered by.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    /// Target order, as in the input file
+    Input,
+    Name,
+    /// Failing targets first
+    State,
+    /// Slowest last response first
+    Latency,
+    /// Lowest uptime first
+    Uptime,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            SortKey::Input => SortKey::Name,
+            SortKey::Name => SortKey::State,
+            SortKey::State => SortKey::Latency,
+            SortKey::Latency => SortKey::Uptime,
+            SortKey::Uptime => SortKey::Input,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortKey::Input => "input",
+            SortKey::Name => "name",
+            SortKey::State => "state",
+            SortKey::Latency => "latency",
+            SortKey::Uptime => "uptime",
+        }
+    }
+}
+
+/// Whether a target passed its last check.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum State {
+    Down,
+    Up,
+    /// Not checked yet.
+    Pending,
+}
+
+/// One target's row.
+#[derive(Clone, Debug)]
+pub struct TargetRow {
+    pub name: String,
+    pub url: String,
+    pub tags: Vec<String>,
+    pub state: State,
+    pub last_status: Option<u16>,
+    /// The latest latencies, oldest first.
+    pub latencies: VecDeque<Duration>,
+    pub checks: u64,
+    pub passes: u64,
+    /// Why the most recent failing check failed; kept after the target recovers.
+    pub last_error: Option<String>,
+}
+
+impl TargetRow {
+    fn new(name: &str, url: &str, tags: &[String]) -> Self {
+        TargetRow {
+            name: name.to_string(),
+            url: url.to_string(),
+            tags: tags.to_vec(),
+            state: State::Pending,
+            last_status: None,
+            latencies: VecDeque::with_capacity(SPARKLINE_WIDTH),
+            checks: 0,
+            passes: 0,
+            last_error: None,
+        }
+    }
+
+    pub fn uptime_percent(&self) -> Option<f64> {
+        (self.checks > 0).then(|| self.passes as f64 * 100.0 / self.checks as f64)
+    }
+
+    fn last_latency(&self) -> Option<Duration> {
+        self.latencies.back().copied()
+    }
+}
+
+/// Everything the terminal view shows.
+pub struct Dashboard {
+    rows: Vec<TargetRow>,
+    sort: SortKey,
+    descending: bool,
+    /// Only rows with this tag are shown.
+    tag: Option<String>,
+    table: TableState,
+    finished: bool,
+    /// URLs dropped by `set_targets`, whose checks still in flight are ignored.
+    removed: BTreeSet<String>,
+}
+
+impl Dashboard {
+    /// A dashboard with a pending row for each of `targets`.
+    pub fn new(targets: &[Target]) -> Self {
+        Dashboard {
+            rows: targets.iter().map(|target| TargetRow::new(&target.name, &target.request.url, &target.tags)).collect(),
+            sort: SortKey::Input,
+            descending: false,
+            tag: None,
+            table: TableState::default().with_selected(Some(0)),
+            finished: false,
+            removed: BTreeSet::new(),
+        }
+    }
+
+    /// Follows a reloaded target list: kept targets take their new name and tags,
+    /// new ones get pending rows and removed ones are dropped. Rows follow the new
+    /// list's order.
+    pub fn set_targets(&mut self, targets: &[Target]) {
+        let mut old = std::mem::take(&mut self.rows);
+        for target in targets {
+            if self.rows.iter().any(|row| row.url == target.request.url) {
+                continue;
+            }
+            let mut row = match old.iter().position(|row| row.url == target.request.url) {
+                Some(index) => old.remove(index),
+                None => TargetRow::new(&target.name, &target.request.url, &target.tags),
+            };
+            row.name = target.name.clone();
+            row.tags = target.tags.clone();
+            self.rows.push(row);
+        }
+
+        self.removed.extend(old.into_iter().map(|row| row.url));
+        self.removed.retain(|url| !self.rows.iter().any(|row| row.url == *url));
+        let last = self.visible().len().saturating_sub(1);
+        self.table.select(self.table.selected().map(|selected| selected.min(last)));
+    }
+
+    /// Updates the row for `status`, adding one if the URL is new.
+    pub fn record(&mut self, status: &WebsiteStatus) {
+        if self.removed.contains(&status.url) {
+            return;
+        }
+        let index = match self.rows.iter().position(|row| row.url == status.url) {
+            Some(index) => index,
+            None => {
+                self.rows.push(TargetRow::new(&status.name, &status.url, &[]));
+                self.rows.len() - 1
+            }
+        };
+        let row = &mut self.rows[index];
+
+        row.checks += 1;
+        row.last_status = status.status_code();
+        if row.latencies.len() == SPARKLINE_WIDTH {
+            row.latencies.pop_front();
+        }
+        row.latencies.push_back(status.response_time);
+        match status.verdict.failure() {
+            None => {
+                row.state = State::Up;
+                row.passes += 1;
+            }
+            Some(reason) => {
+                row.state = State::Down;
+                row.last_error = Some(reason.to_string());
+            }
+        }
+    }
+
+    pub fn sort_by(&mut self, key: SortKey) {
+        self.sort = key;
+    }
+
+    /// Shows only rows tagged `tag`, or every row.
+    pub fn filter_tag(&mut self, tag: Option<&str>) {
+        self.tag = tag.map(str::to_string);
+        self.table.select(Some(0));
+    }
+
+    /// Every tag on any row, in order.
+    pub fn tags(&self) -> Vec<&str> {
+        let tags: BTreeSet<&str> = self.rows.iter().flat_map(|row| row.tags.iter().map(String::as_str)).collect();
+        tags.into_iter().collect()
+    }
+
+    /// The rows that pass the tag filter, in display order.
+    pub fn visible(&self) -> Vec<&TargetRow> {
+        let mut rows: Vec<&TargetRow> = self
+            .rows
+            .iter()
+            .filter(|row| self.tag.as_ref().is_none_or(|tag| row.tags.contains(tag)))
+            .collect();
+
+        // Stable sorts, so ties keep input order
+        match self.sort {
+            SortKey::Input => {}
+            SortKey::Name => rows.sort_by(|a, b| a.name.cmp(&b.name)),
+            SortKey::State => rows.sort_by_key(|row| row.state),
+            SortKey::Latency => rows.sort_by_key(|row| Reverse(row.last_latency())),
+            SortKey::Uptime => rows.sort_by(|a, b| {
+                let uptime = |row: &TargetRow| row.uptime_percent().unwrap_or(f64::INFINITY);
+                uptime(a).total_cmp(&uptime(b))
+            }),
+        }
+        if self.descending {
+            rows.reverse();
+        }
+        rows
+    }
+
+    /// Applies a key press, returning `true` when it asks to quit.
+    pub fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return true,
+            KeyCode::Char('s') => self.sort = self.sort.next(),
+            KeyCode::Char('r') => self.descending = !self.descending,
+            KeyCode::Char('t') => {
+                // Cycles through every tag, then back to showing all rows
+                let tags = self.tags();
+                let next = match &self.tag {
+                    None => tags.first().copied(),
+                    Some(current) => tags.iter().skip_while(|tag| *tag != current).nth(1).copied(),
+                };
+                let next = next.map(str::to_string);
+                self.filter_tag(next.as_deref());
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                let last = self.visible().len().saturating_sub(1);
+                let selected = self.table.selected().map_or(0, |selected| (selected + 1).min(last));
+                self.table.select(Some(selected));
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                let selected = self.table.selected().map_or(0, |selected| selected.saturating_sub(1));
+                self.table.select(Some(selected));
+            }
+            _ => {}
+        }
+        false
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+        let rows = self.visible();
+        let up = rows.iter().filter(|row| row.state == State::Up).count();
+        let down = rows.iter().filter(|row| row.state == State::Down).count();
+        let mut summary = format!(
+            " {} targets, {} up, {} down - sorted by {}{}",
+            rows.len(),
+            up,
+            down,
+            self.sort.label(),
+            if self.descending { " (reversed)" } else { "" }
+        );
+        if let Some(tag) = &self.tag {
+            summary.push_str(&format!(" - tag: {}", tag));
+        }
+        if self.finished {
+            summary.push_str(" - all checks done");
+        }
+
+        let table_rows: Vec<Row> = rows.iter().map(|row| table_row(row)).collect();
+        let table = Table::new(
+            table_rows,
+            [
+                Constraint::Length(7),
+                Constraint::Percentage(25),
+                Constraint::Length(6),
+                Constraint::Length(9),
+                Constraint::Length(SPARKLINE_WIDTH as u16),
+                Constraint::Length(7),
+                Constraint::Percentage(30),
+                Constraint::Percentage(15),
+            ],
+        )
+        .header(
+            Row::new(["State", "Name", "Code", "Latency", "Trend", "Uptime", "Last error", "Tags"])
+                .style(Style::new().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::bordered().title(" Website status "))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+
+        frame.render_widget(Paragraph::new(summary), header);
+        frame.render_stateful_widget(table, body, &mut self.table);
+        frame.render_widget(
+            Paragraph::new(Line::from(" q quit  s sort  r reverse  t next tag  ↑/↓ select")).style(Style::new().fg(Color::DarkGray)),
+            footer,
+        );
+    }
+}
+
+fn table_row(row: &TargetRow) -> Row<'static> {
+    let (state, color) = match row.state {
+        State::Up => ("UP", Color::Green),
+        State::Down => ("DOWN", Color::Red),
+        State::Pending => ("...", Color::DarkGray),
+    };
+    let latencies: Vec<Duration> = row.latencies.iter().copied().collect();
+
+    Row::new([
+        Cell::from(state).style(Style::new().fg(color).add_modifier(Modifier::BOLD)),
+        Cell::from(row.name.clone()),
+        Cell::from(row.last_status.map_or("-".to_string(), |code| code.to_string())),
+        Cell::from(row.last_latency().map_or("-".to_string(), |latency| format!("{} ms", latency.as_millis()))),
+        Cell::from(sparkline(&latencies)).style(Style::new().fg(Color::Cyan)),
+        Cell::from(row.uptime_percent().map_or("-".to_string(), |uptime| format!("{:.1}%", uptime))),
+        Cell::from(row.last_error.clone().unwrap_or_default()).style(Style::new().fg(Color::Red)),
+        Cell::from(row.tags.join(",")),
+    ])
+}
+
+/// Draws `latencies` as block characters scaled to the largest of them.
+pub fn sparkline(latencies: &[Duration]) -> String {
+    let max = latencies.iter().max().copied().unwrap_or_default();
+    latencies
+        .iter()
+        .map(|latency| {
+            let level = match max.as_nanos() {
+                0 => 0,
+                max => (latency.as_nanos() * (SPARK_LEVELS.len() as u128 - 1) / max) as usize,
+            };
+            SPARK_LEVELS[level]
+        })
+        .collect()
+}
+
+/// Shows `monitor`'s results as they arrive, until the user quits or the monitor is
+/// shut down from elsewhere, as by SIGTERM. Each result is also passed to `on_result`,
+/// and each target list received on `reloads` replaces the rows. Quitting shuts the
+/// monitor down and waits for it.
+pub fn run(
+    monitor: Monitor,
+    targets: &[Target],
+    reloads: Receiver<Vec<Target>>,
+    mut on_result: impl FnMut(WebsiteStatus),
+) -> io::Result<()> {
+    let mut dashboard = Dashboard::new(targets);
+    let mut terminal = ratatui::init();
+    let result = show(&mut terminal, &mut dashboard, &monitor, &reloads, &mut on_result);
+    ratatui::restore();
+
+    monitor.shutdown_handle().shutdown();
+    for status in monitor.results() {
+        on_result(status);
+    }
+    monitor.join();
+    result
+}
+
+fn show(
+    terminal: &mut DefaultTerminal,
+    dashboard: &mut Dashboard,
+    monitor: &Monitor,
+    reloads: &Receiver<Vec<Target>>,
+    on_result: &mut impl FnMut(WebsiteStatus),
+) -> io::Result<()> {
+    loop {
+        if let Some(targets) = reloads.try_iter().last() {
+            dashboard.set_targets(&targets);
+        }
+        // Once the monitor is done the last results stay up until the user quits
+        while !dashboard.finished {
+            match monitor.try_result() {
+                Ok(status) => {
+                    dashboard.record(&status);
+                    on_result(status);
+                }
+                Err(TryRecvError::Empty) => break,
+                // A shutdown the user did not ask for here came from a signal, which
+                // must end the process rather than wait for a key press
+                Err(TryRecvError::Disconnected) if monitor.shutdown_handle().is_shutdown() => return Ok(()),
+                Err(TryRecvError::Disconnected) => dashboard.finished = true,
+            }
+        }
+        terminal.draw(|frame| dashboard.draw(frame))?;
+
+        if event::poll(REFRESH)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && dashboard.handle_key(key.code, key.modifiers) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, tagged_target};
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use std::time::SystemTime;
+
+    fn status(url: &str, code: u16, latency_ms: u64) -> WebsiteStatus {
+        let latency = Duration::from_millis(latency_ms);
+        WebsiteStatus { response_time: latency, total_time: latency, timestamp: SystemTime::now(), ..testing::status(url, Ok(code)) }
+    }
+
+    fn dashboard() -> Dashboard {
+        Dashboard::new(&[
+            tagged_target("https://b.example.com", &["web"]),
+            tagged_target("https://a.example.com", &["api", "web"]),
+            tagged_target("tcp://db.internal:5432", &["db"]),
+        ])
+    }
+
+    fn names(dashboard: &Dashboard) -> Vec<&str> {
+        dashboard.visible().iter().map(|row| row.url.as_str()).collect()
+    }
+
+    #[test]
+    fn test_record() {
+        let mut dashboard = dashboard();
+        dashboard.record(&status("https://a.example.com", 200, 80));
+        dashboard.record(&status("https://a.example.com", 503, 300));
+        dashboard.record(&status("https://new.example.com", 200, 10));
+
+        let rows = dashboard.visible();
+        assert_eq!(rows[0].state, State::Pending);
+        assert_eq!(rows[1].state, State::Down);
+        assert_eq!(rows[1].last_status, Some(503));
+        assert_eq!(rows[1].uptime_percent(), Some(50.0));
+        assert_eq!(rows[1].last_error.as_deref(), Some("unexpected status code 503"));
+        assert_eq!(rows[3].url, "https://new.example.com");
+
+        // The error stays visible after the target recovers
+        dashboard.record(&status("https://a.example.com", 200, 90));
+        assert_eq!(dashboard.visible()[1].state, State::Up);
+        assert!(dashboard.visible()[1].last_error.is_some());
+    }
+
+    #[test]
+    fn test_set_targets_follows_reloads() {
+        let mut dashboard = dashboard();
+        dashboard.record(&status("https://a.example.com", 503, 80));
+        dashboard.record(&status("https://b.example.com", 200, 80));
+        dashboard.set_targets(&[
+            tagged_target("tcp://cache.internal:6379", &["db"]),
+            Target { name: "A".to_string(), ..tagged_target("https://a.example.com", &["api"]) },
+        ]);
+        // A check of a removed target that was already running when it was removed
+        dashboard.record(&status("https://b.example.com", 200, 80));
+
+        assert_eq!(names(&dashboard), ["tcp://cache.internal:6379", "https://a.example.com"]);
+        let rows = dashboard.visible();
+        assert_eq!(rows[0].state, State::Pending);
+        assert_eq!((rows[1].name.as_str(), rows[1].tags.clone(), rows[1].checks), ("A", vec!["api".to_string()], 1));
+        assert_eq!(dashboard.tags(), ["api", "db"]);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let ms = |values: &[u64]| values.iter().map(|ms| Duration::from_millis(*ms)).collect::<Vec<_>>();
+        assert_eq!(sparkline(&ms(&[0, 50, 100])), "▁▄█");
+        assert_eq!(sparkline(&ms(&[0, 0])), "▁▁");
+        assert_eq!(sparkline(&[]), "");
+
+        let mut dashboard = dashboard();
+        for latency in 0..30 {
+            dashboard.record(&status("https://a.example.com", 200, latency));
+        }
+        assert_eq!(dashboard.visible()[1].latencies.len(), SPARKLINE_WIDTH);
+    }
+
+    #[test]
+    fn test_sort_and_filter() {
+        let mut dashboard = dashboard();
+        dashboard.record(&status("https://b.example.com", 200, 50));
+        dashboard.record(&status("https://a.example.com", 503, 500));
+
+        assert_eq!(names(&dashboard), ["https://b.example.com", "https://a.example.com", "tcp://db.internal:5432"]);
+        dashboard.sort_by(SortKey::Name);
+        assert_eq!(names(&dashboard)[0], "https://a.example.com");
+        dashboard.sort_by(SortKey::State);
+        assert_eq!(names(&dashboard), ["https://a.example.com", "https://b.example.com", "tcp://db.internal:5432"]);
+        dashboard.sort_by(SortKey::Latency);
+        assert_eq!(names(&dashboard)[0], "https://a.example.com");
+        dashboard.sort_by(SortKey::Uptime);
+        assert_eq!(names(&dashboard)[0], "https://a.example.com");
+        dashboard.handle_key(KeyCode::Char('r'), KeyModifiers::NONE);
+        assert_eq!(names(&dashboard)[0], "tcp://db.internal:5432");
+
+        assert_eq!(dashboard.tags(), ["api", "db", "web"]);
+        dashboard.filter_tag(Some("web"));
+        assert_eq!(dashboard.visible().len(), 2);
+
+        // `t` steps through the tags, then back to every row
+        dashboard.filter_tag(None);
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            dashboard.handle_key(KeyCode::Char('t'), KeyModifiers::NONE);
+            seen.push(dashboard.visible().len());
+        }
+        assert_eq!(seen, [1, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut dashboard = dashboard();
+        assert!(!dashboard.handle_key(KeyCode::Char('s'), KeyModifiers::NONE));
+        assert_eq!(dashboard.sort, SortKey::Name);
+        assert!(dashboard.handle_key(KeyCode::Char('q'), KeyModifiers::NONE));
+        assert!(dashboard.handle_key(KeyCode::Char('c'), KeyModifiers::CONTROL));
+
+        for _ in 0..5 {
+            dashboard.handle_key(KeyCode::Down, KeyModifiers::NONE);
+        }
+        assert_eq!(dashboard.table.selected(), Some(2));
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut dashboard = dashboard();
+        dashboard.record(&status("https://a.example.com", 503, 120));
+        let mut terminal = Terminal::new(TestBackend::new(160, 10)).unwrap();
+        terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("3 targets, 0 up, 1 down - sorted by input"));
+        assert!(screen.contains("DOWN"));
+        assert!(screen.contains("503"));
+        assert!(screen.contains("120 ms"));
+        assert!(screen.contains("0.0%"));
+        assert!(screen.contains("unexpected status code 503"));
+        assert!(screen.contains("api,web"));
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use crate::output::StatusRecord;
+use crate::{Url, WebsiteStatus};
+
+/// An append-only history of check results, stored as JSON Lines in the `StatusRecord` schema.
+///
+/// Lines that cannot be parsed, such as one cut short by a crash, are skipped when reading.
+pub struct HistoryStore {
+    path: PathBuf,
+}
+
+impl HistoryStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        HistoryStore { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, status: &WebsiteStatus) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&StatusRecord::from(status))?;
+        line.push(b'\n');
+        // A single write keeps concurrent appenders from interleaving within a line
+        OpenOptions::new().create(true).append(true).open(&self.path)?.write_all(&line)
+    }
+
+    /// Every record checked at or after `since`, oldest first. A missing file is an empty history.
+    pub fn records_since(&self, since: SystemTime) -> io::Result<Vec<StatusRecord>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut records = Vec::new();
+        for line in BufReader::new(file).lines() {
+            if let Some(record) = parse_record(&line?) {
+                if record_time(&record).is_some_and(|time| time >= since) {
+                    records.push(record);
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    /// Summarizes each URL's results checked at or after `since`.
+    pub fn summarize(&self, since: SystemTime) -> io::Result<Vec<UrlSummary>> {
+        let mut by_url: BTreeMap<Url, Vec<StatusRecord>> = BTreeMap::new();
+        for record in self.records_since(since)? {
+            by_url.entry(record.url.clone()).or_default().push(record);
+        }
+
+        Ok(by_url.into_iter().map(|(url, records)| UrlSummary::new(url, &records)).collect())
+    }
+
+    /// Drops records checked before `cutoff`, returning how many were removed.
+    ///
+    /// The kept records are written to a temporary file that then replaces the history,
+    /// so a crash mid-prune leaves the old file intact.
+    pub fn prune(&self, cutoff: SystemTime) -> io::Result<usize> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err),
+        };
+
+        let mut kept = Vec::new();
+        let mut removed = 0;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            match parse_record(&line).as_ref().and_then(record_time) {
+                Some(time) if time >= cutoff => kept.push(line),
+                _ => removed += 1,
+            }
+        }
+
+        if removed > 0 {
+            let mut temp = self.path.clone().into_os_string();
+            temp.push(".tmp");
+            let mut out = File::create(&temp)?;
+            for line in kept {
+                writeln!(out, "{}", line)?;
+            }
+            out.sync_all()?;
+            fs::rename(&temp, &self.path)?;
+        }
+        Ok(removed)
+    }
+}
+
+fn parse_record(line: &str) -> Option<StatusRecord> {
+    serde_json::from_str(line).ok()
+}
+
+fn record_time(record: &StatusRecord) -> Option<SystemTime> {
+    humantime::parse_rfc3339(&record.timestamp).ok()
+}
+
+/// Uptime, latency percentiles and failures for one URL over a window.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct UrlSummary {
+    pub url: Url,
+    pub checks: usize,
+    pub failures: usize,
+    pub uptime_percent: f64,
+    pub p50_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+}
+
+impl UrlSummary {
+    fn new(url: Url, records: &[StatusRecord]) -> Self {
+        let checks = records.len();
+        let failures = records.iter().filter(|record| !record.ok).count();
+
+        // Only checks that reached the server have a meaningful latency: those with a
+        // status code, and probes, which report what they found instead
+        let mut latencies: Vec<u64> = records
+            .iter()
+            .filter(|record| record.status_code.is_some() || record.detail.is_some())
+            .map(|record| record.response_time_ms)
+            .collect();
+        latencies.sort_unstable();
+
+        UrlSummary {
+            url,
+            checks,
+            failures,
+            uptime_percent: if checks == 0 { 0.0 } else { (checks - failures) as f64 * 100.0 / checks as f64 },
+            p50_ms: percentile(&latencies, 50),
+            p95_ms: percentile(&latencies, 95),
+            p99_ms: percentile(&latencies, 99),
+        }
+    }
+}
+
+/// Nearest-rank percentile of already sorted values.
+fn percentile(sorted: &[u64], p: usize) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    Some(sorted[rank - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, temp_path};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn status(url: &str, secs: u64, outcome: Result<u16, &str>, latency_ms: u64) -> WebsiteStatus {
+        let latency = Duration::from_millis(latency_ms);
+        WebsiteStatus { response_time: latency, total_time: latency, timestamp: at(secs), ..testing::status(url, outcome) }
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs)
+    }
+
+    fn store(name: &str) -> HistoryStore {
+        let path = temp_path(name);
+        let _ = fs::remove_file(&path);
+        HistoryStore::new(path)
+    }
+
+    #[test]
+    fn test_percentile() {
+        let values: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&values, 50), Some(50));
+        assert_eq!(percentile(&values, 95), Some(95));
+        assert_eq!(percentile(&values, 99), Some(99));
+        assert_eq!(percentile(&[7], 99), Some(7));
+        assert_eq!(percentile(&[], 50), None);
+    }
+
+    #[test]
+    fn test_summary_over_window() {
+        let store = store("summary.jsonl");
+        store.append(&status("https://a.example", 0, Ok(200), 900)).unwrap();
+        for i in 1..=10 {
+            store.append(&status("https://a.example", 100 + i, Ok(200), i * 10)).unwrap();
+        }
+        store.append(&status("https://a.example", 200, Ok(503), 20)).unwrap();
+        store.append(&status("https://a.example", 201, Err("timed out"), 5000)).unwrap();
+        store.append(&status("https://b.example", 150, Ok(200), 40)).unwrap();
+
+        let summaries = store.summarize(at(100)).unwrap();
+        fs::remove_file(store.path()).unwrap();
+
+        assert_eq!(summaries.len(), 2);
+        let a = &summaries[0];
+        assert_eq!(a.url, "https://a.example");
+        assert_eq!(a.checks, 12);
+        assert_eq!(a.failures, 2);
+        assert!((a.uptime_percent - 83.333).abs() < 0.01);
+        // The timed-out check is not a latency sample; the one outside the window is ignored
+        assert_eq!(a.p50_ms, Some(50));
+        assert_eq!(a.p99_ms, Some(100));
+        assert_eq!(summaries[1].checks, 1);
+        assert_eq!(summaries[1].uptime_percent, 100.0);
+    }
+
+    #[test]
+    fn test_summary_counts_probe_latency() {
+        let store = store("probes.jsonl");
+        let probe = |secs, latency_ms| WebsiteStatus {
+            status: Ok(None),
+            detail: Some("connected to 10.0.0.5:5432".to_string()),
+            ..status("tcp://db.internal:5432", secs, Ok(200), latency_ms)
+        };
+        store.append(&probe(1, 10)).unwrap();
+        store.append(&probe(2, 30)).unwrap();
+        store.append(&status("tcp://db.internal:5432", 3, Err("connection refused"), 5000)).unwrap();
+
+        let summaries = store.summarize(at(0)).unwrap();
+        fs::remove_file(store.path()).unwrap();
+
+        assert_eq!(summaries[0].checks, 3);
+        assert_eq!(summaries[0].failures, 1);
+        assert_eq!(summaries[0].p50_ms, Some(10));
+        assert_eq!(summaries[0].p99_ms, Some(30));
+    }
+
+    #[test]
+    fn test_skips_corrupt_lines_and_missing_file() {
+        let store = store("corrupt.jsonl");
+        assert!(store.records_since(UNIX_EPOCH).unwrap().is_empty());
+        assert_eq!(store.prune(at(0)).unwrap(), 0);
+
+        store.append(&status("https://a.example", 0, Ok(200), 10)).unwrap();
+        OpenOptions::new().append(true).open(store.path()).unwrap().write_all(b"{\"url\":\"https://a.ex").unwrap();
+
+        let records = store.records_since(UNIX_EPOCH).unwrap();
+        fs::remove_file(store.path()).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].status_code, Some(200));
+    }
+
+    #[test]
+    fn test_prune() {
+        let store = store("prune.jsonl");
+        for secs in [0, 10, 20, 30] {
+            store.append(&status("https://a.example", secs, Ok(200), 10)).unwrap();
+        }
+
+        assert_eq!(store.prune(at(20)).unwrap(), 2);
+        assert_eq!(store.prune(at(20)).unwrap(), 0);
+        let remaining = store.records_since(UNIX_EPOCH).unwrap();
+        fs::remove_file(store.path()).unwrap();
+        assert_eq!(remaining.len(), 2);
+        assert_eq!(remaining[0].timestamp, "2023-11-14T22:13:40.000Z");
+    }
+}
@@ -0,0 +1,288 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+use crate::redirect::{self, Redirect};
+use crate::request::RequestSpec;
+use crate::retry;
+use crate::tls::{self, TlsInfo};
+use crate::transport;
+
+/// The parts of a response that the checker looks at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// Only read when a body limit was passed to `HttpClient::execute`.
+    pub body: Option<Vec<u8>>,
+    /// How long each phase of the request took, where the client could tell.
+    pub timings: Timings,
+    /// The redirects that led to this response, in order. The last one is this
+    /// response itself when the request's redirect policy did not follow it.
+    pub redirects: Vec<Redirect>,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Where the time of a request went. Phases are `None` when they did not happen,
+/// as DNS for an IP address or TLS for plain HTTP, or when the client cannot see them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Timings {
+    pub dns: Option<Duration>,
+    pub connect: Option<Duration>,
+    pub tls: Option<Duration>,
+    /// From sending the request to the first byte of the response.
+    pub ttfb: Option<Duration>,
+    /// From the end of the response headers to the end of the body.
+    pub download: Option<Duration>,
+    /// Spent on the responses that redirected to the final one.
+    pub redirect: Option<Duration>,
+}
+
+/// A request that produced no usable response.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpError {
+    pub message: String,
+    /// Whether trying again might succeed, as for timeouts and refused connections.
+    pub retryable: bool,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// A response body read up to one byte past a limit, which is enough to tell that
+/// it is too large. The download is timed only when the whole body arrived.
+pub(crate) struct LimitedBody {
+    limit: u64,
+    body: Vec<u8>,
+    start: Instant,
+}
+
+impl LimitedBody {
+    /// Starts timing the download.
+    pub(crate) fn new(limit: u64) -> Self {
+        LimitedBody { limit, body: Vec::new(), start: Instant::now() }
+    }
+
+    /// Whether more of the body is worth reading.
+    #[cfg(feature = "async")]
+    pub(crate) fn wants_more(&self) -> bool {
+        self.body.len() as u64 <= self.limit
+    }
+
+    /// Adds a chunk of the body, dropping whatever lies past the limit.
+    #[cfg(feature = "async")]
+    pub(crate) fn extend(&mut self, chunk: &[u8]) {
+        let room = (self.limit + 1).saturating_sub(self.body.len() as u64);
+        let take = usize::try_from(room).map_or(chunk.len(), |room| room.min(chunk.len()));
+        self.body.extend_from_slice(&chunk[..take]);
+    }
+
+    /// Reads the rest of the body from `reader`.
+    pub(crate) fn read_from(mut self, reader: impl Read, timings: &mut Timings) -> Result<Vec<u8>, HttpError> {
+        let room = (self.limit + 1).saturating_sub(self.body.len() as u64);
+        reader.take(room).read_to_end(&mut self.body).map_err(|err| {
+            if transport::is_timeout(&err) {
+                body_error("timed out", true)
+            } else {
+                // Malformed framing will not fix itself
+                body_error(&err, err.kind() != io::ErrorKind::InvalidData)
+            }
+        })?;
+        Ok(self.finish(timings))
+    }
+
+    pub(crate) fn finish(self, timings: &mut Timings) -> Vec<u8> {
+        if self.body.len() as u64 <= self.limit {
+            timings.download = Some(self.start.elapsed());
+        }
+        self.body
+    }
+}
+
+/// A body that stalls or breaks off mid-read is as transient as a timeout, so
+/// clients pass `retryable` unless the body itself was malformed.
+pub(crate) fn body_error(err: impl fmt::Display, retryable: bool) -> HttpError {
+    HttpError { message: format!("Failed to read body: {}", err), retryable }
+}
+
+/// Makes the requests for checks, so the checker can run against something other than the network.
+pub trait HttpClient: Send + Sync {
+    /// Sends `request` and returns the response, whatever its status code. With
+    /// `body_limit`, at most `limit + 1` bytes of the body are read.
+    fn execute(&self, request: &RequestSpec, timeout: Duration, body_limit: Option<u64>) -> Result<HttpResponse, HttpError>;
+
+    /// Like `execute`, calling `before_redirect` with the URL of each redirect before
+    /// following it, so the caller can hold it back or, with an error, give up on it.
+    /// Time spent in `before_redirect` does not count towards `timeout`. By default
+    /// redirects are followed as `execute` follows them.
+    fn execute_with(
+        &self,
+        request: &RequestSpec,
+        timeout: Duration,
+        body_limit: Option<u64>,
+        before_redirect: &mut dyn FnMut(&str) -> Result<(), HttpError>,
+    ) -> Result<HttpResponse, HttpError> {
+        let _ = before_redirect;
+        self.execute(request, timeout, body_limit)
+    }
+
+    /// Reports on the TLS handshake with the host of an `https` request. By default
+    /// this makes a handshake of its own, routed as the request's network options say.
+    fn inspect_tls(&self, request: &RequestSpec, timeout: Duration) -> Result<TlsInfo, String> {
+        tls::inspect(&request.url, &request.network, Instant::now() + timeout)
+    }
+}
+
+/// A client backed by a shared `ureq::Agent`. ureq hides its connections, so only
+/// the download is timed, and requests with network options are refused.
+pub struct UreqClient {
+    agent: ureq::Agent,
+}
+
+impl Default for UreqClient {
+    fn default() -> Self {
+        // Redirects are followed by `execute`, so it can record them and apply the request's policy
+        UreqClient { agent: ureq::AgentBuilder::new().redirects(0).build() }
+    }
+}
+
+impl HttpClient for UreqClient {
+    fn execute(&self, request: &RequestSpec, timeout: Duration, body_limit: Option<u64>) -> Result<HttpResponse, HttpError> {
+        self.execute_with(request, timeout, body_limit, &mut |_| Ok(()))
+    }
+
+    fn execute_with(
+        &self,
+        request: &RequestSpec,
+        timeout: Duration,
+        body_limit: Option<u64>,
+        before_redirect: &mut dyn FnMut(&str) -> Result<(), HttpError>,
+    ) -> Result<HttpResponse, HttpError> {
+        if !request.network.is_direct() {
+            let message = "Network Error: proxies, pinned addresses and local addresses need the timed client".to_string();
+            return Err(HttpError { message, retryable: false });
+        }
+        let mut deadline = Instant::now() + timeout;
+        let mut chain = Vec::new();
+        let mut request = request.clone();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(HttpError { message: "Network Error: timed out following redirects".to_string(), retryable: true });
+            }
+            let mut response = self.send(&request, remaining, body_limit)?;
+            match redirect::next_request(&request, &response, &request.redirects, &mut chain)? {
+                Some(next) => {
+                    let held = Instant::now();
+                    before_redirect(&next.url)?;
+                    deadline += held.elapsed();
+                    request = next;
+                }
+                None => {
+                    response.redirects = chain;
+                    return Ok(response);
+                }
+            }
+        }
+    }
+}
+
+impl UreqClient {
+    fn send(&self, request: &RequestSpec, timeout: Duration, body_limit: Option<u64>) -> Result<HttpResponse, HttpError> {
+        // Error statuses still carry a response worth evaluating
+        let response = match request.send(&self.agent, timeout) {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(err) => {
+                return Err(HttpError { retryable: retry::is_retryable(&err), message: err.to_string() });
+            }
+        };
+
+        let status = response.status();
+        let headers = response
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_string();
+                Some((name, value))
+            })
+            .collect();
+
+        let mut timings = Timings::default();
+        let body = match body_limit {
+            Some(limit) => Some(LimitedBody::new(limit).read_from(response.into_reader(), &mut timings)?),
+            None => None,
+        };
+
+        Ok(HttpResponse { status, headers, body, timings, redirects: Vec::new() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::Method;
+    use crate::testing::{MockResponse, MockServer};
+
+    fn get(url: &str) -> RequestSpec {
+        RequestSpec {
+            method: Method::Get,
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+            redirects: Default::default(),
+            network: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_reads_body_up_to_limit() {
+        let server = MockServer::start(vec![
+            MockResponse::new(200).header("X-Build", "42").body("0123456789"),
+            MockResponse::new(200).body("0123456789"),
+        ]);
+        let client = UreqClient::default();
+
+        let response = client.execute(&get(&server.url()), Duration::from_secs(5), Some(4)).unwrap();
+        assert_eq!(response.body.as_deref(), Some(&b"01234"[..]));
+        assert!(response.headers.contains(&("x-build".to_string(), "42".to_string())));
+
+        let response = client.execute(&get(&server.url()), Duration::from_secs(5), None).unwrap();
+        assert_eq!(response.body, None);
+    }
+
+    #[test]
+    fn test_error_statuses_are_responses() {
+        let server = MockServer::start(vec![MockResponse::new(503)]);
+        let response = UreqClient::default().execute(&get(&server.url()), Duration::from_secs(5), None).unwrap();
+        assert_eq!(response.status, 503);
+    }
+
+    #[test]
+    fn test_transport_errors() {
+        let err = UreqClient::default()
+            .execute(&get(&MockServer::refused_url()), Duration::from_secs(5), None)
+            .unwrap_err();
+        assert!(err.retryable);
+        assert!(err.message.contains("Connection Failed"));
+
+        let err = UreqClient::default().execute(&get("not a url"), Duration::from_secs(5), None).unwrap_err();
+        assert!(!err.retryable);
+
+        let mut request = get("http://127.0.0.1:1");
+        request.network.local_address = Some("127.0.0.1".parse().unwrap());
+        let err = UreqClient::default().execute(&request, Duration::from_secs(5), None).unwrap_err();
+        assert!(!err.retryable);
+        assert!(err.message.contains("timed client"));
+    }
+}
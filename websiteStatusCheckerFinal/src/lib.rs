@@ -0,0 +1,726 @@
+//! Concurrent website status checks, usable as a library.
+//!
+//! Build `Target`s by hand or with `target::parse_targets`, then hand them to a
+//! `Monitor` and read the results back as they complete:
+//!
+//! ```no_run
+//! use website_status_checker::{Config, Monitor, Target};
+//!
+//! let config = Config::default();
+//! let monitor = Monitor::start(&config, vec![Target::new("https://example.com", &config)]);
+//! for status in monitor.results() {
+//!     println!("{}: {:?}", status.url, status.status);
+//! }
+//! monitor.join();
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+pub mod alert;
+#[cfg(feature = "async")]
+pub mod async_engine;
+pub mod content;
+pub mod criteria;
+#[cfg(feature = "tui")]
+pub mod dashboard;
+pub mod history;
+pub mod http;
+pub mod metrics;
+pub mod monitor;
+pub mod network;
+pub mod output;
+pub mod pool;
+pub mod probe;
+pub mod ratelimit;
+pub mod redirect;
+pub mod reload;
+pub mod request;
+pub mod retry;
+pub mod schedule;
+pub mod status_page;
+pub mod target;
+pub mod tls;
+pub mod transport;
+#[cfg(test)]
+mod testing;
+
+use content::ContentPolicy;
+use criteria::{FailureReason, Observation, SuccessCriteria, Verdict};
+use http::{HttpClient, HttpError, HttpResponse, Timings};
+use request::{Body, Method};
+use probe::Finding;
+use ratelimit::{HostLimiter, HostLimits};
+use network::NetworkOptions;
+use redirect::{Redirect, RedirectPolicy};
+use retry::RetryPolicy;
+use tls::{TlsInfo, TlsPolicy};
+
+pub use monitor::{Monitor, ReloadHandle, ShutdownHandle};
+pub use pool::{ShutdownMode, WorkerPool};
+pub use target::Target;
+
+pub const DEFAULT_WORKERS: u16 = 10;
+pub const DEFAULT_TIMEOUT: u64 = 5;
+pub const MAX_RETRIES: u8 = 2;
+/// Monitoring-mode interval for targets that do not set their own.
+pub const DEFAULT_INTERVAL: u64 = 60;
+
+/// How often a retry backoff checks whether the check was cancelled.
+pub(crate) const CANCEL_POLL: Duration = Duration::from_millis(20);
+
+pub type Url = String;
+
+/// Settings shared by every target that does not override them.
+pub struct Config {
+    pub worker_threads: usize,
+    pub timeout: Duration,
+    pub retry: RetryPolicy,
+    pub criteria: SuccessCriteria,
+    pub method: Method,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Body>,
+    /// Re-check targets on this interval until shut down. Without it, and without
+    /// per-target intervals, every target is checked once.
+    pub interval: Option<Duration>,
+    /// What happens to queued checks when a monitor is shut down.
+    pub shutdown_mode: ShutdownMode,
+    /// How long to wait for checks after a shutdown is requested before giving up on them.
+    pub shutdown_timeout: Option<Duration>,
+    /// Politeness limits applied to each host before its checks are dispatched.
+    pub host_limits: HostLimits,
+    /// Inspect the TLS handshake and certificates of HTTPS targets.
+    pub tls: Option<TlsPolicy>,
+    /// Which redirects checks follow, and which fail them.
+    pub redirects: RedirectPolicy,
+    /// Proxy, pinned addresses and local address for HTTP checks.
+    pub network: NetworkOptions,
+    /// Hash the response bodies of HTTP targets, to notice content changes.
+    pub content: Option<ContentPolicy>,
+}
+
+impl Config {
+    pub fn new(worker_threads: usize, timeout: Duration, retry: RetryPolicy, criteria: SuccessCriteria) -> Self {
+        Config {
+            worker_threads,
+            timeout,
+            retry,
+            criteria,
+            method: Method::Get,
+            headers: Vec::new(),
+            body: None,
+            interval: None,
+            shutdown_mode: ShutdownMode::Drain,
+            shutdown_timeout: None,
+            host_limits: HostLimits::default(),
+            tls: None,
+            redirects: RedirectPolicy::default(),
+            network: NetworkOptions::default(),
+            content: None,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new(
+            DEFAULT_WORKERS as usize,
+            Duration::from_secs(DEFAULT_TIMEOUT),
+            RetryPolicy::new(MAX_RETRIES),
+            SuccessCriteria::default(),
+        )
+    }
+}
+
+/// The outcome of a single request made while checking a URL.
+#[derive(Clone, Debug)]
+pub struct Attempt {
+    pub latency: Duration,
+    /// As `WebsiteStatus::status`.
+    pub outcome: Result<Option<u16>, String>,
+}
+
+/// The result of checking one target.
+#[derive(Clone, Debug)]
+pub struct WebsiteStatus {
+    pub name: String,
+    pub url: String,
+    /// Outcome of the final attempt: the status code of any response, or the request error.
+    /// Probes, which have no status code, report `Ok(None)` when they reach the service.
+    pub status: Result<Option<u16>, String>,
+    /// Whether the final attempt met the success criteria.
+    pub verdict: Verdict,
+    /// Latency of the final attempt, so retries do not inflate it.
+    pub response_time: Duration,
+    /// Wall-clock time across all attempts, including the delays between them.
+    pub total_time: Duration,
+    pub attempts: Vec<Attempt>,
+    pub timestamp: SystemTime,
+    /// Where the time of the final attempt went.
+    pub timings: Timings,
+    /// The redirects the final attempt went through, in order.
+    pub redirects: Vec<Redirect>,
+    /// What the final attempt of a probe found, such as the addresses a name resolved to.
+    pub detail: Option<String>,
+    /// The TLS inspection of an HTTPS target, when the target asks for one.
+    pub tls: Option<Result<TlsInfo, String>>,
+    /// A certificate that has expired, expires soon or is not trusted, or an inspection that failed.
+    pub tls_warning: Option<String>,
+    /// Hash of the final response's normalized body, when the target asks for one
+    /// and the whole body was read.
+    pub content_hash: Option<String>,
+}
+
+impl WebsiteStatus {
+    pub fn attempt_count(&self) -> usize {
+        self.attempts.len()
+    }
+
+    /// The HTTP status code of the final response, if there was one.
+    pub fn status_code(&self) -> Option<u16> {
+        self.status.as_ref().ok().copied().flatten()
+    }
+}
+
+/// Checks a target, retrying as its policy allows, and returns the final result.
+pub fn check_website(client: &dyn HttpClient, target: &Target) -> WebsiteStatus {
+    check_website_cancellable(client, target, &AtomicBool::new(false))
+}
+
+/// Like `check_website`, but makes no further attempts once `cancel` is set. The
+/// request in flight at that point still runs to completion or its timeout.
+pub fn check_website_cancellable(client: &dyn HttpClient, target: &Target, cancel: &AtomicBool) -> WebsiteStatus {
+    check_website_limited(client, target, cancel, &HostLimiter::new(HostLimits::default()))
+}
+
+/// Like `check_website_cancellable`, for a check that holds a permit from `limiter`.
+/// Its retries and redirects keep to the rate and crawl delay of their hosts.
+pub(crate) fn check_website_limited(client: &dyn HttpClient, target: &Target, cancel: &AtomicBool, limiter: &HostLimiter) -> WebsiteStatus {
+    let mut check = CheckRun::new(target);
+    loop {
+        let attempt_start = Instant::now();
+        // Waiting for a redirect's host is not part of the attempt's latency
+        let mut held = Duration::ZERO;
+        let mut pace = |url: &str| {
+            let start = Instant::now();
+            let paced = limiter.pace(url, cancel);
+            held += start.elapsed();
+            if paced {
+                Ok(())
+            } else {
+                Err(HttpError { message: "Cancelled before following a redirect".to_string(), retryable: false })
+            }
+        };
+        let observed = client.execute_with(&target.request, target.timeout, check.body_limit(), &mut pace);
+        match check.record(observed, attempt_start.elapsed().saturating_sub(held)) {
+            Some(delay) if sleep_unless_cancelled(delay, cancel) && limiter.pace(&target.request.url, cancel) => continue,
+            _ => break,
+        }
+    }
+
+    let mut status = check.finish();
+    if let Some(policy) = tls_policy(target) {
+        status.set_tls(client.inspect_tls(&target.request, target.timeout), policy);
+    }
+    status
+}
+
+/// The attempts made so far while checking one target, shared by every engine so
+/// they judge responses and retry alike.
+pub(crate) struct CheckRun<'a> {
+    target: &'a Target,
+    attempts: Vec<Attempt>,
+    verdict: Verdict,
+    timings: Timings,
+    redirects: Vec<Redirect>,
+    detail: Option<String>,
+    content_hash: Option<String>,
+    start: Instant,
+}
+
+impl<'a> CheckRun<'a> {
+    pub(crate) fn new(target: &'a Target) -> Self {
+        CheckRun {
+            target,
+            attempts: Vec::new(),
+            verdict: Verdict::Fail(FailureReason::Request("Unknown error".to_string())),
+            timings: Timings::default(),
+            redirects: Vec::new(),
+            detail: None,
+            content_hash: None,
+            start: Instant::now(),
+        }
+    }
+
+    /// How much of the response body to read, if the criteria look at it or it is hashed.
+    pub(crate) fn body_limit(&self) -> Option<u64> {
+        let criteria = &self.target.criteria;
+        let wanted = criteria.needs_body() || self.target.content.is_some();
+        (wanted && self.target.request.method.has_response_body()).then(|| criteria.body_limit())
+    }
+
+    /// Judges an attempt, returning the delay before the next one if another is due.
+    pub(crate) fn record(&mut self, observed: Result<HttpResponse, HttpError>, latency: Duration) -> Option<Duration> {
+        let retryable = match &observed {
+            Ok(response) => retry::is_retryable_status(response.status),
+            Err(err) => err.retryable,
+        };
+
+        match observed {
+            Ok(HttpResponse { status, headers, body, timings, redirects }) => {
+                // A redirect the policy forbids fails the check whatever the final response
+                self.verdict = match self.target.request.redirects.violation(&redirects) {
+                    Some(violation) => Verdict::Fail(FailureReason::Redirect(violation)),
+                    None => self.target.criteria.evaluate(&Observation {
+                        status,
+                        latency,
+                        headers: &headers,
+                        body: body.as_deref(),
+                    }),
+                };
+                // A body cut short at the limit is not hashed, as its end could have changed unseen
+                self.content_hash = match (&self.target.content, &body) {
+                    (Some(policy), Some(body)) if body.len() as u64 <= self.target.criteria.body_limit() => {
+                        Some(policy.hash(body))
+                    }
+                    _ => None,
+                };
+                self.attempts.push(Attempt { latency, outcome: Ok(Some(status)) });
+                self.timings = timings;
+                self.redirects = redirects;
+            }
+            Err(err) => {
+                self.verdict = Verdict::Fail(FailureReason::Request(err.message.clone()));
+                self.attempts.push(Attempt { latency, outcome: Err(err.message) });
+                self.timings = Timings::default();
+                self.redirects = Vec::new();
+                self.content_hash = None;
+            }
+        }
+        self.next_delay(retryable)
+    }
+
+    /// Judges a probe attempt: what it found, or why it could not reach the service.
+    pub(crate) fn record_probe(&mut self, observed: Result<Finding, HttpError>, latency: Duration) -> Option<Duration> {
+        let retryable = match observed {
+            Ok(Finding { detail, mismatch }) => {
+                let too_slow = self.target.criteria.max_latency.filter(|limit| latency > *limit);
+                self.verdict = match (mismatch, too_slow) {
+                    (Some(mismatch), _) => Verdict::Fail(FailureReason::Probe(mismatch)),
+                    (None, Some(limit)) => Verdict::Fail(FailureReason::TooSlow { latency, limit }),
+                    (None, None) => Verdict::Pass,
+                };
+                self.attempts.push(Attempt { latency, outcome: Ok(None) });
+                self.detail = Some(detail);
+                false
+            }
+            Err(err) => {
+                self.verdict = Verdict::Fail(FailureReason::Request(err.message.clone()));
+                self.attempts.push(Attempt { latency, outcome: Err(err.message) });
+                self.detail = None;
+                err.retryable
+            }
+        };
+        self.next_delay(retryable)
+    }
+
+    fn next_delay(&self, retryable: bool) -> Option<Duration> {
+        let attempt = self.attempts.len() as u32;
+        let retry = &self.target.retry;
+        if self.verdict.is_pass() || !retryable || attempt >= retry.max_attempts() {
+            return None;
+        }
+        Some(retry.delay(attempt))
+    }
+
+    pub(crate) fn finish(self) -> WebsiteStatus {
+        let last = self.attempts.last().expect("at least one attempt is always made");
+
+        WebsiteStatus {
+            name: self.target.name.clone(),
+            url: self.target.request.url.clone(),
+            status: last.outcome.clone(),
+            verdict: self.verdict,
+            response_time: last.latency,
+            total_time: self.start.elapsed(),
+            timestamp: SystemTime::now(),
+            attempts: self.attempts,
+            timings: self.timings,
+            redirects: self.redirects,
+            detail: self.detail,
+            tls: None,
+            tls_warning: None,
+            content_hash: self.content_hash,
+        }
+    }
+}
+
+/// The result for a target that is not checked because `Target::validate` rejects it.
+pub(crate) fn rejected(target: &Target, reason: &str) -> WebsiteStatus {
+    let mut check = CheckRun::new(target);
+    check.record(Err(HttpError { message: format!("Invalid target: {}", reason), retryable: false }), Duration::ZERO);
+    check.finish()
+}
+
+/// The TLS policy for `target`, if it is an HTTPS target that asks for inspection.
+pub(crate) fn tls_policy(target: &Target) -> Option<&TlsPolicy> {
+    target.tls.as_ref().filter(|_| tls::is_https(&target.request.url))
+}
+
+impl WebsiteStatus {
+    /// Records a TLS inspection, warning about it as `policy` says.
+    pub(crate) fn set_tls(&mut self, inspection: Result<TlsInfo, String>, policy: &TlsPolicy) {
+        self.tls_warning = match &inspection {
+            Ok(info) => info.warning(policy, self.timestamp),
+            Err(err) => Some(format!("TLS inspection failed: {}", err)),
+        };
+        self.tls = Some(inspection);
+    }
+}
+
+/// Sleeps for `duration` in short slices, returning `false` early if `cancel` is set.
+fn sleep_unless_cancelled(duration: Duration, cancel: &AtomicBool) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep((deadline - now).min(CANCEL_POLL));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use retry::Backoff;
+    use testing::{quick_retries, FakeClient, MockResponse, MockServer};
+    use transport::TimedClient;
+
+    #[test]
+    fn test_check_website_success() {
+        let server = MockServer::start(vec![MockResponse::new(200)]);
+
+        let status = check_website(&TimedClient::default(), &Target::new(&server.url(), &Config::default()));
+        assert!(status.status.is_ok());
+        assert_eq!(status.status.unwrap(), Some(200));
+        assert!(status.verdict.is_pass());
+        assert!(status.response_time > Duration::ZERO);
+        assert!(status.timings.connect.is_some());
+        assert!(status.timings.ttfb.is_some());
+    }
+
+    #[test]
+    fn test_check_website_failure() {
+        let target = Target { retry: quick_retries(1), ..Target::new(&MockServer::refused_url(), &Config::default()) };
+
+        let status = check_website(&TimedClient::default(), &target);
+        assert!(status.status.as_ref().unwrap_err().contains("Connection Failed"));
+        assert!(status.response_time > Duration::ZERO);
+        assert!(status.total_time >= status.response_time);
+        assert_eq!(status.attempt_count(), 2);
+    }
+
+    #[test]
+    fn test_check_website_timeout() {
+        let server = MockServer::start(vec![MockResponse::new(200).delay(Duration::from_millis(500))]);
+        let target = Target {
+            timeout: Duration::from_millis(100),
+            retry: quick_retries(0),
+            ..Target::new(&server.url(), &Config::default())
+        };
+
+        let status = check_website(&TimedClient::default(), &target);
+        assert!(status.status.unwrap_err().contains("timed out"));
+        assert!(status.response_time < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_check_website_slow_response() {
+        let server = MockServer::start(vec![MockResponse::new(200).delay(Duration::from_millis(150))]);
+        let mut target = Target { retry: quick_retries(0), ..Target::new(&server.url(), &Config::default()) };
+        target.criteria.max_latency = Some(Duration::from_millis(50));
+
+        // The response arrives within the timeout, but too late for the latency SLA
+        let status = check_website(&TimedClient::default(), &target);
+        assert_eq!(status.status, Ok(Some(200)));
+        assert!(status.response_time >= Duration::from_millis(150));
+        assert!(matches!(status.verdict, Verdict::Fail(FailureReason::TooSlow { .. })));
+    }
+
+    #[test]
+    fn test_check_website_retries_server_errors_until_success() {
+        let server = MockServer::start(vec![MockResponse::new(503), MockResponse::new(502), MockResponse::new(200)]);
+        let target = Target { retry: quick_retries(3), ..Target::new(&server.url(), &Config::default()) };
+
+        let status = check_website(&TimedClient::default(), &target);
+        assert_eq!(status.status, Ok(Some(200)));
+        assert!(status.verdict.is_pass());
+        let outcomes: Vec<_> = status.attempts.iter().map(|attempt| attempt.outcome.clone()).collect();
+        assert_eq!(outcomes, vec![Ok(Some(503)), Ok(Some(502)), Ok(Some(200))]);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_check_website_with_fake_client() {
+        let client = FakeClient::new(vec![
+            FakeClient::error("timed out", true),
+            FakeClient::status(500),
+            FakeClient::status(204),
+        ]);
+        let target = Target { retry: quick_retries(5), ..Target::new("https://example.com", &Config::default()) };
+
+        let status = check_website(&client, &target);
+        assert_eq!(status.status, Ok(Some(204)));
+        assert_eq!(status.attempt_count(), 3);
+        assert_eq!(status.attempts[0].outcome, Err("timed out".to_string()));
+        assert_eq!(client.requests().len(), 3);
+        assert_eq!(client.requests()[0].url, "https://example.com");
+    }
+
+    #[test]
+    fn test_check_website_stops_retrying_when_cancelled() {
+        let client = FakeClient::new(vec![FakeClient::error("timed out", true), FakeClient::status(200)]);
+        let target = Target {
+            retry: RetryPolicy {
+                base_delay: Duration::from_secs(5),
+                max_delay: Duration::from_secs(5),
+                backoff: Backoff::Fixed,
+                ..RetryPolicy::new(3)
+            },
+            ..Target::new("https://example.com", &Config::default())
+        };
+
+        let cancel = AtomicBool::new(false);
+        let start = Instant::now();
+        let status = thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                cancel.store(true, Ordering::Relaxed);
+            });
+            check_website_cancellable(&client, &target, &cancel)
+        });
+
+        // The backoff is cut short and no second attempt is made
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(status.attempt_count(), 1);
+        assert_eq!(status.status, Err("timed out".to_string()));
+    }
+
+    #[test]
+    fn test_check_website_waits_between_retries() {
+        let target = Target {
+            retry: RetryPolicy {
+                max_retries: 2,
+                backoff: Backoff::Fixed,
+                base_delay: Duration::from_millis(100),
+                max_delay: Duration::from_millis(100),
+            },
+            ..Target::new(&MockServer::refused_url(), &Config::default())
+        };
+
+        // Nothing listens on the port, so every attempt is refused
+        let start = Instant::now();
+        let status = check_website(&TimedClient::default(), &target);
+        assert!(status.status.is_err());
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        // Each attempt is recorded, and the backoff only counts towards the total
+        assert_eq!(status.attempt_count(), 3);
+        assert!(status.attempts.iter().all(|attempt| attempt.outcome.is_err()));
+        assert_eq!(status.response_time, status.attempts[2].latency);
+        assert!(status.response_time < Duration::from_millis(100));
+        assert!(status.total_time >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_check_website_does_not_retry_permanent_errors() {
+        let target = Target {
+            retry: RetryPolicy {
+                base_delay: Duration::from_secs(5),
+                max_delay: Duration::from_secs(5),
+                ..RetryPolicy::new(MAX_RETRIES)
+            },
+            ..Target::new("not a url", &Config::default())
+        };
+
+        let start = Instant::now();
+        let status = check_website(&TimedClient::default(), &target);
+        assert!(status.status.is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(status.attempt_count(), 1);
+    }
+
+    #[test]
+    fn test_check_website_inspects_tls() {
+        let expiring = tls::CertificateInfo {
+            subject: "CN=example.com".to_string(),
+            issuer: "CN=Example CA".to_string(),
+            names: vec!["example.com".to_string()],
+            not_before: SystemTime::now() - Duration::from_secs(90 * 24 * 60 * 60),
+            not_after: SystemTime::now() + Duration::from_secs(5 * 24 * 60 * 60 + 60),
+        };
+        let info = TlsInfo {
+            version: "TLSv1.3".to_string(),
+            cipher: "TLS13_AES_128_GCM_SHA256".to_string(),
+            chain: vec![expiring],
+            verify_error: None,
+        };
+        let config = Config { tls: Some(TlsPolicy::default()), ..Config::default() };
+
+        let client = FakeClient::new(vec![FakeClient::status(200)]).with_tls(Ok(info.clone()));
+        let status = check_website(&client, &Target::new("https://example.com", &config));
+        assert!(status.verdict.is_pass());
+        assert_eq!(status.tls, Some(Ok(info)));
+        assert_eq!(status.tls_warning.as_deref(), Some("certificate CN=example.com expires in 5 days"));
+
+        // Plain HTTP targets have nothing to inspect
+        let client = FakeClient::new(vec![FakeClient::status(200)]);
+        let status = check_website(&client, &Target::new("http://example.com", &config));
+        assert_eq!(status.tls, None);
+        assert_eq!(status.tls_warning, None);
+
+        let client = FakeClient::new(vec![FakeClient::status(200)]).with_tls(Err("handshake failed".to_string()));
+        let status = check_website(&client, &Target::new("https://example.com", &config));
+        assert_eq!(status.tls_warning.as_deref(), Some("TLS inspection failed: handshake failed"));
+    }
+
+    #[test]
+    fn test_check_website_reports_error_status_codes() {
+        let server = MockServer::start(vec![MockResponse::new(404)]);
+
+        let status = check_website(&TimedClient::default(), &Target::new(&server.url(), &Config::default()));
+        assert_eq!(status.status, Ok(Some(404)));
+        assert_eq!(status.verdict, Verdict::Fail(FailureReason::UnexpectedStatus(404)));
+        assert_eq!(status.attempt_count(), 1);
+    }
+
+    #[test]
+    fn test_check_website_body_assertion() {
+        let server = MockServer::start(vec![MockResponse::new(200).body("Service error")]);
+        let mut target = Target::new(&server.url(), &Config::default());
+        target.criteria.body_contains = Some("All systems operational".to_string());
+
+        let status = check_website(&TimedClient::default(), &target);
+        assert_eq!(status.status, Ok(Some(200)));
+        assert_eq!(
+            status.verdict,
+            Verdict::Fail(FailureReason::BodyMissing("All systems operational".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_check_website_hashes_body() {
+        let server = MockServer::start(vec![
+            MockResponse::new(200).body("<p>\n  Hello\n</p>"),
+            MockResponse::new(200).body("0123456789"),
+            MockResponse::new(200).body("Hello"),
+        ]);
+        let policy = ContentPolicy { strip_whitespace: true, ..ContentPolicy::default() };
+        let mut target = Target::new(&server.url(), &Config { content: Some(policy.clone()), ..Config::default() });
+
+        let status = check_website(&TimedClient::default(), &target);
+        assert_eq!(status.content_hash, Some(policy.hash(b"<p>Hello</p>")));
+
+        // A body past the limit was not read to the end
+        target.criteria.max_body_bytes = Some(4);
+        target.criteria.expected_status = vec!["200".parse().unwrap()];
+        let status = check_website(&TimedClient::default(), &target);
+        assert_eq!(status.content_hash, None);
+
+        target.content = None;
+        target.criteria.max_body_bytes = None;
+        assert_eq!(check_website(&TimedClient::default(), &target).content_hash, None);
+    }
+
+    #[test]
+    fn test_check_website_sends_method_headers_and_body() {
+        let server = MockServer::start(vec![MockResponse::new(201)]);
+
+        let mut target = Target::new(&format!("{}hooks", server.url()), &Config::default());
+        target.request.method = Method::Post;
+        target.request.set_header("X-Token", "abc");
+        target.request.body = Some(Body::Text("ping=1&".to_string()));
+        let status = check_website(&TimedClient::default(), &target);
+        assert_eq!(status.status, Ok(Some(201)));
+
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/hooks");
+        assert_eq!(request.header("X-Token"), Some("abc"));
+        assert_eq!(request.body, b"ping=1&");
+    }
+
+    #[test]
+    fn test_check_website_records_redirects() {
+        let final_server = MockServer::start(vec![MockResponse::new(200)]);
+        let server = MockServer::start(vec![
+            MockResponse::new(301).header("Location", &final_server.url()),
+            MockResponse::new(302).header("Location", "/next"),
+        ]);
+
+        let status = check_website(&TimedClient::default(), &Target::new(&server.url(), &Config::default()));
+        assert!(status.verdict.is_pass());
+        assert_eq!(status.redirects.len(), 1);
+        assert_eq!(status.redirects[0].status, 301);
+        assert_eq!(status.redirects[0].location, final_server.url());
+
+        // With a limit of zero the redirect itself is the response
+        let mut target = Target::new(&server.url(), &Config::default());
+        target.request.redirects.max_redirects = 0;
+        target.criteria.expected_status = vec!["302".parse().unwrap()];
+        let status = check_website(&TimedClient::default(), &target);
+        assert!(status.verdict.is_pass());
+        assert_eq!(status.redirects[0].location, format!("{}next", server.url()));
+    }
+
+    #[test]
+    fn test_retries_and_redirects_keep_to_host_limits() {
+        let server = MockServer::start(vec![
+            MockResponse::new(302).header("Location", "/next"),
+            MockResponse::new(503),
+            MockResponse::new(200),
+        ]);
+        let target = Target { retry: quick_retries(1), ..Target::new(&server.url(), &Config::default()) };
+        let limiter = HostLimiter::new(HostLimits { crawl_delay: Some(Duration::from_millis(150)), ..HostLimits::default() });
+        let cancel = AtomicBool::new(false);
+        assert!(limiter.pace(&target.request.url, &cancel));
+
+        // The redirect and the retry each wait out the crawl delay
+        let start = Instant::now();
+        let status = check_website_limited(&TimedClient::default(), &target, &cancel, &limiter);
+        assert!(status.verdict.is_pass());
+        assert_eq!(server.requests().len(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(290), "{:?}", start.elapsed());
+        // Waiting for the redirect is not part of the attempt's latency
+        assert!(status.attempts[0].latency < Duration::from_millis(150), "{:?}", status.attempts[0].latency);
+    }
+
+    #[test]
+    fn test_check_website_fails_on_forbidden_redirects() {
+        let downgrade = HttpResponse {
+            redirects: vec![Redirect {
+                url: "https://example.com/".to_string(),
+                status: 301,
+                location: "http://example.com/".to_string(),
+            }],
+            ..FakeClient::status(301).unwrap()
+        };
+        let mut target = Target { retry: quick_retries(2), ..Target::new("https://example.com/", &Config::default()) };
+        target.request.redirects.deny_downgrade = true;
+
+        let status = check_website(&FakeClient::new(vec![Ok(downgrade)]), &target);
+        assert_eq!(
+            status.verdict,
+            Verdict::Fail(FailureReason::Redirect(
+                "redirect from https://example.com/ to insecure http://example.com/".to_string()
+            ))
+        );
+        assert_eq!(status.attempt_count(), 1);
+        assert_eq!(status.redirects.len(), 1);
+    }
+}
@@ -1,225 +1,258 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::sync::{Arc, Mutex, mpsc::{self, Sender, Receiver}};
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::{Duration, Instant, SystemTime};
-use std::collections::HashMap;
-use ureq;
 
-const DEFAULT_TIMEOUT: u64 = 5;
-const MAX_RETRIES: u8 = 3;
+use clap::Parser;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
 
-type Url = String;
+#[cfg(feature = "tui")]
+use website_status_checker::dashboard;
+use website_status_checker::content::ContentTracker;
+use website_status_checker::history::HistoryStore;
+use website_status_checker::output::{self, ResultWriter};
+use website_status_checker::reload::FileWatcher;
+use website_status_checker::status_page::{self, StatusBoard};
+#[cfg(feature = "async")]
+use website_status_checker::async_engine::AsyncEngine;
+use website_status_checker::{metrics, target, Config, Monitor, ReloadHandle, Target, WebsiteStatus};
 
-enum MonitorMessage {
-    CheckUrl(Url),
-    Shutdown,
-}
+mod cli;
 
-struct Config {
-    worker_threads: usize,
-    timeout: Duration,
-    max_retries: u8,
-}
+use cli::Cli;
 
-impl Config {
-    fn new(worker_threads: usize, timeout: Duration, max_retries: u8) -> Self {
-        Config {
-            worker_threads,
-            timeout,
-            max_retries,
-        }
+/// How often a long-running monitor drops history past the retention period.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How often the target file is checked for edits, and for a SIGHUP.
+const RELOAD_POLL: Duration = Duration::from_secs(1);
+
+fn prune_history(history: &HistoryStore, retention: Duration) {
+    let cutoff = SystemTime::now().checked_sub(retention).unwrap_or(SystemTime::UNIX_EPOCH);
+    if let Err(err) = history.prune(cutoff) {
+        eprintln!("Failed to prune history {}: {}", history.path().display(), err);
     }
 }
 
-struct WebsiteStatus {
-    url: String,
-    status: Result<u16, String>,
-    response_time: Duration,
-    timestamp: SystemTime,
-}
+/// Re-reads the target list into the monitor on SIGHUP and, with `watch`, whenever
+/// the file changes, then passes it to `on_reload` for the views of the targets. A
+/// list that fails to parse leaves the current targets in place.
+fn reload_targets(
+    path: PathBuf,
+    config: Config,
+    watch: bool,
+    reload: ReloadHandle,
+    mut on_reload: impl FnMut(&[Target]) + Send + 'static,
+) {
+    let hangup = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGHUP, Arc::clone(&hangup)).expect("Failed to register signal handler");
+    let mut watcher = watch.then(|| FileWatcher::new(&path));
+
+    thread::spawn(move || loop {
+        thread::sleep(RELOAD_POLL);
+        let changed = watcher.as_mut().is_some_and(FileWatcher::changed);
+        if !hangup.swap(false, Ordering::Relaxed) && !changed {
+            continue;
+        }
 
-fn check_website(url: &str, timeout: Duration, max_retries: u8) -> WebsiteStatus {
-    let mut retries = 0;
-    let mut last_error = None;
-    let start = Instant::now();
-
-    while retries < max_retries {
-        let response = ureq::get(url).timeout(timeout).call();
-
-        if let Ok(response) = response {
-            let elapsed = start.elapsed();
-            return WebsiteStatus {
-                url: url.to_string(),
-                status: Ok(response.status()),
-                response_time: elapsed,
-                timestamp: SystemTime::now(),
-            };
-        } else {
-            last_error = Some(format!("{}", response.unwrap_err()));
-            retries += 1;
+        let targets = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| target::parse_targets(&text, &config).map_err(|err| err.to_string()));
+        match targets {
+            Ok(targets) => {
+                // The monitor has stopped scheduling
+                if !reload.reload(targets.clone()) {
+                    return;
+                }
+                on_reload(&targets);
+            }
+            Err(err) => eprintln!("Failed to reload {}, keeping the current targets: {}", path.display(), err),
         }
-    }
+    });
+}
 
-    WebsiteStatus {
-        url: url.to_string(),
-        status: Err(last_error.unwrap_or("Unknown error".to_string())),
-        response_time: Duration::ZERO,
-        timestamp: SystemTime::now(),
+/// Checks every target once on the async engine.
+#[cfg(feature = "async")]
+fn run_async(cli: &Cli, targets: Vec<Target>, metrics_listener: Option<TcpListener>, on_result: impl FnMut(WebsiteStatus)) {
+    if targets.iter().any(|target| target.interval.is_some()) {
+        eprintln!("The async engine checks each URL once; targets with an interval need --engine threads");
+        process::exit(1);
     }
-}
 
-fn monitor_worker(receiver: Arc<Mutex<Receiver<MonitorMessage>>>, sender: Sender<WebsiteStatus>, timeout: Duration, max_retries: u8) {
-    while let Ok(message) = receiver.lock().unwrap().recv() {
-        match message {
-            MonitorMessage::CheckUrl(url) => {
-                let status = check_website(&url, timeout, max_retries);
-                sender.send(status).unwrap();
-            }
-            MonitorMessage::Shutdown => {
-                break;
-            }
-        }
+    let engine = AsyncEngine::new(cli.concurrency as usize, cli.per_host as usize).unwrap_or_else(|err| {
+        eprintln!("Failed to start the async engine: {}", err);
+        process::exit(1);
+    });
+    if let Some(listener) = metrics_listener {
+        metrics::serve(listener, engine.metrics());
+    }
+    if let Err(err) = Arc::new(engine).run(targets, on_result) {
+        eprintln!("Failed to start the async runtime: {}", err);
+        process::exit(1);
     }
 }
 
 fn main() {
-    // Open the file containing URLs
-    let file = File::open("urls.txt").expect("Failed to open file urls.txt");
-    let reader = BufReader::new(file);
-
-    // Read URLs line by line into a vector
-    let urls: Vec<String> = reader
-        .lines()
-        .map(|line| line.expect("Failed to read line"))
-        .collect();
-
-    if urls.is_empty() {
-        eprintln!("No URLs found in the file.");
+    let cli = Cli::parse();
+    let config = cli.config().unwrap_or_else(|err| err.exit());
+    let history = cli.history();
+
+    if let (Some(history), Some(window)) = (&history, cli.report) {
+        let since = SystemTime::now().checked_sub(window).unwrap_or(SystemTime::UNIX_EPOCH);
+        let result = history
+            .summarize(since)
+            .and_then(|summaries| output::write_summaries(io::stdout(), cli.format, &summaries));
+        if let Err(err) = result {
+            eprintln!("Failed to read history {}: {}", history.path().display(), err);
+            process::exit(1);
+        }
         return;
     }
 
-    let config = Config::new(10, Duration::from_secs(DEFAULT_TIMEOUT), MAX_RETRIES);
-
-    let (task_sender, task_receiver) = mpsc::channel();
-    let (result_sender, result_receiver) = mpsc::channel();
-
-    let task_receiver = std::sync::Arc::new(std::sync::Mutex::new(task_receiver));
-
-    let mut workers = vec![];
-
-    for _ in 0..config.worker_threads {
-        let task_receiver = std::sync::Arc::clone(&task_receiver);
-        let result_sender = result_sender.clone();
-
-        let timeout = config.timeout;
-        let max_retries = config.max_retries;
+    // Read the target list from the input file or stdin
+    let text = match cli.read_input() {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
 
-        let handle = thread::spawn(move || {
-            monitor_worker(task_receiver, result_sender, timeout, max_retries);
-        });
+    let targets = match target::parse_targets(&text, &config) {
+        Ok(targets) => targets,
+        Err(err) => {
+            eprintln!("{}: {}", cli.input.display(), err);
+            process::exit(1);
+        }
+    };
 
-        workers.push(handle);
+    if targets.is_empty() {
+        eprintln!("No URLs found in the input.");
+        return;
     }
 
-    for url in urls {
-        task_sender.send(MonitorMessage::CheckUrl(url)).unwrap();
+    let metrics_listener = cli.metrics_addr.map(|addr| {
+        TcpListener::bind(addr).unwrap_or_else(|err| {
+            eprintln!("Failed to serve metrics on {}: {}", addr, err);
+            process::exit(1);
+        })
+    });
+
+    let status_board = cli.status_addr.map(|addr| {
+        let listener = TcpListener::bind(addr).unwrap_or_else(|err| {
+            eprintln!("Failed to serve the status page on {}: {}", addr, err);
+            process::exit(1);
+        });
+        let board = Arc::new(StatusBoard::new(&targets));
+        if let Some(history) = &history {
+            match history.records_since(SystemTime::UNIX_EPOCH) {
+                Ok(records) => records.iter().for_each(|record| board.record_history(record)),
+                Err(err) => eprintln!("Failed to read history {}: {}", history.path().display(), err),
+            }
+        }
+        status_page::serve(listener, Arc::clone(&board));
+        board
+    });
+
+    // The dashboard takes over the terminal, so results are not printed under it
+    #[cfg(feature = "tui")]
+    let print = !cli.tui;
+    #[cfg(not(feature = "tui"))]
+    let print = true;
+
+    // Hashes recorded before a restart are the baseline, so changes made meanwhile are reported
+    let mut content = ContentTracker::new();
+    if let (Some(history), true) = (&history, targets.iter().any(|target| target.content.is_some())) {
+        match history.records_since(SystemTime::UNIX_EPOCH) {
+            Ok(records) => records.iter().for_each(|record| content.record_history(record)),
+            Err(err) => eprintln!("Failed to read history {}: {}", history.path().display(), err),
+        }
     }
 
-    drop(task_sender);
-
-    for received in result_receiver {
-        match received.status {
-            Ok(status) => println!("{} - Status: {} - Time: {:?} - Timestamp: {:?}", received.url, status, received.response_time, received.timestamp),
-            Err(err) => println!("{} - Failed: {} - Timestamp: {:?}", received.url, err, received.timestamp),
+    let mut writer = ResultWriter::new(io::stdout(), cli.format);
+    let mut alerter = cli.alerter();
+    let mut last_prune = None;
+    let handle = |received: WebsiteStatus| {
+        let change = content.observe(&received);
+        if print {
+            if let Err(err) = writer.write(&received) {
+                eprintln!("Failed to write result: {}", err);
+            }
+            if let Some(change) = &change {
+                if let Err(err) = writer.write_change(change) {
+                    eprintln!("Failed to write content change: {}", err);
+                }
+            }
         }
-    }
+        if let Some(board) = &status_board {
+            board.record(&received);
+        }
+        if alerter.is_enabled() {
+            alerter.handle(&received);
+        }
+        if let Some(history) = &history {
+            if let Err(err) = history.append(&received) {
+                eprintln!("Failed to record history: {}", err);
+            }
+            if let Some(retention) = cli.retention {
+                if last_prune.is_none_or(|pruned: Instant| pruned.elapsed() >= PRUNE_INTERVAL) {
+                    prune_history(history, retention);
+                    last_prune = Some(Instant::now());
+                }
+            }
+        }
+    };
 
-    for worker in workers {
-        worker.join().expect("Worker thread panicked");
+    #[cfg(feature = "async")]
+    if cli.engine == cli::Engine::Async {
+        run_async(&cli, targets, metrics_listener, handle);
+        return;
     }
 
-    println!("All workers shut down gracefully.");
-}
+    // Reloaded target lists reach the dashboard through this channel
+    let (dashboard_reloads, reloads) = mpsc::channel::<Vec<Target>>();
+    #[cfg(feature = "tui")]
+    let dashboard = cli.tui.then(|| (targets.clone(), reloads));
+    #[cfg(not(feature = "tui"))]
+    drop(reloads);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::sync::mpsc;
-
-    #[test]
-    fn test_check_website_success() {
-        // Mock URL that always succeeds
-        let mock_url = "https://httpstat.us/200";
-        let timeout = Duration::from_secs(DEFAULT_TIMEOUT);
-        let max_retries = MAX_RETRIES;
-
-        let status = check_website(mock_url, timeout, max_retries);
-        assert!(status.status.is_ok());
-        assert_eq!(status.status.unwrap(), 200);
-        assert!(status.response_time > Duration::ZERO);
+    let monitor = Monitor::start(&config, targets);
+    if let Some(listener) = metrics_listener {
+        metrics::serve(listener, monitor.metrics());
     }
 
-    #[test]
-    fn test_check_website_failure() {
-        // Mock URL that will fail
-        let mock_url = "https://invalid.url";
-        let timeout = Duration::from_secs(DEFAULT_TIMEOUT);
-        let max_retries = MAX_RETRIES;
-
-        let status = check_website(mock_url, timeout, max_retries);
-        assert!(status.status.is_err());
-        assert_eq!(status.response_time, Duration::ZERO);
+    // Only a monitor that runs until stopped needs to turn signals into a graceful
+    // shutdown, or can pick up an edited target list
+    if monitor.is_monitoring() {
+        let shutdown = monitor.shutdown_handle();
+        for signal in [SIGINT, SIGTERM] {
+            signal_hook::flag::register(signal, shutdown.flag()).expect("Failed to register signal handler");
+        }
+        if cli.input.as_os_str() != "-" {
+            let board = status_board.clone();
+            reload_targets(cli.input.clone(), config, cli.watch, monitor.reload_handle(), move |targets| {
+                if let Some(board) = &board {
+                    board.set_targets(targets);
+                }
+                let _ = dashboard_reloads.send(targets.to_vec());
+            });
+        }
     }
 
-    #[test]
-    fn test_monitor_worker() {
-        let (task_sender, task_receiver) = mpsc::channel();
-        let (result_sender, result_receiver) = mpsc::channel();
-        let task_receiver = Arc::new(Mutex::new(task_receiver));
-
-        let timeout = Duration::from_secs(DEFAULT_TIMEOUT);
-        let max_retries = MAX_RETRIES;
-
-        // Start a worker thread
-        let worker_thread = thread::spawn({
-            let task_receiver = Arc::clone(&task_receiver);
-            move || monitor_worker(task_receiver, result_sender, timeout, max_retries)
-        });
-
-        // Send a mock task to the worker
-        task_sender.send(MonitorMessage::CheckUrl("https://httpstat.us/200".to_string())).unwrap();
-
-        // Drop the sender to ensure the worker thread finishes
-        drop(task_sender);
-
-        // Collect the result
-        let result = result_receiver.recv().unwrap();
-        assert!(result.status.is_ok());
-        assert_eq!(result.status.unwrap(), 200);
-
-        // Ensure the worker thread shuts down cleanly
-        worker_thread.join().unwrap();
+    #[cfg(feature = "tui")]
+    if let Some((targets, reloads)) = dashboard {
+        if let Err(err) = dashboard::run(monitor, &targets, reloads, handle) {
+            eprintln!("Failed to run the dashboard: {}", err);
+            process::exit(1);
+        }
+        return;
     }
 
-    #[test]
-    fn test_graceful_shutdown() {
-        let (task_sender, task_receiver) = mpsc::channel();
-        let (result_sender, _result_receiver) = mpsc::channel();
-        let task_receiver = Arc::new(Mutex::new(task_receiver));
-
-        let timeout = Duration::from_secs(DEFAULT_TIMEOUT);
-        let max_retries = MAX_RETRIES;
-
-        let worker_thread = thread::spawn({
-            let task_receiver = Arc::clone(&task_receiver);
-            move || monitor_worker(task_receiver, result_sender, timeout, max_retries)
-        });
-
-        // Send shutdown signal
-        task_sender.send(MonitorMessage::Shutdown).unwrap();
-
-        // Ensure the worker thread shuts down cleanly
-        worker_thread.join().unwrap();
-    }
+    monitor.run(handle);
+    eprintln!("All workers shut down gracefully.");
 }
@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::transport::Deadline;
+use crate::{Url, WebsiteStatus};
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+/// A connection is dropped if it has not been answered after this long, however
+/// slowly the client keeps sending.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Most of a request line and headers that is read; longer requests are dropped.
+const MAX_REQUEST_BYTES: u64 = 8 * 1024;
+/// Connections answered at once; any more are closed unanswered.
+const MAX_CONNECTIONS: usize = 16;
+
+#[derive(Default)]
+struct UrlMetrics {
+    name: String,
+    up: bool,
+    last_status: Option<u16>,
+    /// Cumulative counts per bucket, as Prometheus expects.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+    checks: u64,
+    failures: u64,
+    retries: u64,
+    cert_expiry_days: Option<i64>,
+}
+
+/// Check results aggregated for scraping.
+#[derive(Default)]
+pub struct Metrics {
+    urls: Mutex<BTreeMap<Url, UrlMetrics>>,
+    enqueued: AtomicUsize,
+    completed: AtomicUsize,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// Counts a check handed to the worker pool.
+    pub fn enqueued(&self) {
+        self.enqueued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Checks sent to the workers that have not produced a result yet.
+    pub fn queue_depth(&self) -> usize {
+        let completed = self.completed.load(Ordering::Relaxed);
+        self.enqueued.load(Ordering::Relaxed).saturating_sub(completed)
+    }
+
+    /// Counts a queued check that was dropped without running.
+    pub fn discarded(&self) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record(&self, status: &WebsiteStatus) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+
+        let mut urls = self.urls.lock().unwrap();
+        let metrics = urls.entry(status.url.clone()).or_default();
+        metrics.name = status.name.clone();
+        metrics.up = status.verdict.is_pass();
+        metrics.last_status = status.status_code();
+        metrics.checks += 1;
+        metrics.failures += u64::from(!metrics.up);
+        metrics.retries += status.attempt_count().saturating_sub(1) as u64;
+        if let Some(Ok(tls)) = &status.tls {
+            metrics.cert_expiry_days = tls.days_until_expiry(status.timestamp);
+        }
+
+        let latency = status.response_time.as_secs_f64();
+        metrics.latency_sum += latency;
+        for (bucket, le) in metrics.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if latency <= le {
+                *bucket += 1;
+            }
+        }
+    }
+
+    /// Renders everything in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let urls = self.urls.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP website_up Whether the last check met the success criteria.\n");
+        out.push_str("# TYPE website_up gauge\n");
+        for (url, metrics) in urls.iter() {
+            let _ = writeln!(out, "website_up{{{}}} {}", labels(url, metrics), u8::from(metrics.up));
+        }
+
+        out.push_str("# HELP website_last_status_code HTTP status code of the last response.\n");
+        out.push_str("# TYPE website_last_status_code gauge\n");
+        for (url, metrics) in urls.iter() {
+            if let Some(code) = metrics.last_status {
+                let _ = writeln!(out, "website_last_status_code{{{}}} {}", labels(url, metrics), code);
+            }
+        }
+
+        out.push_str("# HELP website_response_time_seconds Latency of the final attempt of each check.\n");
+        out.push_str("# TYPE website_response_time_seconds histogram\n");
+        for (url, metrics) in urls.iter() {
+            let labels = labels(url, metrics);
+            for (count, le) in metrics.buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(out, "website_response_time_seconds_bucket{{{},le=\"{}\"}} {}", labels, le, count);
+            }
+            let _ = writeln!(out, "website_response_time_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, metrics.checks);
+            let _ = writeln!(out, "website_response_time_seconds_sum{{{}}} {}", labels, metrics.latency_sum);
+            let _ = writeln!(out, "website_response_time_seconds_count{{{}}} {}", labels, metrics.checks);
+        }
+
+        out.push_str("# HELP website_cert_expiry_days Days until the first certificate in the chain expires.\n");
+        out.push_str("# TYPE website_cert_expiry_days gauge\n");
+        for (url, metrics) in urls.iter() {
+            if let Some(days) = metrics.cert_expiry_days {
+                let _ = writeln!(out, "website_cert_expiry_days{{{}}} {}", labels(url, metrics), days);
+            }
+        }
+
+        counter(&mut out, &urls, "website_checks_total", "Checks completed.", |metrics| metrics.checks);
+        counter(&mut out, &urls, "website_failures_total", "Checks that did not meet the success criteria.", |metrics| metrics.failures);
+        counter(&mut out, &urls, "website_retries_total", "Attempts made after the first one.", |metrics| metrics.retries);
+
+        out.push_str("# HELP website_checker_queue_depth Checks waiting for or running on a worker.\n");
+        out.push_str("# TYPE website_checker_queue_depth gauge\n");
+        let _ = writeln!(out, "website_checker_queue_depth {}", self.queue_depth());
+        out
+    }
+}
+
+fn labels(url: &str, metrics: &UrlMetrics) -> String {
+    format!(r#"url="{}",name="{}""#, escape(url), escape(&metrics.name))
+}
+
+fn counter(out: &mut String, urls: &BTreeMap<Url, UrlMetrics>, name: &str, help: &str, value: fn(&UrlMetrics) -> u64) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+    for (url, metrics) in urls {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels(url, metrics), value(metrics));
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', r"\\").replace('"', "\\\"").replace('\n', r"\n")
+}
+
+/// Serves `GET /metrics` on `listener` from a background thread.
+pub fn serve(listener: TcpListener, metrics: Arc<Metrics>) {
+    serve_routes(listener, "Metrics", move |path| match path {
+        "/metrics" => Some(Reply::ok("text/plain; version=0.0.4", metrics.render())),
+        _ => None,
+    });
+}
+
+/// A successful response to a `GET`.
+pub(crate) struct Reply {
+    content_type: &'static str,
+    body: String,
+}
+
+impl Reply {
+    pub(crate) fn ok(content_type: &'static str, body: String) -> Self {
+        Reply { content_type, body }
+    }
+}
+
+/// Answers `GET`s on `listener` from a background thread, each connection on a thread
+/// of its own so a slow client holds up no one else. `route` gets the path without
+/// its query string; `None` is a 404. Failed requests are logged with `label`.
+pub(crate) fn serve_routes(listener: TcpListener, label: &'static str, route: impl Fn(&str) -> Option<Reply> + Send + Sync + 'static) {
+    let route = Arc::new(route);
+    let active = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("{} request failed: {}", label, err);
+                    continue;
+                }
+            };
+            if active.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                active.fetch_sub(1, Ordering::Relaxed);
+                continue;
+            }
+
+            let (route, active) = (Arc::clone(&route), Arc::clone(&active));
+            thread::spawn(move || {
+                if let Err(err) = respond(stream, route.as_ref()) {
+                    eprintln!("{} request failed: {}", label, err);
+                }
+                active.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+    });
+}
+
+fn respond(stream: TcpStream, route: &impl Fn(&str) -> Option<Reply>) -> io::Result<()> {
+    let socket = stream.try_clone()?;
+    let mut stream = Deadline::new(stream, socket, Instant::now() + REQUEST_TIMEOUT);
+    let mut reader = BufReader::new((&mut stream).take(MAX_REQUEST_BYTES));
+    let mut read_line = |line: &mut String| {
+        line.clear();
+        match reader.read_line(line)? {
+            _ if !line.ends_with('\n') => Err(io::Error::new(io::ErrorKind::InvalidData, "request is too long or cut short")),
+            len => Ok(len),
+        }
+    };
+
+    let mut request_line = String::new();
+    read_line(&mut request_line)?;
+    // The headers are not needed, but have to be read before replying
+    let mut line = String::new();
+    while read_line(&mut line)? > 2 {}
+
+    let mut parts = request_line.split_whitespace();
+    let (status, Reply { content_type, body }) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => match route(target.split('?').next().unwrap_or(target)) {
+            Some(reply) => ("200 OK", reply),
+            None => ("404 Not Found", Reply::ok("text/plain", "Not Found\n".to_string())),
+        },
+        _ => ("405 Method Not Allowed", Reply::ok("text/plain", "Method Not Allowed\n".to_string())),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::Attempt;
+
+    fn status(up: bool, latency_ms: u64, attempts: usize) -> WebsiteStatus {
+        let code = if up { 200 } else { 503 };
+        let latency = Duration::from_millis(latency_ms);
+        WebsiteStatus {
+            name: "Home \"page\"".to_string(),
+            response_time: latency,
+            total_time: latency,
+            attempts: (0..attempts).map(|_| Attempt { latency, outcome: Ok(Some(code)) }).collect(),
+            ..testing::status("https://example.com", Ok(code))
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.record(&status(true, 80, 1));
+        metrics.record(&status(false, 300, 3));
+
+        let text = metrics.render();
+        let labels = r#"url="https://example.com",name="Home \"page\"""#;
+        assert!(text.contains(&format!("website_up{{{}}} 0\n", labels)));
+        assert!(text.contains(&format!("website_last_status_code{{{}}} 503\n", labels)));
+        assert!(text.contains(&format!("website_response_time_seconds_bucket{{{},le=\"0.1\"}} 1\n", labels)));
+        assert!(text.contains(&format!("website_response_time_seconds_bucket{{{},le=\"0.5\"}} 2\n", labels)));
+        assert!(text.contains(&format!("website_response_time_seconds_bucket{{{},le=\"+Inf\"}} 2\n", labels)));
+        assert!(text.contains(&format!("website_response_time_seconds_sum{{{}}} 0.38\n", labels)));
+        assert!(text.contains(&format!("website_checks_total{{{}}} 2\n", labels)));
+        assert!(text.contains(&format!("website_failures_total{{{}}} 1\n", labels)));
+        assert!(text.contains(&format!("website_retries_total{{{}}} 2\n", labels)));
+        assert!(text.contains("# TYPE website_response_time_seconds histogram\n"));
+        assert!(!text.contains("website_cert_expiry_days{"));
+    }
+
+    #[test]
+    fn test_queue_depth() {
+        let metrics = Metrics::new();
+        metrics.enqueued();
+        metrics.enqueued();
+        assert_eq!(metrics.queue_depth(), 2);
+        metrics.record(&status(true, 10, 1));
+        assert_eq!(metrics.queue_depth(), 1);
+        assert!(metrics.render().ends_with("website_checker_queue_depth 1\n"));
+    }
+
+    #[test]
+    fn test_serve() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record(&status(true, 10, 1));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        serve(listener, Arc::clone(&metrics));
+
+        let response = ureq::get(&format!("{}/metrics", base)).call().unwrap();
+        assert_eq!(response.content_type(), "text/plain");
+        assert!(response.into_string().unwrap().contains("website_checks_total"));
+
+        match ureq::get(&format!("{}/other", base)).call() {
+            Err(ureq::Error::Status(code, _)) => assert_eq!(code, 404),
+            _ => panic!("expected a 404"),
+        }
+    }
+
+    #[test]
+    fn test_serve_survives_slow_and_oversized_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        serve(listener, Arc::new(Metrics::new()));
+
+        // A client that stops halfway through its request does not hold up the next
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        stalled.write_all(b"GET /metrics HTTP/1.1\r\n").unwrap();
+        let start = Instant::now();
+        assert!(ureq::get(&format!("http://{}/metrics", addr)).call().is_ok());
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // A request past the limit is dropped unanswered
+        let mut oversized = TcpStream::connect(addr).unwrap();
+        let header = format!("GET /metrics HTTP/1.1\r\nX-Padding: {}\r\n\r\n", "a".repeat(MAX_REQUEST_BYTES as usize));
+        let _ = oversized.write_all(header.as_bytes());
+        oversized.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let mut response = Vec::new();
+        let _ = oversized.read_to_end(&mut response);
+        assert!(response.is_empty());
+    }
+}
@@ -0,0 +1,448 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::http::HttpClient;
+use crate::metrics::Metrics;
+use crate::pool::{ShutdownMode, WorkerPool};
+use crate::ratelimit::HostLimiter;
+use crate::reload;
+use crate::schedule::Scheduler;
+use crate::transport::TimedClient;
+use crate::{Config, Target, WebsiteStatus, DEFAULT_INTERVAL};
+
+/// Upper bound on how long the scheduler sleeps before re-checking the shutdown flag.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(200);
+
+/// Hands a check to the worker pool, counting it towards the queue depth.
+fn enqueue(pool: &WorkerPool, target: Arc<Target>, metrics: &Metrics) -> bool {
+    metrics.enqueued();
+    pool.submit(target)
+}
+
+/// How a monitor in monitoring mode schedules its targets.
+struct Schedule {
+    /// For targets without an interval of their own.
+    default_interval: Duration,
+    /// New target lists sent through a `ReloadHandle`.
+    reloads: Receiver<Vec<Target>>,
+}
+
+/// Enqueues every target once, or on its interval until shutdown, then stops the pool.
+fn dispatch(
+    targets: Vec<Arc<Target>>,
+    schedule: Option<Schedule>,
+    pool: WorkerPool,
+    shutdown: ShutdownHandle,
+    shutdown_mode: ShutdownMode,
+    shutdown_timeout: Option<Duration>,
+    metrics: Arc<Metrics>,
+) {
+    if let Some(Schedule { default_interval, reloads }) = schedule {
+        let mut scheduler = Scheduler::new();
+        let start = Instant::now();
+        for target in &targets {
+            let interval = target.interval.unwrap_or(default_interval);
+            scheduler.add(Arc::clone(target), interval, start);
+        }
+
+        let mut targets = targets;
+        while !shutdown.is_shutdown() {
+            let now = Instant::now();
+            // Only the latest of several queued lists matters
+            if let Some(new) = reloads.try_iter().last() {
+                targets = reschedule(&mut scheduler, targets, new, default_interval, now);
+            }
+            for target in scheduler.due(now) {
+                // A target whose last check has not finished is not piled up behind
+                // itself; it comes round again on its next slot
+                if !pool.is_pending(&target) {
+                    enqueue(&pool, target, &metrics);
+                }
+            }
+
+            let wake = scheduler.next_due().map_or(SHUTDOWN_POLL, |due| due.saturating_duration_since(now));
+            thread::sleep(wake.min(SHUTDOWN_POLL));
+        }
+
+        eprintln!("Shutdown requested, waiting for in-flight checks...");
+    } else {
+        for target in targets {
+            enqueue(&pool, target, &metrics);
+        }
+    }
+
+    // A shutdown can also arrive while a one-off run is still draining, so the
+    // mode and timeout apply from whenever it is requested
+    let mut requested = None;
+    let abandoned = pool.stop(|| {
+        if requested.is_none() && shutdown.is_shutdown() {
+            if shutdown_mode == ShutdownMode::Abort {
+                shutdown.abort();
+            }
+            requested = Some(Instant::now());
+        }
+        requested.zip(shutdown_timeout).map(|(at, timeout)| at + timeout)
+    });
+    if abandoned > 0 {
+        eprintln!("Gave up waiting for {} checks after the shutdown timeout", abandoned);
+    }
+}
+
+/// Moves the schedule from `current` to the `new` targets. Unchanged targets keep
+/// their slots; added and changed ones are due at `now`. Checks already queued or
+/// running for removed targets still complete.
+fn reschedule(
+    scheduler: &mut Scheduler<Arc<Target>>,
+    current: Vec<Arc<Target>>,
+    new: Vec<Target>,
+    default_interval: Duration,
+    now: Instant,
+) -> Vec<Arc<Target>> {
+    let new = new
+        .into_iter()
+        .filter(|target| match target.validate() {
+            Ok(()) => true,
+            Err(err) => {
+                eprintln!("Skipping invalid target {}: {}", target.request.url, err);
+                false
+            }
+        })
+        .collect();
+    let diff = reload::diff(&current, new);
+    eprintln!("Reloaded targets: {}", diff);
+
+    let stale: Vec<&Arc<Target>> = diff.removed.iter().chain(diff.changed.iter().map(|(old, _)| old)).collect();
+    let is_stale = |target: &Arc<Target>| stale.iter().any(|old| Arc::ptr_eq(old, target));
+    scheduler.retain(|target| !is_stale(target));
+    let mut targets: Vec<Arc<Target>> = current.iter().filter(|target| !is_stale(target)).cloned().collect();
+
+    for target in diff.added.into_iter().chain(diff.changed.into_iter().map(|(_, new)| new)) {
+        let target = Arc::new(target);
+        scheduler.add(Arc::clone(&target), target.interval.unwrap_or(default_interval), now);
+        targets.push(target);
+    }
+    targets
+}
+
+/// Replaces the targets of a `Monitor` in monitoring mode without restarting its
+/// workers. Cheap to clone and safe to use from other threads.
+#[derive(Clone, Debug)]
+pub struct ReloadHandle {
+    sender: Sender<Vec<Target>>,
+}
+
+impl ReloadHandle {
+    /// Hands `targets` to the scheduler, which diffs them against the current list
+    /// and logs what changed. Returns `false` once the monitor has stopped scheduling,
+    /// or if it checks each target once.
+    pub fn reload(&self, targets: Vec<Target>) -> bool {
+        self.sender.send(targets).is_ok()
+    }
+}
+
+/// Asks a running `Monitor` to stop scheduling checks. Cheap to clone and safe to
+/// use from other threads or a signal handler.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownHandle {
+    flag: Arc<AtomicBool>,
+    abort: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    /// Stops scheduling; queued checks are drained or dropped as the config's
+    /// `shutdown_mode` says.
+    pub fn shutdown(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    /// Stops scheduling, drops queued checks and stops in-flight checks from retrying.
+    pub fn abort(&self) {
+        self.abort.store(true, Ordering::Relaxed);
+        self.shutdown();
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+
+    /// The underlying flag, for `signal_hook::flag::register` and similar.
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.flag)
+    }
+}
+
+/// Checks targets on a pool of worker threads and delivers the results as they complete.
+///
+/// Without an interval every target is checked once and the results end when all
+/// checks are done. With one, targets are re-checked until `ShutdownHandle::shutdown`
+/// is called; queued checks are then drained or dropped per `Config::shutdown_mode`,
+/// and the results end once the workers exit or `Config::shutdown_timeout` passes.
+/// Dropping the monitor also requests a shutdown. Targets that `Target::validate`
+/// rejects are never checked: each gets one failed result, or is skipped with a
+/// log line when it arrives through a reload.
+pub struct Monitor {
+    results: Receiver<WebsiteStatus>,
+    shutdown: ShutdownHandle,
+    metrics: Arc<Metrics>,
+    monitoring: bool,
+    reload: ReloadHandle,
+    dispatcher: Option<JoinHandle<()>>,
+}
+
+impl Monitor {
+    /// Starts checking `targets` over the network with `config.worker_threads` workers,
+    /// timing each phase of every request.
+    pub fn start(config: &Config, targets: Vec<Target>) -> Self {
+        Monitor::with_client(config, targets, Arc::new(TimedClient::default()))
+    }
+
+    /// Like `start`, but makes requests through `client`.
+    pub fn with_client(config: &Config, targets: Vec<Target>, client: Arc<dyn HttpClient>) -> Self {
+        let shutdown = ShutdownHandle::default();
+        let metrics = Arc::new(Metrics::new());
+        let (result_sender, results) = mpsc::channel();
+
+        // Targets built in code have not been through `parse_targets`, so one that
+        // fails its checks gets a single failed result instead of being checked
+        let mut valid = Vec::new();
+        for target in targets {
+            match target.validate() {
+                Ok(()) => valid.push(target),
+                Err(err) => {
+                    let _ = result_sender.send(crate::rejected(&target, &err));
+                }
+            }
+        }
+        let targets = valid;
+        let limiter = Arc::new(HostLimiter::new(config.host_limits.clone()));
+        let pool = WorkerPool::with_cancel(
+            config.worker_threads,
+            client,
+            Arc::clone(&metrics),
+            result_sender,
+            Arc::clone(&shutdown.abort),
+            limiter,
+        );
+
+        // Monitoring mode is on when the config or any target has an interval
+        let monitoring = config.interval.is_some() || targets.iter().any(|target| target.interval.is_some());
+        let (sender, reloads) = mpsc::channel();
+        let schedule = monitoring.then(|| Schedule {
+            default_interval: config.interval.unwrap_or(Duration::from_secs(DEFAULT_INTERVAL)),
+            reloads,
+        });
+        let targets = targets.into_iter().map(Arc::new).collect();
+        let (shutdown_mode, shutdown_timeout) = (config.shutdown_mode, config.shutdown_timeout);
+        let dispatcher = thread::spawn({
+            let shutdown = shutdown.clone();
+            let metrics = Arc::clone(&metrics);
+            move || dispatch(targets, schedule, pool, shutdown, shutdown_mode, shutdown_timeout, metrics)
+        });
+
+        Monitor { results, shutdown, metrics, monitoring, reload: ReloadHandle { sender }, dispatcher: Some(dispatcher) }
+    }
+
+    /// Whether targets are re-checked until shutdown, rather than checked once.
+    pub fn is_monitoring(&self) -> bool {
+        self.monitoring
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    pub fn reload_handle(&self) -> ReloadHandle {
+        self.reload.clone()
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Blocks for each result in turn; the iterator ends once every worker has stopped
+    /// or been given up on.
+    pub fn results(&self) -> mpsc::Iter<'_, WebsiteStatus> {
+        self.results.iter()
+    }
+
+    /// The next result if one is ready, without blocking. `Disconnected` means no
+    /// more results will arrive.
+    pub fn try_result(&self) -> Result<WebsiteStatus, TryRecvError> {
+        self.results.try_recv()
+    }
+
+    /// Calls `on_result` for every result, then waits for the workers to exit.
+    pub fn run(self, mut on_result: impl FnMut(WebsiteStatus)) {
+        for status in self.results() {
+            on_result(status);
+        }
+        self.join();
+    }
+
+    /// Waits for the scheduler to exit, which happens once the workers have exited
+    /// or been given up on.
+    pub fn join(mut self) {
+        if let Some(dispatcher) = self.dispatcher.take() {
+            dispatcher.join().expect("Monitor thread panicked");
+        }
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.shutdown.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeClient;
+
+    fn targets(config: &Config, paths: &[&str]) -> Vec<Target> {
+        paths
+            .iter()
+            .map(|path| Target::new(&format!("https://example.com/{}", path), config))
+            .collect()
+    }
+
+    #[test]
+    fn test_monitor_checks_each_target_once() {
+        let config = Config { worker_threads: 2, ..Config::default() };
+        let client = Arc::new(FakeClient::new(vec![FakeClient::status(200); 3]));
+        let monitor = Monitor::with_client(&config, targets(&config, &["a", "b", "c"]), client);
+        assert!(!monitor.is_monitoring());
+        assert!(!monitor.reload_handle().reload(Vec::new()));
+
+        let mut urls = Vec::new();
+        monitor.run(|status| urls.push(status.url));
+        urls.sort();
+        assert_eq!(urls, vec!["https://example.com/a", "https://example.com/b", "https://example.com/c"]);
+    }
+
+    #[test]
+    fn test_monitor_reports_invalid_targets() {
+        let config = Config::default();
+        let client = Arc::new(FakeClient::new(vec![FakeClient::status(200)]));
+        let mut targets = targets(&config, &["a"]);
+        targets.push(Target::new("tcp://db.internal", &config));
+        let monitor = Monitor::with_client(&config, targets, client.clone());
+
+        let mut results: Vec<WebsiteStatus> = monitor.results().collect();
+        results.sort_by(|a, b| a.url.cmp(&b.url));
+        assert_eq!(results[0].status, Ok(Some(200)));
+        assert_eq!(results[1].url, "tcp://db.internal");
+        assert!(results[1].status.as_ref().unwrap_err().starts_with("Invalid target: "));
+        assert!(!results[1].verdict.is_pass());
+        assert_eq!(client.requests().len(), 1);
+        monitor.join();
+    }
+
+    #[test]
+    fn test_monitor_repeats_until_shutdown() {
+        let config = Config { interval: Some(Duration::from_millis(20)), ..Config::default() };
+        let client = Arc::new(FakeClient::new(vec![FakeClient::status(200); 100]));
+        let monitor = Monitor::with_client(&config, targets(&config, &["a"]), client);
+        assert!(monitor.is_monitoring());
+
+        let shutdown = monitor.shutdown_handle();
+        let mut seen = 0;
+        for status in monitor.results() {
+            assert_eq!(status.status, Ok(Some(200)));
+            seen += 1;
+            if seen == 3 {
+                shutdown.shutdown();
+            }
+        }
+
+        // The results end once the scheduler stops and the queue drains
+        assert!(seen >= 3);
+        assert_eq!(monitor.metrics().queue_depth(), 0);
+        monitor.join();
+    }
+
+    #[test]
+    fn test_slow_checks_are_not_piled_up() {
+        let config = Config { worker_threads: 4, interval: Some(Duration::from_millis(20)), ..Config::default() };
+        let client = Arc::new(FakeClient::new(vec![FakeClient::status(200); 100]).with_delay(Duration::from_millis(100)));
+        let monitor = Monitor::with_client(&config, targets(&config, &["slow"]), client.clone());
+
+        // Each check takes five intervals, but the next only starts once it is done
+        thread::sleep(Duration::from_millis(350));
+        monitor.shutdown_handle().shutdown();
+        let seen = monitor.results().count();
+        monitor.join();
+        assert!((2..=4).contains(&seen), "{}", seen);
+        assert_eq!(client.requests().len(), seen);
+    }
+
+    #[test]
+    fn test_reload_swaps_targets() {
+        let config = Config { interval: Some(Duration::from_millis(20)), ..Config::default() };
+        let client = Arc::new(FakeClient::new(vec![FakeClient::status(200); 100]));
+        let monitor = Monitor::with_client(&config, targets(&config, &["a"]), client);
+        let reload = monitor.reload_handle();
+        let shutdown = monitor.shutdown_handle();
+
+        let mut urls = Vec::new();
+        for status in monitor.results() {
+            if urls.is_empty() {
+                let mut new = targets(&config, &["b"]);
+                new.push(Target::new("tcp://db.internal", &config));
+                assert!(reload.reload(new));
+            }
+            urls.push(status.url);
+            if urls.iter().filter(|url| url.ends_with("/b")).count() == 3 {
+                shutdown.shutdown();
+            }
+        }
+        monitor.join();
+
+        // At most one check of the removed target was already queued when the new list arrived
+        let first_b = urls.iter().position(|url| url.ends_with("/b")).unwrap();
+        assert!(urls[first_b..].iter().filter(|url| url.ends_with("/a")).count() <= 1);
+        // The invalid target in the new list was skipped rather than checked
+        assert!(urls.iter().all(|url| url.starts_with("https://")));
+        assert!(!reload.reload(Vec::new()));
+    }
+
+    #[test]
+    fn test_abort_during_one_off_run() {
+        let config = Config { worker_threads: 1, ..Config::default() };
+        let client = Arc::new(FakeClient::new(vec![FakeClient::status(200); 20]).with_delay(Duration::from_millis(20)));
+        let paths: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+        let monitor = Monitor::with_client(&config, targets(&config, &paths), client);
+
+        let shutdown = monitor.shutdown_handle();
+        let mut seen = 0;
+        for _ in monitor.results() {
+            seen += 1;
+            shutdown.abort();
+        }
+        assert!(seen < 20);
+        assert_eq!(monitor.metrics().queue_depth(), 0);
+        monitor.join();
+    }
+
+    #[test]
+    fn test_shutdown_timeout_ends_results() {
+        let config = Config {
+            interval: Some(Duration::from_secs(60)),
+            shutdown_timeout: Some(Duration::from_millis(100)),
+            ..Config::default()
+        };
+        let client = Arc::new(FakeClient::new(vec![FakeClient::status(200)]).with_delay(Duration::from_secs(3)));
+        let monitor = Monitor::with_client(&config, targets(&config, &["stuck"]), client);
+
+        thread::sleep(Duration::from_millis(50));
+        let start = Instant::now();
+        monitor.shutdown_handle().shutdown();
+        assert_eq!(monitor.results().count(), 0);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        monitor.join();
+    }
+}
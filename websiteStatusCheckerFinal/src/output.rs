@@ -0,0 +1,564 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::content::ContentChange;
+use crate::history::UrlSummary;
+use crate::http::Timings;
+use crate::WebsiteStatus;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line (JSON Lines)
+    Json,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+/// Flat, serializable view of a `WebsiteStatus`.
+///
+/// This is the stable schema for the JSON Lines and CSV outputs and the history
+/// file: fields may be added at the end, but existing names and meanings do not change.
+/// New fields need a serde default so older history still parses.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct StatusRecord {
+    pub url: String,
+    /// RFC 3339 timestamp in UTC, with millisecond precision.
+    pub timestamp: String,
+    pub ok: bool,
+    pub status_code: Option<u16>,
+    pub response_time_ms: u64,
+    pub error: Option<String>,
+    /// Time across all attempts, including the backoff between them.
+    pub total_time_ms: u64,
+    pub attempts: usize,
+    pub name: String,
+    #[serde(default)]
+    pub tls_version: Option<String>,
+    #[serde(default)]
+    pub tls_cipher: Option<String>,
+    /// Subject of the server's own certificate.
+    #[serde(default)]
+    pub cert_subject: Option<String>,
+    #[serde(default)]
+    pub cert_issuer: Option<String>,
+    /// The certificate's DNS names and IP addresses, separated by spaces.
+    #[serde(default)]
+    pub cert_names: Option<String>,
+    /// Days until the first certificate in the chain expires; negative once it has.
+    #[serde(default)]
+    pub cert_expires_in_days: Option<i64>,
+    #[serde(default)]
+    pub tls_warning: Option<String>,
+    /// Phase timings of the final attempt; missing for phases that did not happen or were not measured.
+    #[serde(default)]
+    pub dns_ms: Option<u64>,
+    #[serde(default)]
+    pub connect_ms: Option<u64>,
+    #[serde(default)]
+    pub tls_ms: Option<u64>,
+    #[serde(default)]
+    pub ttfb_ms: Option<u64>,
+    #[serde(default)]
+    pub download_ms: Option<u64>,
+    /// Time spent on redirects before the final response.
+    #[serde(default)]
+    pub redirect_ms: Option<u64>,
+    /// Each redirect as `status url -> location`, separated by `; `.
+    #[serde(default)]
+    pub redirect_chain: Option<String>,
+    /// What a probe found, such as the addresses a name resolved to.
+    #[serde(default)]
+    pub detail: Option<String>,
+    /// SHA-256 of the normalized response body, for targets that hash it.
+    #[serde(default)]
+    pub content_hash: Option<String>,
+}
+
+impl From<&WebsiteStatus> for StatusRecord {
+    fn from(status: &WebsiteStatus) -> Self {
+        let tls = status.tls.as_ref().and_then(|inspection| inspection.as_ref().ok());
+        let leaf = tls.and_then(|tls| tls.leaf());
+
+        StatusRecord {
+            url: status.url.clone(),
+            timestamp: humantime::format_rfc3339_millis(status.timestamp).to_string(),
+            ok: status.verdict.is_pass(),
+            status_code: status.status_code(),
+            response_time_ms: status.response_time.as_millis() as u64,
+            error: status.verdict.failure().map(|reason| reason.to_string()),
+            total_time_ms: status.total_time.as_millis() as u64,
+            attempts: status.attempt_count(),
+            name: status.name.clone(),
+            tls_version: tls.map(|tls| tls.version.clone()),
+            tls_cipher: tls.map(|tls| tls.cipher.clone()),
+            cert_subject: leaf.map(|cert| cert.subject.clone()),
+            cert_issuer: leaf.map(|cert| cert.issuer.clone()),
+            cert_names: leaf.map(|cert| cert.names.join(" ")),
+            cert_expires_in_days: tls.and_then(|tls| tls.days_until_expiry(status.timestamp)),
+            tls_warning: status.tls_warning.clone(),
+            dns_ms: millis(status.timings.dns),
+            connect_ms: millis(status.timings.connect),
+            tls_ms: millis(status.timings.tls),
+            ttfb_ms: millis(status.timings.ttfb),
+            download_ms: millis(status.timings.download),
+            redirect_ms: millis(status.timings.redirect),
+            redirect_chain: redirect_chain(status),
+            detail: status.detail.clone(),
+            content_hash: status.content_hash.clone(),
+        }
+    }
+}
+
+fn millis(duration: Option<Duration>) -> Option<u64> {
+    duration.map(|duration| duration.as_millis() as u64)
+}
+
+fn redirect_chain(status: &WebsiteStatus) -> Option<String> {
+    if status.redirects.is_empty() {
+        return None;
+    }
+    Some(status.redirects.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))
+}
+
+/// The measured phases of `timings`, as in `DNS 1.2ms, connect 310µs`.
+fn phases(timings: &Timings) -> String {
+    let phases = [
+        ("redirects", timings.redirect),
+        ("DNS", timings.dns),
+        ("connect", timings.connect),
+        ("TLS", timings.tls),
+        ("TTFB", timings.ttfb),
+        ("download", timings.download),
+    ];
+    phases
+        .iter()
+        .filter_map(|(phase, duration)| Some(format!("{} {:?}", phase, (*duration)?)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Writes results to an output stream in the selected format.
+pub struct ResultWriter<W: Write> {
+    format: OutputFormat,
+    out: ResultSink<W>,
+}
+
+enum ResultSink<W: Write> {
+    Plain(W),
+    Csv(Box<csv::Writer<W>>),
+}
+
+impl<W: Write> ResultWriter<W> {
+    pub fn new(out: W, format: OutputFormat) -> Self {
+        let out = match format {
+            OutputFormat::Csv => ResultSink::Csv(Box::new(csv::Writer::from_writer(out))),
+            OutputFormat::Text | OutputFormat::Json => ResultSink::Plain(out),
+        };
+
+        ResultWriter { format, out }
+    }
+
+    /// Writes a single result and flushes it, so consumers see it immediately.
+    pub fn write(&mut self, status: &WebsiteStatus) -> io::Result<()> {
+        match (&mut self.out, self.format) {
+            (ResultSink::Plain(out), OutputFormat::Text) => {
+                let outcome = match (status.verdict.failure(), &status.status, &status.detail) {
+                    (None, Ok(_), Some(detail)) => format!("OK: {}", detail),
+                    (None, Ok(Some(code)), None) => format!("Status: {}", code),
+                    (None, Ok(None), None) => "OK".to_string(),
+                    (Some(reason), Ok(_), Some(detail)) => format!("Failed: {} ({})", reason, detail),
+                    (Some(reason), Ok(Some(code)), None) => format!("Failed: {} (status {})", reason, code),
+                    (Some(reason), Ok(None), None) => format!("Failed: {}", reason),
+                    (_, Err(err), _) => format!("Failed: {}", err),
+                };
+                let label = if status.name == status.url {
+                    status.url.clone()
+                } else {
+                    format!("{} ({})", status.name, status.url)
+                };
+                write!(out, "{} - {} - Time: {:?} - Attempts: {} - Timestamp: {:?}", label, outcome, status.response_time, status.attempt_count(), status.timestamp)?;
+                if let Some(chain) = redirect_chain(status) {
+                    write!(out, " - Redirects: {}", chain)?;
+                }
+                let phases = phases(&status.timings);
+                if !phases.is_empty() {
+                    write!(out, " - Timings: {}", phases)?;
+                }
+                if let Some(Ok(tls)) = &status.tls {
+                    write!(out, " - TLS: {} {}", tls.version, tls.cipher)?;
+                    if let Some(days) = tls.days_until_expiry(status.timestamp) {
+                        write!(out, ", certificate expires in {} days", days)?;
+                    }
+                }
+                if let Some(warning) = &status.tls_warning {
+                    write!(out, " - Warning: {}", warning)?;
+                }
+                writeln!(out)?;
+                out.flush()
+            }
+            (ResultSink::Plain(out), _) => {
+                serde_json::to_writer(&mut *out, &StatusRecord::from(status))?;
+                writeln!(out)?;
+                out.flush()
+            }
+            (ResultSink::Csv(out), _) => {
+                out.serialize(StatusRecord::from(status))?;
+                out.flush()
+            }
+        }
+    }
+
+    /// Writes a content change, as a line of text or a JSON object with `"event": "content_change"`.
+    /// CSV rows all share the `StatusRecord` columns, so there the change only shows
+    /// as a new `content_hash`.
+    pub fn write_change(&mut self, change: &ContentChange) -> io::Result<()> {
+        match (&mut self.out, self.format) {
+            (ResultSink::Plain(out), OutputFormat::Text) => {
+                let label = if change.name == change.url {
+                    change.url.clone()
+                } else {
+                    format!("{} ({})", change.name, change.url)
+                };
+                let short = |hash: &str| hash.chars().take(12).collect::<String>();
+                writeln!(
+                    out,
+                    "{} - Content changed: {} -> {} - Timestamp: {}",
+                    label,
+                    short(&change.previous_hash),
+                    short(&change.hash),
+                    change.timestamp
+                )?;
+                out.flush()
+            }
+            (ResultSink::Plain(out), _) => {
+                serde_json::to_writer(&mut *out, change)?;
+                writeln!(out)?;
+                out.flush()
+            }
+            (ResultSink::Csv(_), _) => Ok(()),
+        }
+    }
+}
+
+/// Writes a history report, one line or row per URL.
+pub fn write_summaries<W: Write>(out: W, format: OutputFormat, summaries: &[UrlSummary]) -> io::Result<()> {
+    let millis = |value: Option<u64>| value.map_or("-".to_string(), |ms| format!("{} ms", ms));
+
+    match format {
+        OutputFormat::Text => {
+            let mut out = out;
+            for summary in summaries {
+                writeln!(
+                    out,
+                    "{} - Uptime: {:.2}% - Checks: {} - Failures: {} - p50: {} - p95: {} - p99: {}",
+                    summary.url,
+                    summary.uptime_percent,
+                    summary.checks,
+                    summary.failures,
+                    millis(summary.p50_ms),
+                    millis(summary.p95_ms),
+                    millis(summary.p99_ms)
+                )?;
+            }
+            out.flush()
+        }
+        OutputFormat::Json => {
+            let mut out = out;
+            for summary in summaries {
+                serde_json::to_writer(&mut out, summary)?;
+                writeln!(out)?;
+            }
+            out.flush()
+        }
+        OutputFormat::Csv => {
+            let mut out = csv::Writer::from_writer(out);
+            for summary in summaries {
+                out.serialize(summary)?;
+            }
+            out.flush()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::criteria::{FailureReason, Verdict};
+    use crate::redirect::Redirect;
+    use crate::testing;
+    use crate::Attempt;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn sample(status: Result<u16, String>) -> WebsiteStatus {
+        let outcome = status.as_ref().copied().map_err(String::as_str);
+        WebsiteStatus {
+            response_time: Duration::from_micros(123_456),
+            total_time: Duration::from_micros(623_456),
+            attempts: vec![
+                Attempt { latency: Duration::from_millis(250), outcome: Err("timed out".to_string()) },
+                Attempt { latency: Duration::from_micros(123_456), outcome: status.clone().map(Some) },
+            ],
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_700_000_000_250),
+            ..testing::status("https://example.com", outcome)
+        }
+    }
+
+    fn render(format: OutputFormat, statuses: &[WebsiteStatus]) -> String {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = ResultWriter::new(&mut buffer, format);
+            for status in statuses {
+                writer.write(status).unwrap();
+            }
+        }
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_record_fields() {
+        let record = StatusRecord::from(&sample(Ok(200)));
+        assert_eq!(record.timestamp, "2023-11-14T22:13:20.250Z");
+        assert_eq!(record.response_time_ms, 123);
+        assert_eq!(record.total_time_ms, 623);
+        assert_eq!(record.attempts, 2);
+        assert_eq!(record.status_code, Some(200));
+        assert!(record.ok);
+        assert!(record.error.is_none());
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let output = render(OutputFormat::Json, &[sample(Ok(200)), sample(Err("timed out".to_string()))]);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            r#"{"url":"https://example.com","timestamp":"2023-11-14T22:13:20.250Z","ok":true,"status_code":200,"response_time_ms":123,"error":null,"total_time_ms":623,"attempts":2,"name":"https://example.com","tls_version":null,"tls_cipher":null,"cert_subject":null,"cert_issuer":null,"cert_names":null,"cert_expires_in_days":null,"tls_warning":null,"dns_ms":null,"connect_ms":null,"tls_ms":null,"ttfb_ms":null,"download_ms":null,"redirect_ms":null,"redirect_chain":null,"detail":null,"content_hash":null}"#
+        );
+
+        let failure: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(failure["ok"], false);
+        assert_eq!(failure["status_code"], serde_json::Value::Null);
+        assert_eq!(failure["error"], "timed out");
+    }
+
+    #[test]
+    fn test_csv_header_written_once() {
+        let output = render(
+            OutputFormat::Csv,
+            &[sample(Ok(200)), sample(Err("Connection Failed: refused, try again".to_string()))],
+        );
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "url,timestamp,ok,status_code,response_time_ms,error,total_time_ms,attempts,name,tls_version,tls_cipher,cert_subject,cert_issuer,cert_names,cert_expires_in_days,tls_warning,dns_ms,connect_ms,tls_ms,ttfb_ms,download_ms,redirect_ms,redirect_chain,detail,content_hash");
+        assert_eq!(lines[1], "https://example.com,2023-11-14T22:13:20.250Z,true,200,123,,623,2,https://example.com,,,,,,,,,,,,,,,,");
+        assert_eq!(
+            lines[2],
+            r#"https://example.com,2023-11-14T22:13:20.250Z,false,,123,"Connection Failed: refused, try again",623,2,https://example.com,,,,,,,,,,,,,,,,"#
+        );
+    }
+
+    #[test]
+    fn test_failed_verdict_keeps_status_code() {
+        let record = StatusRecord::from(&sample(Ok(503)));
+        assert!(!record.ok);
+        assert_eq!(record.status_code, Some(503));
+        assert_eq!(record.error.as_deref(), Some("unexpected status code 503"));
+    }
+
+    #[test]
+    fn test_text_output() {
+        let output = render(OutputFormat::Text, &[sample(Ok(200)), sample(Ok(503))]);
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].starts_with("https://example.com - Status: 200 - Time: 123.456ms - Attempts: 2"));
+        assert!(lines[1].starts_with("https://example.com - Failed: unexpected status code 503 (status 503)"));
+
+        let named = WebsiteStatus { name: "Homepage".to_string(), ..sample(Ok(200)) };
+        assert!(render(OutputFormat::Text, &[named]).starts_with("Homepage (https://example.com) - Status: 200"));
+    }
+
+    #[test]
+    fn test_tls_fields() {
+        let cert = crate::tls::CertificateInfo {
+            subject: "CN=example.com".to_string(),
+            issuer: "CN=Example CA".to_string(),
+            names: vec!["example.com".to_string(), "www.example.com".to_string()],
+            not_before: UNIX_EPOCH,
+            not_after: UNIX_EPOCH + Duration::from_millis(1_700_000_000_250) + Duration::from_secs(10 * 24 * 60 * 60),
+        };
+        let status = WebsiteStatus {
+            tls: Some(Ok(crate::tls::TlsInfo {
+                version: "TLSv1.3".to_string(),
+                cipher: "TLS13_AES_128_GCM_SHA256".to_string(),
+                chain: vec![cert],
+                verify_error: None,
+            })),
+            tls_warning: Some("certificate CN=example.com expires in 10 days".to_string()),
+            ..sample(Ok(200))
+        };
+
+        let record = StatusRecord::from(&status);
+        assert_eq!(record.tls_version.as_deref(), Some("TLSv1.3"));
+        assert_eq!(record.cert_subject.as_deref(), Some("CN=example.com"));
+        assert_eq!(record.cert_names.as_deref(), Some("example.com www.example.com"));
+        assert_eq!(record.cert_expires_in_days, Some(10));
+
+        let text = render(OutputFormat::Text, &[status]);
+        assert!(text.ends_with(
+            " - TLS: TLSv1.3 TLS13_AES_128_GCM_SHA256, certificate expires in 10 days - Warning: certificate CN=example.com expires in 10 days\n"
+        ));
+    }
+
+    #[test]
+    fn test_timing_fields() {
+        let status = WebsiteStatus {
+            timings: Timings {
+                dns: Some(Duration::from_micros(1_500)),
+                connect: Some(Duration::from_millis(12)),
+                tls: None,
+                ttfb: Some(Duration::from_millis(80)),
+                download: Some(Duration::from_millis(30)),
+                redirect: None,
+            },
+            ..sample(Ok(200))
+        };
+
+        let record = StatusRecord::from(&status);
+        assert_eq!(record.dns_ms, Some(1));
+        assert_eq!(record.connect_ms, Some(12));
+        assert_eq!(record.tls_ms, None);
+        assert_eq!(record.ttfb_ms, Some(80));
+        assert_eq!(record.download_ms, Some(30));
+
+        let csv = render(OutputFormat::Csv, std::slice::from_ref(&status));
+        assert!(csv.lines().nth(1).unwrap().ends_with(",1,12,,80,30,,,,"));
+
+        let text = render(OutputFormat::Text, &[status]);
+        assert!(text.ends_with(" - Timings: DNS 1.5ms, connect 12ms, TTFB 80ms, download 30ms\n"));
+    }
+
+    #[test]
+    fn test_redirect_chain() {
+        let hop = |status, url: &str, location: &str| Redirect { url: url.to_string(), status, location: location.to_string() };
+        let status = WebsiteStatus {
+            redirects: vec![
+                hop(301, "http://example.com/", "https://example.com/"),
+                hop(302, "https://example.com/", "https://example.com/home"),
+            ],
+            ..sample(Ok(200))
+        };
+
+        let chain = "301 http://example.com/ -> https://example.com/; 302 https://example.com/ -> https://example.com/home";
+        assert_eq!(StatusRecord::from(&status).redirect_chain.as_deref(), Some(chain));
+        assert!(render(OutputFormat::Text, &[status]).contains(&format!(" - Redirects: {}", chain)));
+    }
+
+    #[test]
+    fn test_probe_results() {
+        let probe = WebsiteStatus {
+            name: "tcp://db.internal:5432".to_string(),
+            url: "tcp://db.internal:5432".to_string(),
+            status: Ok(None),
+            detail: Some("connected to 10.0.0.5:5432".to_string()),
+            ..sample(Ok(200))
+        };
+        let passed = WebsiteStatus { verdict: Verdict::Pass, ..probe.clone() };
+
+        let record = StatusRecord::from(&passed);
+        assert!(record.ok);
+        assert_eq!(record.status_code, None);
+        assert_eq!(record.detail.as_deref(), Some("connected to 10.0.0.5:5432"));
+        assert!(render(OutputFormat::Text, &[passed]).starts_with("tcp://db.internal:5432 - OK: connected to 10.0.0.5:5432 - Time:"));
+
+        let failed = WebsiteStatus {
+            verdict: Verdict::Fail(FailureReason::Probe("banner does not contain `SSH`".to_string())),
+            ..probe
+        };
+        assert!(render(OutputFormat::Text, &[failed])
+            .starts_with("tcp://db.internal:5432 - Failed: banner does not contain `SSH` (connected to 10.0.0.5:5432) - Time:"));
+    }
+
+    #[test]
+    fn test_content_changes() {
+        let change = ContentChange {
+            name: "Homepage".to_string(),
+            url: "https://example.com".to_string(),
+            previous_hash: "a".repeat(64),
+            hash: "b".repeat(64),
+            timestamp: "2023-11-14T22:13:20.250Z".to_string(),
+        };
+        let write = |format| {
+            let mut out = Vec::new();
+            ResultWriter::new(&mut out, format).write_change(&change).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            write(OutputFormat::Text),
+            "Homepage (https://example.com) - Content changed: aaaaaaaaaaaa -> bbbbbbbbbbbb - Timestamp: 2023-11-14T22:13:20.250Z\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(&write(OutputFormat::Json)).unwrap();
+        assert_eq!(json["event"], "content_change");
+        assert_eq!(json["hash"], "b".repeat(64));
+        assert_eq!(write(OutputFormat::Csv), "");
+
+        let record = StatusRecord::from(&WebsiteStatus { content_hash: Some("c".repeat(64)), ..sample(Ok(200)) });
+        assert_eq!(record.content_hash, Some("c".repeat(64)));
+    }
+
+    #[test]
+    fn test_old_records_still_parse() {
+        let line = r#"{"url":"https://example.com","timestamp":"2023-11-14T22:13:20.250Z","ok":true,"status_code":200,"response_time_ms":123,"error":null,"total_time_ms":623,"attempts":2,"name":"https://example.com"}"#;
+        let record: StatusRecord = serde_json::from_str(line).unwrap();
+        assert_eq!(record.tls_version, None);
+        assert_eq!(record.ttfb_ms, None);
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let record = StatusRecord::from(&sample(Err("timed out".to_string())));
+        let line = serde_json::to_string(&record).unwrap();
+        assert_eq!(serde_json::from_str::<StatusRecord>(&line).unwrap(), record);
+    }
+
+    #[test]
+    fn test_summaries() {
+        let summaries = [UrlSummary {
+            url: "https://example.com".to_string(),
+            checks: 8,
+            failures: 1,
+            uptime_percent: 87.5,
+            p50_ms: Some(120),
+            p95_ms: Some(480),
+            p99_ms: None,
+        }];
+
+        let mut text = Vec::new();
+        write_summaries(&mut text, OutputFormat::Text, &summaries).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "https://example.com - Uptime: 87.50% - Checks: 8 - Failures: 1 - p50: 120 ms - p95: 480 ms - p99: -\n"
+        );
+
+        let mut csv = Vec::new();
+        write_summaries(&mut csv, OutputFormat::Csv, &summaries).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "url,checks,failures,uptime_percent,p50_ms,p95_ms,p99_ms\nhttps://example.com,8,1,87.5,120,480,\n"
+        );
+    }
+
+    #[test]
+    fn test_timestamp_is_rfc3339() {
+        let record = StatusRecord::from(&WebsiteStatus {
+            timestamp: SystemTime::now(),
+            ..sample(Ok(200))
+        });
+        assert!(humantime::parse_rfc3339(&record.timestamp).is_ok());
+    }
+}
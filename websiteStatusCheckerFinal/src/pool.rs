@@ -0,0 +1,518 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+
+use crate::http::HttpClient;
+use crate::metrics::Metrics;
+use crate::probe::{check_probe_limited, Probe};
+use crate::ratelimit::{self, HostLimiter, HostLimits, HostPermit};
+use crate::{check_website_limited, Target, WebsiteStatus, CANCEL_POLL};
+
+/// How often a shutting-down pool checks whether its workers have exited.
+const JOIN_POLL: Duration = Duration::from_millis(10);
+
+/// What happens to queued checks when a pool shuts down.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ShutdownMode {
+    /// Finish every queued check before stopping
+    Drain,
+    /// Drop queued checks and stop retrying the ones in flight
+    Abort,
+}
+
+/// A check on its way to the workers, with the permit its host's limits gave it.
+enum MonitorMessage {
+    CheckUrl(Arc<Target>, HostPermit),
+    /// A `tcp://` or `dns://` target, which is probed rather than requested.
+    RunProbe(Arc<Target>, Probe, HostPermit),
+    Shutdown,
+}
+
+enum GateMessage {
+    Submit(Arc<Target>),
+    /// Sent once by `WorkerPool::stop`; no checks are submitted after it.
+    Stop,
+}
+
+/// Holds checks back until their host's limits let them start, so workers only
+/// receive checks they can run at once and a throttled host cannot tie them up.
+struct Gate {
+    submissions: Receiver<GateMessage>,
+    tasks: Sender<MonitorMessage>,
+    limiter: Arc<HostLimiter>,
+    cancel: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+    pending: Pending,
+    workers: usize,
+}
+
+impl Gate {
+    fn run(self) {
+        let mut waiting = VecDeque::new();
+        let mut stopping = false;
+        loop {
+            if self.cancel.load(Ordering::Relaxed) {
+                for target in waiting.drain(..) {
+                    self.pending.remove(&target);
+                    self.metrics.discarded();
+                }
+            }
+            let Some(wait) = self.release(&mut waiting) else {
+                // Every worker has exited
+                return;
+            };
+            if stopping {
+                if waiting.is_empty() {
+                    break;
+                }
+                thread::sleep(wait);
+                continue;
+            }
+
+            let message = if waiting.is_empty() {
+                self.submissions.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                self.submissions.recv_timeout(wait)
+            };
+            match message {
+                Ok(GateMessage::Submit(target)) => waiting.push_back(target),
+                Ok(GateMessage::Stop) | Err(RecvTimeoutError::Disconnected) => stopping = true,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+
+        // Behind every check, so draining workers finish them first
+        for _ in 0..self.workers {
+            let _ = self.tasks.send(MonitorMessage::Shutdown);
+        }
+    }
+
+    /// Sends each waiting check whose host's limits let it start to the workers,
+    /// keeping the checks of each host in order. Returns how long until another
+    /// might start, or `None` if the workers are gone.
+    fn release(&self, waiting: &mut VecDeque<Arc<Target>>) -> Option<Duration> {
+        let mut wait = CANCEL_POLL;
+        let mut throttled = HashSet::new();
+        let mut delivered = true;
+        waiting.retain(|target| {
+            let origin = ratelimit::origin(&target.request.url);
+            if !delivered || throttled.contains(&origin) {
+                return true;
+            }
+            match self.limiter.try_acquire(&target.request.url) {
+                Ok(permit) => {
+                    let message = match target.probe.clone() {
+                        Some(probe) => MonitorMessage::RunProbe(Arc::clone(target), probe, permit),
+                        None => MonitorMessage::CheckUrl(Arc::clone(target), permit),
+                    };
+                    delivered = self.tasks.send(message).is_ok();
+                    !delivered
+                }
+                Err(until) => {
+                    wait = wait.min(until);
+                    throttled.insert(origin);
+                    true
+                }
+            }
+        });
+        delivered.then_some(wait)
+    }
+}
+
+/// Where workers deliver results. Emptied when the pool gives up on its workers,
+/// so the consumer is not kept waiting by a worker that is stuck in a request.
+type ResultSlot = Arc<Mutex<Option<Sender<WebsiteStatus>>>>;
+
+/// The targets with a check queued, held back or running, by identity. Each entry
+/// keeps its target alive, so its address cannot be reused while it is listed.
+#[derive(Clone, Default)]
+struct Pending(Arc<Mutex<HashMap<usize, Arc<Target>>>>);
+
+impl Pending {
+    fn key(target: &Arc<Target>) -> usize {
+        Arc::as_ptr(target) as usize
+    }
+
+    fn insert(&self, target: &Arc<Target>) {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).insert(Pending::key(target), Arc::clone(target));
+    }
+
+    fn remove(&self, target: &Arc<Target>) {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).remove(&Pending::key(target));
+    }
+
+    fn contains(&self, target: &Arc<Target>) -> bool {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).contains_key(&Pending::key(target))
+    }
+}
+
+struct Worker {
+    tasks: Arc<Mutex<Receiver<MonitorMessage>>>,
+    results: ResultSlot,
+    client: Arc<dyn HttpClient>,
+    metrics: Arc<Metrics>,
+    cancel: Arc<AtomicBool>,
+    limiter: Arc<HostLimiter>,
+    pending: Pending,
+}
+
+impl Worker {
+    fn run(self) {
+        loop {
+            // The guard is dropped at the end of this statement, so other workers
+            // can receive while this one is checking
+            let message = self.tasks.lock().unwrap_or_else(PoisonError::into_inner).recv();
+            let (target, probe, permit) = match message {
+                Ok(MonitorMessage::CheckUrl(target, permit)) => (target, None, permit),
+                Ok(MonitorMessage::RunProbe(target, probe, permit)) => (target, Some(probe), permit),
+                Ok(MonitorMessage::Shutdown) | Err(_) => break,
+            };
+            if self.cancel.load(Ordering::Relaxed) {
+                self.pending.remove(&target);
+                self.metrics.discarded();
+                continue;
+            }
+
+            let status = match &probe {
+                Some(probe) => check_probe_limited(&target, probe, &self.cancel, &self.limiter),
+                None => check_website_limited(self.client.as_ref(), &target, &self.cancel, &self.limiter),
+            };
+            drop(permit);
+            self.pending.remove(&target);
+            self.metrics.record(&status);
+
+            let delivered = match &*self.results.lock().unwrap_or_else(PoisonError::into_inner) {
+                Some(sender) => sender.send(status).is_ok(),
+                None => false,
+            };
+            if !delivered {
+                // Nobody is listening for results any more
+                break;
+            }
+        }
+    }
+}
+
+/// A fixed set of worker threads that run checks from a shared queue.
+pub struct WorkerPool {
+    submissions: Sender<GateMessage>,
+    pending: Pending,
+    results: ResultSlot,
+    workers: Vec<JoinHandle<()>>,
+    gate: JoinHandle<()>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl WorkerPool {
+    /// Starts `size` workers (at least one) that send each result to `results`.
+    pub fn new(size: usize, client: Arc<dyn HttpClient>, metrics: Arc<Metrics>, results: Sender<WebsiteStatus>) -> Self {
+        WorkerPool::with_limits(size, client, metrics, results, HostLimits::default())
+    }
+
+    /// Like `new`, with each check waiting until its host's `limits` allow it to start.
+    /// Waiting checks are held back before they reach a worker, so workers stay free
+    /// for checks of other hosts.
+    pub fn with_limits(
+        size: usize,
+        client: Arc<dyn HttpClient>,
+        metrics: Arc<Metrics>,
+        results: Sender<WebsiteStatus>,
+        limits: HostLimits,
+    ) -> Self {
+        let limiter = Arc::new(HostLimiter::new(limits));
+        WorkerPool::with_cancel(size, client, metrics, results, Arc::new(AtomicBool::new(false)), limiter)
+    }
+
+    /// Like `with_limits`, with a caller-owned flag that aborts the pool when set.
+    pub(crate) fn with_cancel(
+        size: usize,
+        client: Arc<dyn HttpClient>,
+        metrics: Arc<Metrics>,
+        results: Sender<WebsiteStatus>,
+        cancel: Arc<AtomicBool>,
+        limiter: Arc<HostLimiter>,
+    ) -> Self {
+        let (tasks, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let results = Arc::new(Mutex::new(Some(results)));
+        let pending = Pending::default();
+
+        let workers: Vec<_> = (0..size.max(1))
+            .map(|_| {
+                let worker = Worker {
+                    tasks: Arc::clone(&receiver),
+                    results: Arc::clone(&results),
+                    client: Arc::clone(&client),
+                    metrics: Arc::clone(&metrics),
+                    cancel: Arc::clone(&cancel),
+                    limiter: Arc::clone(&limiter),
+                    pending: pending.clone(),
+                };
+                thread::spawn(move || worker.run())
+            })
+            .collect();
+
+        let (submissions, receiver) = mpsc::channel();
+        let gate = Gate {
+            submissions: receiver,
+            tasks,
+            limiter,
+            cancel: Arc::clone(&cancel),
+            metrics,
+            pending: pending.clone(),
+            workers: workers.len(),
+        };
+        let gate = thread::spawn(move || gate.run());
+
+        WorkerPool { submissions, pending, results, workers, gate, cancel }
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Queues a check, returning `false` if every worker has already exited.
+    pub fn submit(&self, target: Arc<Target>) -> bool {
+        self.pending.insert(&target);
+        self.submissions.send(GateMessage::Submit(target)).is_ok()
+    }
+
+    /// Whether a check of `target`, the same `Arc` rather than an equal target, is
+    /// still queued, held back for its host or running.
+    pub fn is_pending(&self, target: &Arc<Target>) -> bool {
+        self.pending.contains(target)
+    }
+
+    /// Drops queued checks and stops in-flight checks from retrying.
+    pub fn abort(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Stops every worker and waits for them to exit, returning how many were abandoned.
+    ///
+    /// With a `timeout`, workers still running when it expires are aborted and left to
+    /// finish their current request in the background; no more results are delivered.
+    pub fn shutdown(self, mode: ShutdownMode, timeout: Option<Duration>) -> usize {
+        if mode == ShutdownMode::Abort {
+            self.abort();
+        }
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self.stop(|| deadline)
+    }
+
+    /// Has the workers exit once the checks queued or held back for their hosts are
+    /// done, and waits until they do or the deadline returned by `deadline` passes.
+    pub(crate) fn stop(mut self, mut deadline: impl FnMut() -> Option<Instant>) -> usize {
+        let _ = self.submissions.send(GateMessage::Stop);
+
+        loop {
+            let (finished, running) = self.workers.drain(..).partition(|worker| worker.is_finished());
+            self.workers = running;
+            for worker in finished {
+                if worker.join().is_err() {
+                    eprintln!("A worker thread panicked");
+                }
+            }
+
+            if self.workers.is_empty() {
+                // The gate exits once it has told the workers to, or found them gone
+                if self.gate.join().is_err() {
+                    eprintln!("The worker pool's gate thread panicked");
+                }
+                return 0;
+            }
+            if deadline().is_some_and(|deadline| Instant::now() >= deadline) {
+                self.abort();
+                self.results.lock().unwrap_or_else(PoisonError::into_inner).take();
+                return self.workers.len();
+            }
+            thread::sleep(JOIN_POLL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeClient;
+    use crate::Config;
+
+    fn pool(size: usize, client: FakeClient) -> (WorkerPool, Receiver<WebsiteStatus>, Arc<FakeClient>) {
+        let client = Arc::new(client);
+        let (sender, receiver) = mpsc::channel();
+        (WorkerPool::new(size, client.clone(), Arc::new(Metrics::new()), sender), receiver, client)
+    }
+
+    fn target(path: &str) -> Arc<Target> {
+        Arc::new(Target::new(&format!("https://example.com/{}", path), &Config::default()))
+    }
+
+    #[test]
+    fn test_worker_pool() {
+        let (pool, results, _) = pool(1, FakeClient::new(vec![FakeClient::status(200)]));
+
+        assert!(pool.submit(target("a")));
+        let result = results.recv().unwrap();
+        assert_eq!(result.status, Ok(Some(200)));
+
+        assert_eq!(pool.shutdown(ShutdownMode::Drain, None), 0);
+        assert!(results.recv().is_err());
+    }
+
+    #[test]
+    fn test_probes_share_the_pipeline() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("tcp://{}", listener.local_addr().unwrap());
+        let (pool, results, client) = pool(2, FakeClient::new(vec![FakeClient::status(200)]));
+
+        pool.submit(Arc::new(Target::new(&url, &Config::default())));
+        pool.submit(target("a"));
+        assert_eq!(pool.shutdown(ShutdownMode::Drain, None), 0);
+
+        let mut results: Vec<_> = results.iter().map(|status| (status.url, status.status, status.verdict.is_pass())).collect();
+        results.sort();
+        assert_eq!(results, vec![("https://example.com/a".to_string(), Ok(Some(200)), true), (url, Ok(None), true)]);
+        // The probe never reached the HTTP client
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_drain_finishes_queued_checks_in_order() {
+        let (pool, results, client) = pool(1, FakeClient::new(vec![FakeClient::status(200), FakeClient::status(201), FakeClient::status(202)]));
+
+        for path in ["a", "b", "c"] {
+            pool.submit(target(path));
+        }
+        assert_eq!(pool.shutdown(ShutdownMode::Drain, None), 0);
+
+        let results: Vec<_> = results.iter().map(|status| (status.url, status.status)).collect();
+        assert_eq!(
+            results,
+            vec![
+                ("https://example.com/a".to_string(), Ok(Some(200))),
+                ("https://example.com/b".to_string(), Ok(Some(201))),
+                ("https://example.com/c".to_string(), Ok(Some(202))),
+            ]
+        );
+        assert_eq!(client.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_all_workers_exit() {
+        let (pool, results, _) = pool(8, FakeClient::new(vec![FakeClient::status(200); 40]));
+        assert_eq!(pool.size(), 8);
+
+        for i in 0..40 {
+            pool.submit(target(&i.to_string()));
+        }
+        assert_eq!(pool.shutdown(ShutdownMode::Drain, None), 0);
+
+        // Every worker dropped its way to the results, so the channel is closed
+        assert_eq!(results.iter().count(), 40);
+    }
+
+    #[test]
+    fn test_workers_check_concurrently() {
+        let client = FakeClient::new(vec![FakeClient::status(200); 4]).with_delay(Duration::from_millis(200));
+        let (pool, results, _) = pool(4, client);
+
+        let start = Instant::now();
+        for i in 0..4 {
+            pool.submit(target(&i.to_string()));
+        }
+        assert_eq!(pool.shutdown(ShutdownMode::Drain, None), 0);
+        assert_eq!(results.iter().count(), 4);
+        assert!(start.elapsed() < Duration::from_millis(600));
+    }
+
+    #[test]
+    fn test_host_limits_hold_back_checks() {
+        let client = Arc::new(FakeClient::new(vec![FakeClient::status(200); 4]).with_delay(Duration::from_millis(50)));
+        let (sender, results) = mpsc::channel();
+        let limits = HostLimits { max_in_flight: Some(1), ..HostLimits::default() };
+        let pool = WorkerPool::with_limits(4, client, Arc::new(Metrics::new()), sender, limits);
+
+        // Every target is on example.com, so four workers still check one at a time
+        let start = Instant::now();
+        for i in 0..4 {
+            pool.submit(target(&i.to_string()));
+        }
+        assert_eq!(pool.shutdown(ShutdownMode::Drain, None), 0);
+        assert_eq!(results.iter().count(), 4);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_throttled_host_does_not_tie_up_workers() {
+        let client = Arc::new(FakeClient::new(vec![FakeClient::status(200); 4]).with_delay(Duration::from_millis(100)));
+        let (sender, results) = mpsc::channel();
+        let limits = HostLimits { max_in_flight: Some(1), ..HostLimits::default() };
+        let pool = WorkerPool::with_limits(2, client, Arc::new(Metrics::new()), sender, limits);
+
+        for i in 0..3 {
+            pool.submit(target(&i.to_string()));
+        }
+        pool.submit(Arc::new(Target::new("https://example.org/", &Config::default())));
+
+        // The other host's check runs alongside the first, rather than behind a worker waiting on example.com
+        let first: Vec<_> = results.iter().take(2).map(|status| status.url).collect();
+        assert!(first.contains(&"https://example.org/".to_string()), "{:?}", first);
+        assert_eq!(pool.shutdown(ShutdownMode::Drain, None), 0);
+        assert_eq!(results.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_abort_drops_queued_checks() {
+        let client = FakeClient::new(vec![FakeClient::status(200); 10]).with_delay(Duration::from_millis(50));
+        let (pool, results, client) = pool(1, client);
+
+        for i in 0..10 {
+            pool.submit(target(&i.to_string()));
+        }
+        results.recv().unwrap();
+        assert_eq!(pool.shutdown(ShutdownMode::Abort, None), 0);
+
+        // At most the check in flight when the abort arrived completes
+        assert!(results.iter().count() <= 1);
+        assert!(client.requests().len() <= 2);
+    }
+
+    #[test]
+    fn test_timeout_abandons_stuck_workers() {
+        let client = FakeClient::new(vec![FakeClient::status(200)]).with_delay(Duration::from_secs(2));
+        let (pool, results, _) = pool(2, client);
+
+        pool.submit(target("slow"));
+        thread::sleep(Duration::from_millis(50));
+
+        let start = Instant::now();
+        assert_eq!(pool.shutdown(ShutdownMode::Drain, Some(Duration::from_millis(100))), 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // The stuck worker's result is never delivered, so the consumer is not left waiting
+        assert!(results.recv().is_err());
+    }
+
+    #[test]
+    fn test_missing_consumer_does_not_panic() {
+        let (pool, results, _) = pool(2, FakeClient::new(vec![FakeClient::status(200); 4]));
+        drop(results);
+
+        for i in 0..4 {
+            pool.submit(target(&i.to_string()));
+        }
+        assert_eq!(pool.shutdown(ShutdownMode::Drain, None), 0);
+    }
+
+    #[test]
+    fn test_graceful_shutdown() {
+        let (pool, _results, client) = pool(3, FakeClient::new(Vec::new()));
+        assert_eq!(pool.shutdown(ShutdownMode::Drain, None), 0);
+        assert!(client.requests().is_empty());
+    }
+}
@@ -0,0 +1,304 @@
+//! Checks for services without an HTTP endpoint, such as databases and brokers.
+//!
+//! A probe target is written as a URL like any other: `tcp://host:port` connects to
+//! a port, optionally reading a banner that must contain a string, and `dns://name`
+//! resolves a name, optionally requiring some addresses in the answer. Probes are
+//! retried, timed and reported exactly like HTTP checks; they have no status code.
+
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::http::HttpError;
+use crate::ratelimit::{HostLimiter, HostLimits};
+use crate::{sleep_unless_cancelled, CheckRun, Target, WebsiteStatus};
+
+/// Most of a banner that is read while looking for the expected string.
+const MAX_BANNER_BYTES: usize = 4096;
+
+/// A check that is not an HTTP request.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Probe {
+    /// Connects to `port`, then reads until `banner` appears if one is expected.
+    Tcp { host: String, port: u16, banner: Option<String> },
+    /// Resolves `host`, which must answer with every address in `expect`.
+    Dns { host: String, expect: Vec<IpAddr> },
+}
+
+impl Probe {
+    /// The probe a `tcp://` or `dns://` URL describes, or `None` for any other scheme.
+    pub fn from_url(url: &str) -> Result<Option<Probe>, String> {
+        let parsed = url::Url::parse(url).map_err(|err| format!("invalid URL `{}`: {}", url, err))?;
+        let host = || {
+            // IPv6 hosts keep their brackets in `host_str`, which resolving does not want
+            let host = parsed.host_str().filter(|host| !host.is_empty());
+            host.map(|host| host.trim_start_matches('[').trim_end_matches(']').to_string())
+                .ok_or_else(|| format!("missing host in `{}`", url))
+        };
+
+        match parsed.scheme() {
+            "tcp" => {
+                let port = parsed.port().ok_or_else(|| format!("missing port in `{}`", url))?;
+                Ok(Some(Probe::Tcp { host: host()?, port, banner: None }))
+            }
+            "dns" => {
+                if parsed.port().is_some() {
+                    return Err(format!("dns probes take no port, in `{}`", url));
+                }
+                Ok(Some(Probe::Dns { host: host()?, expect: Vec::new() }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Makes one attempt, returning what it found and why that fails the check, if it does.
+    pub(crate) fn attempt(&self, timeout: Duration) -> Result<Finding, HttpError> {
+        let deadline = Instant::now() + timeout;
+        match self {
+            Probe::Tcp { host, port, banner } => {
+                let addrs = resolve(host, *port, deadline)?;
+                let (mut stream, addr) = connect(&addrs, deadline)?;
+                let detail = format!("connected to {}", addr);
+                match banner {
+                    Some(expected) => read_banner(&mut stream, expected, deadline, detail),
+                    None => Ok(Finding { detail, mismatch: None }),
+                }
+            }
+            Probe::Dns { host, expect } => {
+                let mut addrs: Vec<IpAddr> = resolve(host, 0, deadline)?.iter().map(SocketAddr::ip).collect();
+                addrs.sort();
+                addrs.dedup();
+
+                let missing: Vec<String> = expect.iter().filter(|ip| !addrs.contains(ip)).map(ToString::to_string).collect();
+                let answer = addrs.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+                Ok(Finding {
+                    mismatch: (!missing.is_empty()).then(|| format!("{} does not resolve to {}", host, missing.join(", "))),
+                    detail: format!("resolved to {}", answer),
+                })
+            }
+        }
+    }
+}
+
+/// What an attempt that reached the service found.
+pub(crate) struct Finding {
+    /// Shown with the result, as the address connected to or the addresses resolved.
+    pub(crate) detail: String,
+    /// Why the service answered, but not as expected.
+    pub(crate) mismatch: Option<String>,
+}
+
+/// Probes `target`, retrying as its policy allows. Like `check_website_cancellable`,
+/// no further attempts are made once `cancel` is set.
+pub fn check_probe(target: &Target, probe: &Probe, cancel: &AtomicBool) -> WebsiteStatus {
+    check_probe_limited(target, probe, cancel, &HostLimiter::new(HostLimits::default()))
+}
+
+/// Like `check_probe`, for a check that holds a permit from `limiter`; its retries
+/// keep to the host's rate and crawl delay.
+pub(crate) fn check_probe_limited(target: &Target, probe: &Probe, cancel: &AtomicBool, limiter: &HostLimiter) -> WebsiteStatus {
+    let mut check = CheckRun::new(target);
+    loop {
+        let attempt_start = Instant::now();
+        let observed = probe.attempt(target.timeout);
+        match check.record_probe(observed, attempt_start.elapsed()) {
+            Some(delay) if sleep_unless_cancelled(delay, cancel) && limiter.pace(&target.request.url, cancel) => continue,
+            _ => break,
+        }
+    }
+    check.finish()
+}
+
+/// Looks `host` up on a separate thread, since the system resolver has no timeout of its own.
+fn resolve(host: &str, port: u16, deadline: Instant) -> Result<Vec<SocketAddr>, HttpError> {
+    let (sender, receiver) = mpsc::channel();
+    let name = host.to_string();
+    thread::spawn(move || {
+        let _ = sender.send((name.as_str(), port).to_socket_addrs().map(Iterator::collect::<Vec<_>>));
+    });
+
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    match receiver.recv_timeout(remaining) {
+        Ok(Ok(addrs)) if !addrs.is_empty() => Ok(addrs),
+        Ok(Ok(_)) => Err(retryable(format!("Dns Failed: no addresses for {}", host))),
+        Ok(Err(err)) => Err(retryable(format!("Dns Failed: resolve dns name '{}': {}", host, err))),
+        Err(_) => Err(retryable(format!("Dns Failed: resolving {} timed out", host))),
+    }
+}
+
+fn connect(addrs: &[SocketAddr], deadline: Instant) -> Result<(TcpStream, SocketAddr), HttpError> {
+    let mut last_error = None;
+    for addr in addrs {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        match TcpStream::connect_timeout(addr, remaining) {
+            Ok(stream) => return Ok((stream, *addr)),
+            Err(err) => last_error = Some(err),
+        }
+    }
+    Err(match last_error {
+        Some(err) if err.kind() != io::ErrorKind::TimedOut => retryable(format!("Connection Failed: Connect error: {}", err)),
+        _ => retryable("Connection Failed: connect timed out".to_string()),
+    })
+}
+
+/// Reads until `expected` turns up, the server stops sending, or the deadline passes.
+fn read_banner(stream: &mut TcpStream, expected: &str, deadline: Instant, detail: String) -> Result<Finding, HttpError> {
+    let mut banner = Vec::new();
+    let mut buf = [0; 512];
+    let mut closed = false;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        stream.set_read_timeout(Some(remaining)).map_err(|err| retryable(format!("Network Error: {}", err)))?;
+        match stream.read(&mut buf) {
+            Ok(0) => {
+                closed = true;
+                break;
+            }
+            Ok(read) => banner.extend_from_slice(&buf[..read]),
+            Err(err) if matches!(err.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => break,
+            Err(err) => return Err(retryable(format!("Network Error: reading banner: {}", err))),
+        }
+        if String::from_utf8_lossy(&banner).contains(expected) || banner.len() >= MAX_BANNER_BYTES {
+            break;
+        }
+    }
+
+    match (banner.is_empty(), closed) {
+        (true, true) => return Err(retryable("Network Error: connection closed without a banner".to_string())),
+        (true, false) => return Err(retryable("Network Error: timed out waiting for a banner".to_string())),
+        _ => {}
+    }
+    let text = String::from_utf8_lossy(&banner);
+    let first_line = text.lines().next().unwrap_or_default().trim();
+    Ok(Finding {
+        detail: format!("{}, banner `{}`", detail, first_line),
+        mismatch: (!text.contains(expected)).then(|| format!("banner does not contain `{}`", expected)),
+    })
+}
+
+fn retryable(message: String) -> HttpError {
+    HttpError { message, retryable: true }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::criteria::{FailureReason, Verdict};
+    use crate::retry::{Backoff, RetryPolicy};
+    use crate::testing::MockServer;
+    use crate::Config;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    fn target(url: &str) -> Target {
+        let retry = RetryPolicy { max_retries: 0, backoff: Backoff::Fixed, base_delay: Duration::ZERO, max_delay: Duration::ZERO };
+        Target { retry, timeout: Duration::from_secs(2), ..Target::new(url, &Config::default()) }
+    }
+
+    fn check(target: &Target) -> WebsiteStatus {
+        check_probe(target, target.probe.as_ref().unwrap(), &AtomicBool::new(false))
+    }
+
+    fn expect_banner(target: &mut Target, expected: &str) {
+        if let Some(Probe::Tcp { banner, .. }) = &mut target.probe {
+            *banner = Some(expected.to_string());
+        }
+    }
+
+    /// Accepts one connection and writes `banner` to it after `delay`.
+    fn banner_server(banner: &'static str, delay: Duration) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("tcp://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            thread::sleep(delay);
+            let _ = stream.write_all(banner.as_bytes());
+        });
+        url
+    }
+
+    #[test]
+    fn test_from_url() {
+        assert_eq!(
+            Probe::from_url("tcp://db.internal:5432"),
+            Ok(Some(Probe::Tcp { host: "db.internal".to_string(), port: 5432, banner: None }))
+        );
+        assert_eq!(
+            Probe::from_url("tcp://[::1]:6379"),
+            Ok(Some(Probe::Tcp { host: "::1".to_string(), port: 6379, banner: None }))
+        );
+        assert_eq!(Probe::from_url("dns://example.com"), Ok(Some(Probe::Dns { host: "example.com".to_string(), expect: Vec::new() })));
+        assert_eq!(Probe::from_url("https://example.com"), Ok(None));
+        assert!(Probe::from_url("tcp://db.internal").is_err());
+        assert!(Probe::from_url("dns://example.com:53").is_err());
+        assert!(Probe::from_url("tcp://:80").is_err());
+    }
+
+    #[test]
+    fn test_tcp_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let status = check(&target(&format!("tcp://{}", addr)));
+        assert!(status.verdict.is_pass());
+        assert_eq!(status.status, Ok(None));
+        assert_eq!(status.detail, Some(format!("connected to {}", addr)));
+
+        let refused = MockServer::refused_url().replace("http://", "tcp://");
+        let status = check(&target(refused.trim_end_matches('/')));
+        assert!(status.status.unwrap_err().contains("Connection Failed"));
+        assert_eq!(status.detail, None);
+    }
+
+    #[test]
+    fn test_banner() {
+        let mut probe = target(&banner_server("SSH-2.0-OpenSSH_9.6\r\n", Duration::from_millis(50)));
+        expect_banner(&mut probe, "SSH-2.0");
+        let status = check(&probe);
+        assert!(status.verdict.is_pass());
+        assert!(status.detail.unwrap().ends_with(", banner `SSH-2.0-OpenSSH_9.6`"));
+
+        let mut probe = target(&banner_server("+OK redis\r\n", Duration::ZERO));
+        expect_banner(&mut probe, "SSH-2.0");
+        let status = check(&probe);
+        assert_eq!(status.status, Ok(None));
+        assert_eq!(status.verdict, Verdict::Fail(FailureReason::Probe("banner does not contain `SSH-2.0`".to_string())));
+    }
+
+    #[test]
+    fn test_silent_server_times_out() {
+        let mut probe = target(&banner_server("late", Duration::from_secs(1)));
+        probe.timeout = Duration::from_millis(100);
+        expect_banner(&mut probe, "late");
+        let status = check(&probe);
+        assert!(status.status.unwrap_err().contains("timed out"));
+    }
+
+    #[test]
+    fn test_dns() {
+        let mut probe = target("dns://localhost");
+        let status = check(&probe);
+        assert!(status.verdict.is_pass());
+        assert!(status.detail.unwrap().contains("127.0.0.1"));
+
+        probe.probe = Some(Probe::Dns { host: "localhost".to_string(), expect: vec!["127.0.0.1".parse().unwrap()] });
+        assert!(check(&probe).verdict.is_pass());
+
+        probe.probe = Some(Probe::Dns { host: "localhost".to_string(), expect: vec!["192.0.2.1".parse().unwrap()] });
+        assert_eq!(
+            check(&probe).verdict,
+            Verdict::Fail(FailureReason::Probe("localhost does not resolve to 192.0.2.1".to_string()))
+        );
+
+        let status = check(&target("dns://does-not-exist.invalid"));
+        assert!(status.status.unwrap_err().contains("Dns Failed"));
+    }
+}
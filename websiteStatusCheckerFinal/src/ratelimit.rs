@@ -0,0 +1,314 @@
+//! Per-host politeness: limits on how hard checks may hit any one origin, so that
+//! a list with many URLs on the same host does not look like an attack to it.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::CANCEL_POLL;
+
+/// The scheme, host and port of `url`, which is what the limits apply to. Every
+/// unparseable URL shares one key, as none of them reach a server.
+pub fn origin(url: &str) -> String {
+    let Ok(url) = url::Url::parse(url) else { return String::new() };
+    match url.origin() {
+        origin @ url::Origin::Tuple(..) => origin.ascii_serialization(),
+        // Probe URLs such as `tcp://host:port` have no web origin, so their host and port stand in
+        url::Origin::Opaque(_) => format!("{}://{}:{}", url.scheme(), url.host_str().unwrap_or_default(), url.port().unwrap_or(0)),
+    }
+}
+
+/// Limits that apply to each host separately. The default limits nothing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostLimits {
+    /// Sustained checks per second to one host, refilling a token bucket.
+    pub rate: Option<f64>,
+    /// Checks a host can receive back to back before `rate` applies.
+    pub burst: u32,
+    /// Checks running against one host at once.
+    pub max_in_flight: Option<usize>,
+    /// Least time between the starts of two checks to one host, like robots.txt `Crawl-delay`.
+    pub crawl_delay: Option<Duration>,
+}
+
+impl Default for HostLimits {
+    fn default() -> Self {
+        HostLimits { rate: None, burst: 1, max_in_flight: None, crawl_delay: None }
+    }
+}
+
+impl HostLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self.rate.is_none() && self.max_in_flight.is_none() && self.crawl_delay.is_none()
+    }
+}
+
+struct HostState {
+    in_flight: usize,
+    tokens: f64,
+    refilled: Instant,
+    last_start: Option<Instant>,
+}
+
+impl HostState {
+    fn new(limits: &HostLimits, now: Instant) -> Self {
+        HostState { in_flight: 0, tokens: limits.burst.max(1) as f64, refilled: now, last_start: None }
+    }
+
+    /// How long until a request may start, or `None` if one may start now. Only a
+    /// `new_check` needs a place among the host's in-flight checks.
+    fn wait(&mut self, limits: &HostLimits, now: Instant, new_check: bool) -> Option<Duration> {
+        if let Some(rate) = limits.rate {
+            let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * rate).min(limits.burst.max(1) as f64);
+            self.refilled = now;
+        }
+
+        // A finished check wakes the waiters, so the poll interval is only a fallback
+        if new_check && limits.max_in_flight.is_some_and(|max| self.in_flight >= max) {
+            return Some(CANCEL_POLL);
+        }
+        if let (Some(delay), Some(last_start)) = (limits.crawl_delay, self.last_start) {
+            let next = last_start + delay;
+            if next > now {
+                return Some(next - now);
+            }
+        }
+        match limits.rate {
+            Some(rate) if self.tokens < 1.0 => Some(Duration::from_secs_f64((1.0 - self.tokens) / rate)),
+            _ => None,
+        }
+    }
+
+    fn start(&mut self, limits: &HostLimits, now: Instant, new_check: bool) {
+        self.in_flight += usize::from(new_check);
+        self.last_start = Some(now);
+        if limits.rate.is_some() {
+            self.tokens -= 1.0;
+        }
+    }
+}
+
+/// Holds checks back until their host's limits allow them. Shared by every worker.
+///
+/// A check takes a `HostPermit` to start, which counts it as in flight. Its retries
+/// and redirects are further requests to a host, so they are paced by the rate and
+/// crawl delay as well, but they run within the check's own permit.
+pub struct HostLimiter {
+    limits: HostLimits,
+    hosts: Mutex<HashMap<String, HostState>>,
+    released: Condvar,
+}
+
+impl HostLimiter {
+    pub fn new(limits: HostLimits) -> Self {
+        HostLimiter { limits, hosts: Mutex::new(HashMap::new()), released: Condvar::new() }
+    }
+
+    pub fn limits(&self) -> &HostLimits {
+        &self.limits
+    }
+
+    /// Blocks until a check of `url` may start, returning `None` instead if `cancel`
+    /// is set first. The check counts as in flight until the permit is dropped.
+    pub fn acquire(self: &Arc<Self>, url: &str, cancel: &AtomicBool) -> Option<HostPermit> {
+        if self.limits.is_unlimited() {
+            return Some(HostPermit { limiter: Arc::clone(self), origin: None });
+        }
+        let origin = self.wait_to_start(url, cancel, true)?;
+        Some(HostPermit { limiter: Arc::clone(self), origin: Some(origin) })
+    }
+
+    /// Like `acquire`, without blocking: if the host's limits hold the check back,
+    /// returns how long until they might let it start.
+    pub fn try_acquire(self: &Arc<Self>, url: &str) -> Result<HostPermit, Duration> {
+        if self.limits.is_unlimited() {
+            return Ok(HostPermit { limiter: Arc::clone(self), origin: None });
+        }
+        let origin = origin(url);
+        let mut hosts = self.hosts.lock().unwrap_or_else(PoisonError::into_inner);
+        self.try_start(&mut hosts, &origin, true)?;
+        Ok(HostPermit { limiter: Arc::clone(self), origin: Some(origin) })
+    }
+
+    /// Blocks until a check that already holds a permit may send another request to
+    /// the host of `url`, as for a retry or a redirect. Returns `false` instead if
+    /// `cancel` is set first.
+    pub fn pace(&self, url: &str, cancel: &AtomicBool) -> bool {
+        self.limits.is_unlimited() || self.wait_to_start(url, cancel, false).is_some()
+    }
+
+    /// Waits until a request to the origin of `url` may start and starts it, returning
+    /// the origin, or `None` if `cancel` is set first.
+    fn wait_to_start(&self, url: &str, cancel: &AtomicBool, new_check: bool) -> Option<String> {
+        let origin = origin(url);
+        let mut hosts = self.hosts.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            match self.try_start(&mut hosts, &origin, new_check) {
+                Ok(()) => return Some(origin),
+                Err(wait) => {
+                    let wait = wait.min(CANCEL_POLL);
+                    hosts = self.released.wait_timeout(hosts, wait).unwrap_or_else(PoisonError::into_inner).0;
+                }
+            }
+        }
+    }
+
+    /// Starts a request to `origin` if its limits allow it now, or says how long until they might.
+    fn try_start(&self, hosts: &mut HashMap<String, HostState>, origin: &str, new_check: bool) -> Result<(), Duration> {
+        let now = Instant::now();
+        let state = hosts.entry(origin.to_string()).or_insert_with(|| HostState::new(&self.limits, now));
+        match state.wait(&self.limits, now, new_check) {
+            Some(wait) => Err(wait),
+            None => {
+                state.start(&self.limits, now, new_check);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A check's place among its host's in-flight checks, given up on drop.
+pub struct HostPermit {
+    limiter: Arc<HostLimiter>,
+    origin: Option<String>,
+}
+
+impl Drop for HostPermit {
+    fn drop(&mut self) {
+        let Some(origin) = &self.origin else { return };
+        let mut hosts = self.limiter.hosts.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(state) = hosts.get_mut(origin) {
+            state.in_flight -= 1;
+        }
+        self.limiter.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn limiter(limits: HostLimits) -> Arc<HostLimiter> {
+        Arc::new(HostLimiter::new(limits))
+    }
+
+    /// Starts and immediately finishes `count` checks of `url`, returning how long it took.
+    fn time_checks(limiter: &Arc<HostLimiter>, url: &str, count: usize) -> Duration {
+        let start = Instant::now();
+        for _ in 0..count {
+            drop(limiter.acquire(url, &AtomicBool::new(false)).unwrap());
+        }
+        start.elapsed()
+    }
+
+    #[test]
+    fn test_origin() {
+        assert_eq!(origin("https://example.com/a?b"), "https://example.com");
+        assert_eq!(origin("http://example.com:8080/"), "http://example.com:8080");
+        assert_ne!(origin("http://example.com/"), origin("https://example.com/"));
+        assert_eq!(origin("not a url"), origin("also not a url"));
+        assert_eq!(origin("tcp://db.internal:5432"), "tcp://db.internal:5432");
+        assert_ne!(origin("tcp://db.internal:5432"), origin("tcp://cache.internal:6379"));
+    }
+
+    #[test]
+    fn test_unlimited_does_not_wait() {
+        let limiter = limiter(HostLimits::default());
+        assert!(time_checks(&limiter, "https://example.com/", 100) < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_max_in_flight_per_host() {
+        let limiter = limiter(HostLimits { max_in_flight: Some(1), ..HostLimits::default() });
+        let cancel = AtomicBool::new(false);
+        let held = limiter.acquire("https://example.com/a", &cancel).unwrap();
+
+        // Other hosts are not held up
+        assert!(time_checks(&limiter, "https://example.org/", 1) < Duration::from_millis(50));
+
+        // The waiter reports when it got in, which cannot be before the first check ended
+        let waiter = thread::spawn({
+            let limiter = Arc::clone(&limiter);
+            move || {
+                time_checks(&limiter, "https://example.com/b", 1);
+                Instant::now()
+            }
+        });
+        thread::sleep(Duration::from_millis(100));
+        let released = Instant::now();
+        drop(held);
+        assert!(waiter.join().unwrap() >= released);
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = limiter(HostLimits { rate: Some(10.0), burst: 2, ..HostLimits::default() });
+
+        // Two checks use up the burst, then each further one waits 100ms for a token
+        let elapsed = time_checks(&limiter, "https://example.com/", 4);
+        assert!(elapsed >= Duration::from_millis(180));
+        assert!(elapsed < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_crawl_delay() {
+        let limiter = limiter(HostLimits { crawl_delay: Some(Duration::from_millis(100)), ..HostLimits::default() });
+        assert!(time_checks(&limiter, "https://example.com/", 3) >= Duration::from_millis(200));
+        assert!(time_checks(&limiter, "https://example.org/", 1) < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_try_acquire() {
+        let limiter = limiter(HostLimits { max_in_flight: Some(1), crawl_delay: Some(Duration::from_millis(100)), ..HostLimits::default() });
+        let held = limiter.try_acquire("https://example.com/a").unwrap();
+        assert_eq!(limiter.try_acquire("https://example.com/b").err(), Some(CANCEL_POLL));
+        drop(held);
+
+        let wait = limiter.try_acquire("https://example.com/b").err().unwrap();
+        assert!(wait > Duration::from_millis(50) && wait <= Duration::from_millis(100), "{:?}", wait);
+        assert!(limiter.try_acquire("https://example.org/").is_ok());
+    }
+
+    #[test]
+    fn test_pace_within_a_check() {
+        let limiter = limiter(HostLimits {
+            max_in_flight: Some(1),
+            crawl_delay: Some(Duration::from_millis(100)),
+            ..HostLimits::default()
+        });
+        let cancel = AtomicBool::new(false);
+        let _held = limiter.acquire("https://example.com/", &cancel).unwrap();
+
+        // A retry needs no place of its own, but still keeps to the crawl delay
+        let start = Instant::now();
+        assert!(limiter.pace("https://example.com/", &cancel));
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        assert!(limiter.try_acquire("https://example.com/").is_err());
+
+        cancel.store(true, Ordering::Relaxed);
+        assert!(!limiter.pace("https://example.com/", &cancel));
+        assert!(limiter.pace("https://example.com/", &AtomicBool::new(false)));
+    }
+
+    #[test]
+    fn test_cancel_while_waiting() {
+        let limiter = limiter(HostLimits { max_in_flight: Some(1), ..HostLimits::default() });
+        let cancel = AtomicBool::new(false);
+        let _held = limiter.acquire("https://example.com/", &cancel).unwrap();
+
+        let acquired = thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                cancel.store(true, Ordering::Relaxed);
+            });
+            limiter.acquire("https://example.com/", &cancel).is_some()
+        });
+        assert!(!acquired);
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+
+use crate::http::{HttpError, HttpResponse};
+use crate::request::{Method, RequestSpec};
+
+pub const DEFAULT_MAX_REDIRECTS: u32 = 5;
+
+/// Which redirects a check follows, and which ones fail it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RedirectPolicy {
+    /// Redirects followed before the check fails; `0` returns the first response as it is.
+    pub max_redirects: u32,
+    /// Fail the check instead of following a redirect from HTTPS to plain HTTP.
+    pub deny_downgrade: bool,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        RedirectPolicy { max_redirects: DEFAULT_MAX_REDIRECTS, deny_downgrade: false }
+    }
+}
+
+impl RedirectPolicy {
+    /// Why `chain` breaks this policy, if it does.
+    pub fn violation(&self, chain: &[Redirect]) -> Option<String> {
+        if self.deny_downgrade {
+            if let Some(hop) = chain.iter().find(|hop| hop.is_downgrade()) {
+                return Some(format!("redirect from {} to insecure {}", hop.url, hop.location));
+            }
+        }
+        if self.max_redirects > 0 && chain.len() as u32 > self.max_redirects {
+            return Some(format!("more than {} redirects", self.max_redirects));
+        }
+        None
+    }
+}
+
+/// A redirect response received while checking a target.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Redirect {
+    /// The URL that answered with the redirect.
+    pub url: String,
+    pub status: u16,
+    /// Where it pointed, resolved against `url`.
+    pub location: String,
+}
+
+impl Redirect {
+    pub fn is_downgrade(&self) -> bool {
+        self.url.starts_with("https:") && self.location.starts_with("http:")
+    }
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} -> {}", self.status, self.url, self.location)
+    }
+}
+
+pub fn is_redirect(status: u16) -> bool {
+    matches!(status, 301 | 302 | 303 | 307 | 308)
+}
+
+/// Records `response` in `chain` if it is a redirect, and returns the request that
+/// follows it when `policy` allows. `None` means `response` is the final one.
+pub(crate) fn next_request(
+    request: &RequestSpec,
+    response: &HttpResponse,
+    policy: &RedirectPolicy,
+    chain: &mut Vec<Redirect>,
+) -> Result<Option<RequestSpec>, HttpError> {
+    let location = match response.header("location") {
+        Some(location) if is_redirect(response.status) => location,
+        _ => return Ok(None),
+    };
+
+    let from = url::Url::parse(&request.url).map_err(|err| bad_location(location, err.to_string()))?;
+    let to = from.join(location).map_err(|err| bad_location(location, err.to_string()))?;
+    let hop = Redirect { url: request.url.clone(), status: response.status, location: to.to_string() };
+    let follow = chain.len() < policy.max_redirects as usize && !(policy.deny_downgrade && hop.is_downgrade());
+    chain.push(hop);
+    if !follow {
+        return Ok(None);
+    }
+    if !matches!(to.scheme(), "http" | "https") {
+        return Err(bad_location(location, "not an HTTP URL".to_string()));
+    }
+
+    let mut next = RequestSpec { url: to.to_string(), ..request.clone() };
+    // Browsers turn these into GETs without a body; 307 and 308 repeat the request as it was
+    if matches!(response.status, 301..=303) && request.method != Method::Head {
+        next.method = Method::Get;
+        next.body = None;
+        next.headers.retain(|(name, _)| !name.eq_ignore_ascii_case("Content-Type"));
+    }
+    // Credentials are only sent to the host they were meant for
+    if to.host_str() != from.host_str() {
+        next.headers.retain(|(name, _)| !name.eq_ignore_ascii_case("Authorization") && !name.eq_ignore_ascii_case("Cookie"));
+    }
+    Ok(Some(next))
+}
+
+fn bad_location(location: &str, reason: String) -> HttpError {
+    HttpError { message: format!("Bad redirect location `{}`: {}", location, reason), retryable: false }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Timings;
+    use crate::request::Body;
+
+    fn redirect(status: u16, location: &str) -> HttpResponse {
+        HttpResponse {
+            status,
+            headers: vec![("location".to_string(), location.to_string())],
+            body: None,
+            timings: Timings::default(),
+            redirects: Vec::new(),
+        }
+    }
+
+    fn post(url: &str) -> RequestSpec {
+        RequestSpec {
+            method: Method::Post,
+            url: url.to_string(),
+            headers: vec![
+                ("Authorization".to_string(), "Bearer secret".to_string()),
+                ("Content-Type".to_string(), "text/plain".to_string()),
+            ],
+            body: Some(Body::Text("hello".to_string())),
+            redirects: RedirectPolicy::default(),
+            network: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_follows_relative_locations() {
+        let mut chain = Vec::new();
+        let next = next_request(&post("https://example.com/a/b"), &redirect(307, "../c?x=1"), &RedirectPolicy::default(), &mut chain)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next.url, "https://example.com/c?x=1");
+        assert_eq!(next.method, Method::Post);
+        assert!(next.body.is_some());
+        assert!(next.header("Authorization").is_some());
+        assert_eq!(chain, vec![Redirect { url: "https://example.com/a/b".to_string(), status: 307, location: next.url.clone() }]);
+    }
+
+    #[test]
+    fn test_see_other_becomes_get_and_drops_credentials_across_hosts() {
+        let mut chain = Vec::new();
+        let next = next_request(&post("https://example.com/"), &redirect(303, "https://other.example/"), &RedirectPolicy::default(), &mut chain)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next.method, Method::Get);
+        assert_eq!(next.body, None);
+        assert_eq!(next.header("Content-Type"), None);
+        assert_eq!(next.header("Authorization"), None);
+    }
+
+    #[test]
+    fn test_final_responses() {
+        let mut chain = Vec::new();
+        let request = post("https://example.com/");
+        let policy = RedirectPolicy::default();
+        assert_eq!(next_request(&request, &redirect(200, "/elsewhere"), &policy, &mut chain).unwrap(), None);
+        assert_eq!(next_request(&request, &HttpResponse { headers: Vec::new(), ..redirect(302, "") }, &policy, &mut chain).unwrap(), None);
+        assert!(chain.is_empty());
+
+        // Not following still records the redirect that was not followed
+        let policy = RedirectPolicy { max_redirects: 0, deny_downgrade: false };
+        assert_eq!(next_request(&request, &redirect(301, "/moved"), &policy, &mut chain).unwrap(), None);
+        assert_eq!(chain[0].location, "https://example.com/moved");
+        assert_eq!(policy.violation(&chain), None);
+    }
+
+    #[test]
+    fn test_hop_limit() {
+        let policy = RedirectPolicy { max_redirects: 2, deny_downgrade: false };
+        let mut chain = Vec::new();
+        let mut request = post("http://example.com/0");
+        for hop in 1..=2 {
+            request = next_request(&request, &redirect(302, &format!("/{}", hop)), &policy, &mut chain).unwrap().unwrap();
+        }
+        assert_eq!(policy.violation(&chain), None);
+
+        assert_eq!(next_request(&request, &redirect(302, "/3"), &policy, &mut chain).unwrap(), None);
+        assert_eq!(chain.len(), 3);
+        assert_eq!(policy.violation(&chain).as_deref(), Some("more than 2 redirects"));
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let deny = RedirectPolicy { deny_downgrade: true, ..RedirectPolicy::default() };
+        let mut chain = Vec::new();
+        let request = post("https://example.com/");
+        assert_eq!(next_request(&request, &redirect(301, "http://example.com/"), &deny, &mut chain).unwrap(), None);
+        assert_eq!(
+            deny.violation(&chain).as_deref(),
+            Some("redirect from https://example.com/ to insecure http://example.com/")
+        );
+
+        // Allowed by default, and upgrades are always fine
+        assert_eq!(RedirectPolicy::default().violation(&chain), None);
+        let upgrade = Redirect { url: "http://example.com/".to_string(), status: 301, location: "https://example.com/".to_string() };
+        assert_eq!(deny.violation(&[upgrade]), None);
+    }
+
+    #[test]
+    fn test_bad_locations() {
+        let mut chain = Vec::new();
+        let err = next_request(&post("https://example.com/"), &redirect(302, "ftp://example.com/"), &RedirectPolicy::default(), &mut chain)
+            .unwrap_err();
+        assert!(!err.retryable);
+        assert!(err.message.contains("Bad redirect location"));
+    }
+}
@@ -0,0 +1,183 @@
+//! Swapping the target list of a running monitor without restarting it.
+//!
+//! `diff` works out which targets a new list adds, removes and changes, so the
+//! scheduler only touches those; `FileWatcher` notices when the list is edited.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::Target;
+
+/// How a new target list differs from the one being checked.
+#[derive(Debug, Default)]
+pub struct TargetDiff {
+    pub added: Vec<Target>,
+    pub removed: Vec<Arc<Target>>,
+    /// A current target and its replacement with the same URL but different settings.
+    pub changed: Vec<(Arc<Target>, Target)>,
+    pub unchanged: usize,
+}
+
+impl TargetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for TargetDiff {
+    /// One line, e.g. `1 added, 1 removed, 0 changed, 3 unchanged (+ https://a, - https://b)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} removed, {} changed, {} unchanged",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len(),
+            self.unchanged
+        )?;
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let urls: Vec<String> = self
+            .added
+            .iter()
+            .map(|target| format!("+ {}", target.request.url))
+            .chain(self.removed.iter().map(|target| format!("- {}", target.request.url)))
+            .chain(self.changed.iter().map(|(_, target)| format!("~ {}", target.request.url)))
+            .collect();
+        write!(f, " ({})", urls.join(", "))
+    }
+}
+
+/// Compares the targets being checked with a new list. Identical targets are left
+/// alone; a target whose URL stays but whose settings differ counts as changed.
+/// Duplicate targets are matched one for one.
+pub fn diff(current: &[Arc<Target>], new: Vec<Target>) -> TargetDiff {
+    let mut unmatched: Vec<&Arc<Target>> = current.iter().collect();
+    let mut unchanged = 0;
+    let mut fresh = Vec::new();
+    for target in new {
+        match unmatched.iter().position(|old| ***old == target) {
+            Some(index) => {
+                unmatched.remove(index);
+                unchanged += 1;
+            }
+            None => fresh.push(target),
+        }
+    }
+
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    for target in fresh {
+        match unmatched.iter().position(|old| old.request.url == target.request.url) {
+            Some(index) => changed.push((Arc::clone(unmatched.remove(index)), target)),
+            None => added.push(target),
+        }
+    }
+
+    TargetDiff { added, removed: unmatched.into_iter().cloned().collect(), changed, unchanged }
+}
+
+/// Notices edits to a file by polling its modification time and length.
+pub struct FileWatcher {
+    path: PathBuf,
+    last: Option<(SystemTime, u64)>,
+}
+
+impl FileWatcher {
+    /// Starts watching `path` from its current state.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let last = stamp(&path);
+        FileWatcher { path, last }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file was modified, created or deleted since the last call.
+    pub fn changed(&mut self) -> bool {
+        let stamp = stamp(&self.path);
+        let changed = stamp != self.last;
+        self.last = stamp;
+        changed
+    }
+}
+
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::Config;
+    use std::time::Duration;
+
+    fn targets(urls: &[&str]) -> Vec<Arc<Target>> {
+        urls.iter().map(|url| Arc::new(Target::new(url, &Config::default()))).collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let current = targets(&["https://kept.example", "https://gone.example", "https://slow.example"]);
+        let new = vec![
+            Target::new("https://new.example", &Config::default()),
+            Target { timeout: Duration::from_secs(30), ..Target::new("https://slow.example", &Config::default()) },
+            Target::new("https://kept.example", &Config::default()),
+        ];
+
+        let diff = diff(&current, new);
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.added[0].request.url, "https://new.example");
+        assert!(Arc::ptr_eq(&diff.removed[0], &current[1]));
+        assert!(Arc::ptr_eq(&diff.changed[0].0, &current[2]));
+        assert_eq!(diff.changed[0].1.timeout, Duration::from_secs(30));
+        assert_eq!(
+            diff.to_string(),
+            "1 added, 1 removed, 1 changed, 1 unchanged (+ https://new.example, - https://gone.example, ~ https://slow.example)"
+        );
+    }
+
+    #[test]
+    fn test_diff_matches_duplicates_and_regexes() {
+        let config = Config {
+            criteria: crate::criteria::SuccessCriteria {
+                body_regex: Some(regex::Regex::new("ok|healthy").unwrap()),
+                ..Default::default()
+            },
+            ..Config::default()
+        };
+        let current: Vec<Arc<Target>> = (0..2).map(|_| Arc::new(Target::new("https://a.example", &config))).collect();
+        let diff = diff(&current, vec![Target::new("https://a.example", &config)]);
+
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.to_string(), "0 added, 1 removed, 0 changed, 1 unchanged (- https://a.example)");
+        assert!(super::diff(&current[..1], vec![Target::new("https://a.example", &config)]).is_empty());
+    }
+
+    #[test]
+    fn test_file_watcher() {
+        let path = testing::temp_path("watch.txt");
+        let _ = fs::remove_file(&path);
+        let mut watcher = FileWatcher::new(&path);
+        assert!(!watcher.changed());
+
+        fs::write(&path, "https://a.example\n").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::write(&path, "https://a.example\nhttps://b.example\n").unwrap();
+        assert!(watcher.changed());
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.changed());
+    }
+}
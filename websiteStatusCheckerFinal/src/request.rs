@@ -0,0 +1,246 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::network::NetworkOptions;
+use crate::redirect::RedirectPolicy;
+use crate::Url;
+
+/// HTTP methods a check can use.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Options,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+            Method::Options => "OPTIONS",
+        }
+    }
+
+    /// HEAD responses never carry a body, so there is nothing to download or assert on.
+    pub fn has_response_body(&self) -> bool {
+        *self != Method::Head
+    }
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "GET" => Ok(Method::Get),
+            "HEAD" => Ok(Method::Head),
+            "POST" => Ok(Method::Post),
+            "PUT" => Ok(Method::Put),
+            "PATCH" => Ok(Method::Patch),
+            "DELETE" => Ok(Method::Delete),
+            "OPTIONS" => Ok(Method::Options),
+            _ => Err(format!("unsupported method `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A request body.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Body {
+    Text(String),
+    /// Sent with `Content-Type: application/json` unless the headers say otherwise.
+    Json(serde_json::Value),
+}
+
+impl Body {
+    pub(crate) fn content_type(&self) -> Option<&'static str> {
+        match self {
+            Body::Text(_) => None,
+            Body::Json(_) => Some("application/json"),
+        }
+    }
+}
+
+impl fmt::Display for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Body::Text(text) => f.write_str(text),
+            Body::Json(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Everything needed to make the request for a check.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestSpec {
+    pub method: Method,
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Body>,
+    pub redirects: RedirectPolicy,
+    /// How the request reaches the host.
+    pub network: NetworkOptions,
+}
+
+impl RequestSpec {
+    /// Sets a header, replacing any existing header with the same name.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Checks combinations that cannot produce a meaningful request, and headers
+    /// that cannot be sent as written.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.method == Method::Head && self.body.is_some() {
+            return Err("HEAD requests cannot have a body".to_string());
+        }
+        for (name, value) in &self.headers {
+            validate_header(name, value)?;
+        }
+        Ok(())
+    }
+
+    // ureq's error type is large, but it is returned once per attempt
+    #[allow(clippy::result_large_err)]
+    pub fn send(&self, agent: &ureq::Agent, timeout: Duration) -> Result<ureq::Response, ureq::Error> {
+        let mut request = agent.request(self.method.as_str(), &self.url).timeout(timeout);
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+
+        match &self.body {
+            Some(body) => {
+                if let (Some(content_type), None) = (body.content_type(), self.header("Content-Type")) {
+                    request = request.set("Content-Type", content_type);
+                }
+                request.send_string(&body.to_string())
+            }
+            None => request.call(),
+        }
+    }
+}
+
+/// Parses a header written as `Name: value`.
+pub fn parse_header(s: &str) -> Result<(String, String), String> {
+    match s.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            let (name, value) = (name.trim(), value.trim());
+            validate_header(name, value)?;
+            Ok((name.to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected `Name: value`, got `{}`", s)),
+    }
+}
+
+/// Checks that `name` is a valid field name (an RFC 9110 token) and that `value`
+/// has no CR, LF or NUL, which would let it end its line and inject headers or a
+/// whole second request.
+pub fn validate_header(name: &str, value: &str) -> Result<(), String> {
+    let is_token = |byte: u8| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte);
+    if name.is_empty() || !name.bytes().all(is_token) {
+        return Err(format!("invalid header name `{}`", name.escape_debug()));
+    }
+    if value.contains(['\r', '\n', '\0']) {
+        return Err(format!("header `{}` has a line break or NUL in its value", name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(url: &str) -> RequestSpec {
+        RequestSpec {
+            method: Method::Get,
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+            redirects: RedirectPolicy::default(),
+            network: NetworkOptions::default(),
+        }
+    }
+
+    #[test]
+    fn test_parse_method() {
+        assert_eq!("head".parse(), Ok(Method::Head));
+        assert_eq!("POST".parse(), Ok(Method::Post));
+        assert!("FETCH".parse::<Method>().is_err());
+        assert_eq!(Method::Options.to_string(), "OPTIONS");
+    }
+
+    #[test]
+    fn test_set_header_replaces_case_insensitively() {
+        let mut spec = get("https://example.com");
+        spec.set_header("User-Agent", "first");
+        spec.set_header("user-agent", "second");
+        assert_eq!(spec.headers, vec![("user-agent".to_string(), "second".to_string())]);
+        assert_eq!(spec.header("USER-AGENT"), Some("second"));
+    }
+
+    #[test]
+    fn test_head_with_body_is_invalid() {
+        let spec = RequestSpec {
+            method: Method::Head,
+            body: Some(Body::Text("x".to_string())),
+            ..get("https://example.com")
+        };
+        assert!(spec.validate().is_err());
+        assert!(get("https://example.com").validate().is_ok());
+    }
+
+    #[test]
+    fn test_json_body() {
+        let body = Body::Json(serde_json::json!({ "deep": true }));
+        assert_eq!(body.to_string(), r#"{"deep":true}"#);
+        assert_eq!(body.content_type(), Some("application/json"));
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(parse_header("Authorization: Bearer a:b"), Ok(("Authorization".to_string(), "Bearer a:b".to_string())));
+        assert!(parse_header("missing").is_err());
+        assert!(parse_header("X: a\r\nHost: evil").is_err());
+        assert!(parse_header("Bad Name: a").is_err());
+        assert!(parse_header("X(1): a").is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_unsendable_headers() {
+        let mut spec = get("https://example.com");
+        spec.set_header("X-Trace", "a\r\nHost: evil");
+        assert!(spec.validate().unwrap_err().contains("line break"));
+
+        let mut spec = get("https://example.com");
+        spec.set_header("X\nInjected", "a");
+        assert!(spec.validate().unwrap_err().contains("invalid header name"));
+
+        let mut spec = get("https://example.com");
+        spec.set_header("X-Trace", "a\0b");
+        assert!(spec.validate().is_err());
+    }
+}
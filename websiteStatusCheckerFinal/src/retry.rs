@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use clap::ValueEnum;
+use ureq::ErrorKind;
+
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(500);
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// How the delay between attempts grows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Backoff {
+    /// The same delay before every retry
+    Fixed,
+    /// Double the delay before each retry
+    Exponential,
+    /// Exponential, with a random delay between zero and the exponential value
+    Jitter,
+}
+
+/// Controls how many times a failed check is retried and how long to wait in between.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt, so `0` means a single attempt.
+    pub max_retries: u8,
+    pub backoff: Backoff,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u8) -> Self {
+        RetryPolicy {
+            max_retries,
+            backoff: Backoff::Jitter,
+            base_delay: DEFAULT_RETRY_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+        }
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_retries as u32 + 1
+    }
+
+    /// The delay to wait before the given retry (`1` for the first retry).
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exponential = || {
+            let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+            self.base_delay.saturating_mul(factor).min(self.max_delay)
+        };
+
+        match self.backoff {
+            Backoff::Fixed => self.base_delay.min(self.max_delay),
+            Backoff::Exponential => exponential(),
+            Backoff::Jitter => {
+                let ceiling = exponential().as_millis() as u64;
+                Duration::from_millis(fastrand::u64(0..=ceiling))
+            }
+        }
+    }
+}
+
+/// Whether a failed request is worth retrying.
+///
+/// Connection problems, timeouts and 5xx responses are usually transient; a 4xx
+/// response or a malformed URL will fail the same way every time.
+pub fn is_retryable(err: &ureq::Error) -> bool {
+    match err {
+        ureq::Error::Status(code, _) => is_retryable_status(*code),
+        ureq::Error::Transport(transport) => matches!(
+            transport.kind(),
+            ErrorKind::Dns | ErrorKind::ConnectionFailed | ErrorKind::Io | ErrorKind::ProxyConnect
+        ),
+    }
+}
+
+/// Server errors are worth retrying; client errors are not.
+pub fn is_retryable_status(code: u16) -> bool {
+    code >= 500
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(backoff: Backoff) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 5,
+            backoff,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(350),
+        }
+    }
+
+    #[test]
+    fn test_max_attempts() {
+        assert_eq!(RetryPolicy::new(0).max_attempts(), 1);
+        assert_eq!(RetryPolicy::new(3).max_attempts(), 4);
+    }
+
+    #[test]
+    fn test_fixed_delay() {
+        let policy = policy(Backoff::Fixed);
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(4), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_exponential_delay_is_capped() {
+        let policy = policy(Backoff::Exponential);
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(350));
+        assert_eq!(policy.delay(200), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn test_jitter_stays_within_bounds() {
+        let jitter = policy(Backoff::Jitter);
+        let exponential = policy(Backoff::Exponential);
+        for retry in 1..10 {
+            let ceiling = exponential.delay(retry);
+            for _ in 0..50 {
+                assert!(jitter.delay(retry) <= ceiling);
+            }
+        }
+    }
+
+    #[test]
+    fn test_status_errors() {
+        let server_error = ureq::Error::Status(503, ureq::Response::new(503, "Service Unavailable", "").unwrap());
+        let not_found = ureq::Error::Status(404, ureq::Response::new(404, "Not Found", "").unwrap());
+        assert!(is_retryable(&server_error));
+        assert!(!is_retryable(&not_found));
+    }
+
+    #[test]
+    fn test_transport_errors() {
+        let refused = ureq::get("http://127.0.0.1:1").call().unwrap_err();
+        let invalid = ureq::get("not a url").call().unwrap_err();
+        assert!(is_retryable(&refused));
+        assert!(!is_retryable(&invalid));
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+struct Entry<T> {
+    item: T,
+    interval: Duration,
+    next_due: Instant,
+}
+
+/// Keeps track of when each target is next due for a check in monitoring mode.
+pub struct Scheduler<T> {
+    entries: Vec<Entry<T>>,
+}
+
+impl<T> Default for Scheduler<T> {
+    fn default() -> Self {
+        Scheduler { entries: Vec::new() }
+    }
+}
+
+impl<T: Clone> Scheduler<T> {
+    pub fn new() -> Self {
+        Scheduler { entries: Vec::new() }
+    }
+
+    /// Schedules `item` every `interval`, with the first check due at `start`.
+    pub fn add(&mut self, item: T, interval: Duration, start: Instant) {
+        self.entries.push(Entry {
+            item,
+            interval,
+            next_due: start,
+        });
+    }
+
+    /// Unschedules every item for which `keep` returns `false`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        self.entries.retain(|entry| keep(&entry.item));
+    }
+
+    /// Returns the items due at `now` and moves each of them to its next slot.
+    ///
+    /// An item that fell several intervals behind is returned once, not once per
+    /// missed slot, so a stalled pool does not cause a burst of duplicate checks.
+    pub fn due(&mut self, now: Instant) -> Vec<T> {
+        let mut due = Vec::new();
+
+        for entry in &mut self.entries {
+            if entry.next_due <= now {
+                due.push(entry.item.clone());
+                entry.next_due += entry.interval;
+                if entry.next_due <= now {
+                    entry.next_due = now + entry.interval;
+                }
+            }
+        }
+
+        due
+    }
+
+    /// The earliest instant at which any item becomes due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.entries.iter().map(|entry| entry.next_due).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_due_respects_per_url_interval() {
+        let start = Instant::now();
+        let mut scheduler = Scheduler::new();
+        scheduler.add("fast".to_string(), Duration::from_secs(1), start);
+        scheduler.add("slow".to_string(), Duration::from_secs(3), start);
+
+        assert_eq!(scheduler.due(start), vec!["fast", "slow"]);
+        assert!(scheduler.due(start).is_empty());
+        assert_eq!(scheduler.next_due(), Some(start + Duration::from_secs(1)));
+
+        assert_eq!(scheduler.due(start + Duration::from_secs(1)), vec!["fast"]);
+        assert_eq!(scheduler.due(start + Duration::from_secs(2)), vec!["fast"]);
+        assert_eq!(scheduler.due(start + Duration::from_secs(3)), vec!["fast", "slow"]);
+    }
+
+    #[test]
+    fn test_missed_slots_are_not_replayed() {
+        let start = Instant::now();
+        let mut scheduler = Scheduler::new();
+        scheduler.add("url".to_string(), Duration::from_secs(1), start);
+
+        assert_eq!(scheduler.due(start).len(), 1);
+        assert_eq!(scheduler.due(start + Duration::from_secs(10)).len(), 1);
+        assert!(scheduler.due(start + Duration::from_millis(10_500)).is_empty());
+        assert_eq!(scheduler.next_due(), Some(start + Duration::from_secs(11)));
+    }
+
+    #[test]
+    fn test_retain() {
+        let start = Instant::now();
+        let mut scheduler = Scheduler::new();
+        scheduler.add("kept".to_string(), Duration::from_secs(1), start);
+        scheduler.add("removed".to_string(), Duration::from_secs(1), start);
+
+        scheduler.retain(|item| item != "removed");
+        assert_eq!(scheduler.due(start), vec!["kept"]);
+    }
+
+    #[test]
+    fn test_empty_scheduler() {
+        let mut scheduler: Scheduler<String> = Scheduler::new();
+        assert!(scheduler.next_due().is_none());
+        assert!(scheduler.due(Instant::now()).is_empty());
+    }
+}
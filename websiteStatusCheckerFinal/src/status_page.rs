@@ -0,0 +1,556 @@
+//! A public status page: an HTML overview and a JSON API of each target's current
+//! state, recent checks and incidents, grouped by target tag.
+//!
+//! Feed a `StatusBoard` every result, optionally after replaying the history file, and
+//! `serve` it:
+//!
+//! - `GET /` is the HTML page, which refreshes itself.
+//! - `GET /api/status` is every group with its targets' current and recent states.
+//! - `GET /api/incidents` is every incident, newest first.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Write as _;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::metrics::{serve_routes, Reply};
+use crate::output::StatusRecord;
+use crate::{Target, Url, WebsiteStatus};
+
+/// Checks kept per target for its history.
+pub const RECENT_CHECKS: usize = 90;
+/// Incidents kept per target; older ones are forgotten.
+pub const MAX_INCIDENTS: usize = 50;
+/// How often the HTML page reloads itself, in seconds.
+const REFRESH_SECS: u32 = 30;
+
+/// One check in a target's history.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Check {
+    /// RFC 3339 timestamp in UTC.
+    pub timestamp: String,
+    pub ok: bool,
+    pub status_code: Option<u16>,
+    pub response_time_ms: u64,
+    pub error: Option<String>,
+}
+
+/// A run of failing checks, from the first failure to the next passing check.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Incident {
+    pub name: String,
+    pub url: Url,
+    pub started: String,
+    /// When the target passed again; `None` while the incident is ongoing.
+    pub resolved: Option<String>,
+    pub duration_secs: Option<u64>,
+    pub failed_checks: u64,
+    /// Why the first failing check failed.
+    pub reason: String,
+}
+
+/// A target's current state and history.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SiteStatus {
+    pub name: String,
+    pub url: Url,
+    pub tags: Vec<String>,
+    /// `up`, `down`, or `pending` before the first check.
+    pub state: &'static str,
+    pub last_check: Option<Check>,
+    pub checks: u64,
+    pub uptime_percent: Option<f64>,
+    /// The latest checks, oldest first.
+    pub history: Vec<Check>,
+    /// Newest first.
+    pub incidents: Vec<Incident>,
+}
+
+/// The targets sharing a tag. Untagged targets form a group without one.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Group {
+    pub tag: Option<String>,
+    pub up: usize,
+    pub down: usize,
+    pub sites: Vec<SiteStatus>,
+}
+
+/// Everything on the status page.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct StatusSummary {
+    pub generated_at: String,
+    /// Distinct targets, whatever groups they are in.
+    pub up: usize,
+    pub down: usize,
+    pub groups: Vec<Group>,
+}
+
+struct OpenIncident {
+    started: SystemTime,
+    resolved: Option<SystemTime>,
+    failed_checks: u64,
+    reason: String,
+}
+
+struct Site {
+    name: String,
+    tags: Vec<String>,
+    checks: u64,
+    passes: u64,
+    recent: VecDeque<(SystemTime, Check)>,
+    incidents: VecDeque<OpenIncident>,
+}
+
+impl Site {
+    fn new(name: &str, tags: &[String]) -> Self {
+        Site {
+            name: name.to_string(),
+            tags: tags.to_vec(),
+            checks: 0,
+            passes: 0,
+            recent: VecDeque::with_capacity(RECENT_CHECKS),
+            incidents: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, time: SystemTime, check: Check) {
+        self.checks += 1;
+        let ongoing = self.incidents.back_mut().filter(|incident| incident.resolved.is_none());
+        if check.ok {
+            self.passes += 1;
+            if let Some(incident) = ongoing {
+                incident.resolved = Some(time);
+            }
+        } else if let Some(incident) = ongoing {
+            incident.failed_checks += 1;
+        } else {
+            if self.incidents.len() == MAX_INCIDENTS {
+                self.incidents.pop_front();
+            }
+            self.incidents.push_back(OpenIncident {
+                started: time,
+                resolved: None,
+                failed_checks: 1,
+                reason: check.error.clone().unwrap_or_else(|| "check failed".to_string()),
+            });
+        }
+
+        if self.recent.len() == RECENT_CHECKS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((time, check));
+    }
+
+    fn status(&self, url: &str) -> SiteStatus {
+        let last_check = self.recent.back().map(|(_, check)| check.clone());
+        let state = match &last_check {
+            None => "pending",
+            Some(check) if check.ok => "up",
+            Some(_) => "down",
+        };
+
+        SiteStatus {
+            name: self.name.clone(),
+            url: url.to_string(),
+            tags: self.tags.clone(),
+            state,
+            last_check,
+            checks: self.checks,
+            uptime_percent: (self.checks > 0).then(|| self.passes as f64 * 100.0 / self.checks as f64),
+            history: self.recent.iter().map(|(_, check)| check.clone()).collect(),
+            incidents: self.incidents.iter().rev().map(|incident| self.incident(url, incident)).collect(),
+        }
+    }
+
+    fn incident(&self, url: &str, incident: &OpenIncident) -> Incident {
+        Incident {
+            name: self.name.clone(),
+            url: url.to_string(),
+            started: timestamp(incident.started),
+            resolved: incident.resolved.map(timestamp),
+            duration_secs: incident
+                .resolved
+                .map(|resolved| resolved.duration_since(incident.started).unwrap_or_default().as_secs()),
+            failed_checks: incident.failed_checks,
+            reason: incident.reason.clone(),
+        }
+    }
+}
+
+/// Check results kept for the status page.
+#[derive(Default)]
+pub struct StatusBoard {
+    sites: Mutex<BTreeMap<Url, Site>>,
+    /// URLs dropped by `set_targets`, whose checks still in flight are ignored.
+    removed: Mutex<BTreeSet<Url>>,
+}
+
+impl StatusBoard {
+    /// A board listing each of `targets` as pending until its first result.
+    pub fn new(targets: &[Target]) -> Self {
+        let sites = targets
+            .iter()
+            .map(|target| (target.request.url.clone(), Site::new(&target.name, &target.tags)))
+            .collect();
+        StatusBoard { sites: Mutex::new(sites), removed: Mutex::default() }
+    }
+
+    /// Follows a reloaded target list: new targets are listed as pending, kept ones
+    /// take their new name and tags, and removed ones are dropped with their history.
+    pub fn set_targets(&self, targets: &[Target]) {
+        let mut sites = self.sites.lock().unwrap();
+        let mut kept = BTreeMap::new();
+        for target in targets {
+            if kept.contains_key(&target.request.url) {
+                continue;
+            }
+            let mut site = sites.remove(&target.request.url).unwrap_or_else(|| Site::new(&target.name, &target.tags));
+            site.name = target.name.clone();
+            site.tags = target.tags.clone();
+            kept.insert(target.request.url.clone(), site);
+        }
+
+        let mut removed = self.removed.lock().unwrap();
+        removed.extend(sites.keys().cloned());
+        removed.retain(|url| !kept.contains_key(url));
+        *sites = kept;
+    }
+
+    pub fn record(&self, status: &WebsiteStatus) {
+        if !self.removed.lock().unwrap().contains(&status.url) {
+            self.record_history(&StatusRecord::from(status));
+        }
+    }
+
+    /// Adds a result read back from the history file. Records should be replayed
+    /// oldest first, before new results arrive; unparseable timestamps are skipped.
+    pub fn record_history(&self, record: &StatusRecord) {
+        let Ok(time) = humantime::parse_rfc3339(&record.timestamp) else {
+            return;
+        };
+        let check = Check {
+            timestamp: timestamp(time),
+            ok: record.ok,
+            status_code: record.status_code,
+            response_time_ms: record.response_time_ms,
+            error: record.error.clone(),
+        };
+
+        let mut sites = self.sites.lock().unwrap();
+        // Targets removed from the input file still show up from their history
+        sites.entry(record.url.clone()).or_insert_with(|| Site::new(&record.name, &[])).push(time, check);
+    }
+
+    pub fn summary(&self) -> StatusSummary {
+        let sites = self.sites.lock().unwrap();
+        let mut groups: BTreeMap<Option<&str>, Vec<SiteStatus>> = BTreeMap::new();
+        let (mut up, mut down) = (0, 0);
+
+        for (url, site) in sites.iter() {
+            let status = site.status(url);
+            match status.state {
+                "up" => up += 1,
+                "down" => down += 1,
+                _ => {}
+            }
+            if site.tags.is_empty() {
+                groups.entry(None).or_default().push(status);
+            } else {
+                for tag in &site.tags {
+                    groups.entry(Some(tag.as_str())).or_default().push(status.clone());
+                }
+            }
+        }
+
+        // Tagged groups first, in tag order, then the untagged targets
+        let mut groups: Vec<Group> = groups
+            .into_iter()
+            .map(|(tag, sites)| Group {
+                tag: tag.map(str::to_string),
+                up: sites.iter().filter(|site| site.state == "up").count(),
+                down: sites.iter().filter(|site| site.state == "down").count(),
+                sites,
+            })
+            .collect();
+        if groups.first().is_some_and(|group| group.tag.is_none()) {
+            groups.rotate_left(1);
+        }
+
+        StatusSummary { generated_at: timestamp(SystemTime::now()), up, down, groups }
+    }
+
+    /// Every target's incidents, newest first.
+    pub fn incidents(&self) -> Vec<Incident> {
+        let sites = self.sites.lock().unwrap();
+        let mut incidents: Vec<(SystemTime, Incident)> = sites
+            .iter()
+            .flat_map(|(url, site)| site.incidents.iter().map(move |incident| (incident.started, site.incident(url, incident))))
+            .collect();
+        incidents.sort_by_key(|(started, _)| Reverse(*started));
+        incidents.into_iter().map(|(_, incident)| incident).collect()
+    }
+
+    /// Renders the status page as a self-contained HTML document.
+    pub fn render_html(&self) -> String {
+        let summary = self.summary();
+        let incidents = self.incidents();
+        let mut out = String::new();
+
+        let _ = write!(
+            out,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <meta http-equiv=\"refresh\" content=\"{}\">\n<title>Status</title>\n<style>{}</style>\n</head>\n<body>\n",
+            REFRESH_SECS, STYLE
+        );
+        let banner = match summary.down {
+            0 => ("ok", "All systems operational".to_string()),
+            down => ("down", format!("{} of {} sites down", down, summary.up + summary.down)),
+        };
+        let _ = writeln!(out, "<h1 class=\"banner {}\">{}</h1>", banner.0, banner.1);
+
+        for group in &summary.groups {
+            let title = group.tag.as_deref().unwrap_or("Other");
+            let _ = writeln!(out, "<section>\n<h2>{}</h2>\n<table>", escape(title));
+            for site in &group.sites {
+                let uptime = site.uptime_percent.map_or("-".to_string(), |uptime| format!("{:.2}%", uptime));
+                let _ = write!(
+                    out,
+                    "<tr><td class=\"state {}\">{}</td><td><a href=\"{}\">{}</a></td><td>{}</td><td class=\"bar\">",
+                    site.state,
+                    site.state,
+                    escape(&site.url),
+                    escape(&site.name),
+                    uptime
+                );
+                for check in &site.history {
+                    let _ = write!(
+                        out,
+                        "<span class=\"{}\" title=\"{}\"></span>",
+                        if check.ok { "up" } else { "down" },
+                        escape(&check_title(check))
+                    );
+                }
+                let error = site.last_check.as_ref().and_then(|check| check.error.as_deref()).unwrap_or("");
+                let _ = writeln!(out, "</td><td class=\"error\">{}</td></tr>", escape(error));
+            }
+            out.push_str("</table>\n</section>\n");
+        }
+
+        out.push_str("<section>\n<h2>Incidents</h2>\n");
+        if incidents.is_empty() {
+            out.push_str("<p>No incidents recorded.</p>\n");
+        } else {
+            out.push_str("<ul class=\"incidents\">\n");
+            for incident in &incidents {
+                let until = match (&incident.resolved, incident.duration_secs) {
+                    (Some(resolved), Some(secs)) => format!("resolved {} after {}s", resolved, secs),
+                    _ => "ongoing".to_string(),
+                };
+                let _ = writeln!(
+                    out,
+                    "<li><strong>{}</strong> {} - {} ({} failed checks): {}</li>",
+                    escape(&incident.name),
+                    incident.started,
+                    until,
+                    incident.failed_checks,
+                    escape(&incident.reason)
+                );
+            }
+            out.push_str("</ul>\n");
+        }
+        let _ = write!(out, "</section>\n<footer>Updated {}</footer>\n</body>\n</html>\n", summary.generated_at);
+        out
+    }
+}
+
+const STYLE: &str = "body{font-family:sans-serif;max-width:960px;margin:2em auto;color:#222}\
+    .banner{padding:.6em;border-radius:4px;color:#fff}.banner.ok{background:#2e7d32}.banner.down{background:#c62828}\
+    table{width:100%;border-collapse:collapse}td{padding:.3em;border-bottom:1px solid #eee}\
+    .state{font-weight:bold;text-transform:uppercase}.state.up{color:#2e7d32}.state.down{color:#c62828}.state.pending{color:#999}\
+    .bar span{display:inline-block;width:4px;height:16px;margin-right:1px}.bar .up{background:#4caf50}.bar .down{background:#e53935}\
+    .error{color:#c62828}footer{color:#999;margin-top:2em}";
+
+fn check_title(check: &Check) -> String {
+    let outcome = match (check.status_code, &check.error) {
+        (_, Some(error)) => error.clone(),
+        (Some(code), None) => code.to_string(),
+        (None, None) => "ok".to_string(),
+    };
+    format!("{} {} ({} ms)", check.timestamp, outcome, check.response_time_ms)
+}
+
+fn timestamp(time: SystemTime) -> String {
+    // Whole seconds read better on the page than the history file's milliseconds
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    humantime::format_rfc3339_seconds(UNIX_EPOCH + std::time::Duration::from_secs(secs)).to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Serves the status page and its JSON API on `listener` from a background thread.
+pub fn serve(listener: TcpListener, board: Arc<StatusBoard>) {
+    serve_routes(listener, "Status page", move |path| match path {
+        "/" | "/index.html" => Some(Reply::ok("text/html; charset=utf-8", board.render_html())),
+        "/api/status" => Some(Reply::ok("application/json", json(&board.summary()))),
+        "/api/incidents" => Some(Reply::ok("application/json", json(&board.incidents()))),
+        _ => None,
+    });
+}
+
+fn json(value: &impl Serialize) -> String {
+    serde_json::to_string(value).expect("status page values serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, tagged_target};
+    use std::time::Duration;
+
+    fn status(url: &str, secs: u64, code: u16) -> WebsiteStatus {
+        WebsiteStatus {
+            response_time: Duration::from_millis(40),
+            total_time: Duration::from_millis(40),
+            timestamp: UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs),
+            ..testing::status(url, Ok(code))
+        }
+    }
+
+    fn board() -> StatusBoard {
+        StatusBoard::new(&[
+            tagged_target("https://a.example.com", &["web", "api"]),
+            tagged_target("https://b.example.com", &["web"]),
+            tagged_target("https://c.example.com", &[]),
+        ])
+    }
+
+    #[test]
+    fn test_incidents() {
+        let board = board();
+        for (secs, code) in [(0, 200), (60, 503), (120, 502), (180, 200), (240, 500)] {
+            board.record(&status("https://a.example.com", secs, code));
+        }
+
+        let incidents = board.incidents();
+        assert_eq!(incidents.len(), 2);
+        assert_eq!(incidents[0].started, "2023-11-14T22:17:20Z");
+        assert_eq!(incidents[0].resolved, None);
+        assert_eq!(incidents[1].started, "2023-11-14T22:14:20Z");
+        assert_eq!(incidents[1].resolved.as_deref(), Some("2023-11-14T22:16:20Z"));
+        assert_eq!(incidents[1].duration_secs, Some(120));
+        assert_eq!(incidents[1].failed_checks, 2);
+        assert_eq!(incidents[1].reason, "unexpected status code 503");
+    }
+
+    #[test]
+    fn test_summary_groups_by_tag() {
+        let board = board();
+        board.record(&status("https://a.example.com", 0, 200));
+        board.record(&status("https://a.example.com", 60, 200));
+        board.record(&status("https://b.example.com", 0, 503));
+
+        let summary = board.summary();
+        assert_eq!((summary.up, summary.down), (1, 1));
+        let tags: Vec<Option<&str>> = summary.groups.iter().map(|group| group.tag.as_deref()).collect();
+        assert_eq!(tags, [Some("api"), Some("web"), None]);
+
+        let web = &summary.groups[1];
+        assert_eq!((web.up, web.down), (1, 1));
+        assert_eq!(web.sites[0].history.len(), 2);
+        assert_eq!(web.sites[0].uptime_percent, Some(100.0));
+        assert_eq!(web.sites[1].state, "down");
+        assert_eq!(web.sites[1].last_check.as_ref().unwrap().status_code, Some(503));
+        assert_eq!(summary.groups[2].sites[0].state, "pending");
+    }
+
+    #[test]
+    fn test_history_is_replayed_and_capped() {
+        let board = board();
+        for secs in 0..RECENT_CHECKS as u64 + 10 {
+            let record = StatusRecord::from(&status("https://gone.example.com", secs, 200));
+            board.record_history(&record);
+        }
+        board.record_history(&StatusRecord { timestamp: "yesterday".to_string(), ..StatusRecord::from(&status("https://a.example.com", 0, 200)) });
+
+        let summary = board.summary();
+        let other = summary.groups.last().unwrap();
+        let gone = other.sites.iter().find(|site| site.url == "https://gone.example.com").unwrap();
+        assert_eq!(gone.checks, RECENT_CHECKS as u64 + 10);
+        assert_eq!(gone.history.len(), RECENT_CHECKS);
+        assert_eq!(summary.groups[0].sites[0].state, "pending");
+    }
+
+    #[test]
+    fn test_set_targets_follows_reloads() {
+        let board = board();
+        board.record(&status("https://a.example.com", 0, 200));
+        board.record(&status("https://b.example.com", 0, 503));
+        board.set_targets(&[
+            Target { name: "A".to_string(), ..tagged_target("https://a.example.com", &["api"]) },
+            tagged_target("https://d.example.com", &["db"]),
+        ]);
+        // A check of a removed target that was already running when it was removed
+        board.record(&status("https://b.example.com", 60, 503));
+
+        let summary = board.summary();
+        let sites: Vec<(&str, &str, &str)> = summary
+            .groups
+            .iter()
+            .flat_map(|group| group.sites.iter().map(move |site| (group.tag.as_deref().unwrap(), site.name.as_str(), site.state)))
+            .collect();
+        assert_eq!(sites, [("api", "A", "up"), ("db", "https://d.example.com", "pending")]);
+        assert!(board.incidents().is_empty());
+
+        // A target that comes back starts afresh
+        board.set_targets(&[tagged_target("https://b.example.com", &[])]);
+        board.record(&status("https://b.example.com", 120, 200));
+        assert_eq!(board.summary().groups[0].sites[0].checks, 1);
+    }
+
+    #[test]
+    fn test_render_html_escapes() {
+        let board = StatusBoard::new(&[Target { name: "<Home>".to_string(), ..tagged_target("https://a.example.com", &[]) }]);
+        board.record(&status("https://a.example.com", 0, 503));
+
+        let html = board.render_html();
+        assert!(html.contains("1 of 1 sites down"));
+        assert!(html.contains("&lt;Home&gt;"));
+        assert!(!html.contains("<Home>"));
+        assert!(html.contains("<h2>Other</h2>"));
+        assert!(html.contains("ongoing (1 failed checks): unexpected status code 503"));
+    }
+
+    #[test]
+    fn test_serve() {
+        let board = Arc::new(board());
+        board.record(&status("https://a.example.com", 0, 200));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        serve(listener, Arc::clone(&board));
+
+        let page = ureq::get(&format!("{}/", base)).call().unwrap();
+        assert_eq!(page.content_type(), "text/html");
+        assert!(page.into_string().unwrap().contains("All systems operational"));
+
+        let get_json = |path: &str| -> serde_json::Value {
+            let body = ureq::get(&format!("{}{}", base, path)).call().unwrap().into_string().unwrap();
+            serde_json::from_str(&body).unwrap()
+        };
+        let api = get_json("/api/status?fresh=1");
+        assert_eq!(api["up"], 1);
+        assert_eq!(api["groups"][0]["sites"][0]["url"], "https://a.example.com");
+        assert_eq!(get_json("/api/incidents"), serde_json::json!([]));
+
+        match ureq::get(&format!("{}/metrics", base)).call() {
+            Err(ureq::Error::Status(code, _)) => assert_eq!(code, 404),
+            _ => panic!("expected a 404"),
+        }
+    }
+}
@@ -0,0 +1,471 @@
+//! Test doubles for the HTTP layer: a scripted `HttpClient`, an HTTP server on
+//! localhost and proxies that stand in for real ones in front of it. Also the
+//! fixtures that tests across the crate share.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::criteria::{FailureReason, Verdict};
+use crate::http::{HttpClient, HttpError, HttpResponse, Timings};
+use crate::request::RequestSpec;
+use crate::retry::{Backoff, RetryPolicy};
+use crate::tls::TlsInfo;
+use crate::{Config, Target, WebsiteStatus};
+
+/// A result for `url` that got `outcome`, judged as the default criteria judge it,
+/// with a 10 ms response taken at 2023-11-14T22:13:20Z. Tests change the rest with
+/// struct update syntax.
+pub fn status(url: &str, outcome: Result<u16, &str>) -> WebsiteStatus {
+    let verdict = match outcome {
+        Ok(code) if (200..400).contains(&code) => Verdict::Pass,
+        Ok(code) => Verdict::Fail(FailureReason::UnexpectedStatus(code)),
+        Err(err) => Verdict::Fail(FailureReason::Request(err.to_string())),
+    };
+    WebsiteStatus {
+        name: url.to_string(),
+        url: url.to_string(),
+        status: outcome.map(Some).map_err(str::to_string),
+        verdict,
+        response_time: Duration::from_millis(10),
+        total_time: Duration::from_millis(10),
+        attempts: Vec::new(),
+        timestamp: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        timings: Timings::default(),
+        redirects: Vec::new(),
+        detail: None,
+        tls: None,
+        tls_warning: None,
+        content_hash: None,
+    }
+}
+
+/// A target for `url` with the default config and `tags`.
+pub fn tagged_target(url: &str, tags: &[&str]) -> Target {
+    Target { tags: tags.iter().map(|tag| tag.to_string()).collect(), ..Target::new(url, &Config::default()) }
+}
+
+/// A short fixed backoff so retry tests stay fast.
+pub fn quick_retries(max_retries: u8) -> RetryPolicy {
+    RetryPolicy { max_retries, backoff: Backoff::Fixed, base_delay: Duration::from_millis(10), max_delay: Duration::from_millis(10) }
+}
+
+/// A path in the temp directory that no other test process uses. Tests in this
+/// process keep apart by passing different names.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("status-checker-{}-{}", std::process::id(), name))
+}
+
+/// An `HttpClient` that returns scripted results in order, without touching the network.
+pub struct FakeClient {
+    results: Mutex<VecDeque<Result<HttpResponse, HttpError>>>,
+    requests: Mutex<Vec<RequestSpec>>,
+    delay: Duration,
+    tls: Option<Result<TlsInfo, String>>,
+}
+
+impl FakeClient {
+    pub fn new(results: Vec<Result<HttpResponse, HttpError>>) -> Self {
+        FakeClient { results: Mutex::new(results.into()), requests: Mutex::new(Vec::new()), delay: Duration::ZERO, tls: None }
+    }
+
+    /// Makes every request take `delay`, as a slow server would.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Reports `inspection` for every TLS inspection, instead of failing them.
+    pub fn with_tls(mut self, inspection: Result<TlsInfo, String>) -> Self {
+        self.tls = Some(inspection);
+        self
+    }
+
+    /// A response with the given status and no headers or body.
+    pub fn status(status: u16) -> Result<HttpResponse, HttpError> {
+        Ok(HttpResponse { status, headers: Vec::new(), body: None, timings: Timings::default(), redirects: Vec::new() })
+    }
+
+    pub fn error(message: &str, retryable: bool) -> Result<HttpResponse, HttpError> {
+        Err(HttpError { message: message.to_string(), retryable })
+    }
+
+    /// The requests made so far, in order.
+    pub fn requests(&self) -> Vec<RequestSpec> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl HttpClient for FakeClient {
+    fn execute(&self, request: &RequestSpec, _timeout: Duration, _body_limit: Option<u64>) -> Result<HttpResponse, HttpError> {
+        self.requests.lock().unwrap().push(request.clone());
+        thread::sleep(self.delay);
+        self.results
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| FakeClient::error("FakeClient ran out of scripted results", false))
+    }
+
+    fn inspect_tls(&self, _request: &RequestSpec, _timeout: Duration) -> Result<TlsInfo, String> {
+        self.tls.clone().unwrap_or_else(|| Err("FakeClient has no scripted TLS inspection".to_string()))
+    }
+}
+
+/// A canned response, served after an optional delay.
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+    delay: Duration,
+    body_delay: Duration,
+    trickle: Duration,
+}
+
+impl MockResponse {
+    pub fn new(status: u16) -> Self {
+        MockResponse {
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+            delay: Duration::ZERO,
+            body_delay: Duration::ZERO,
+            trickle: Duration::ZERO,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = body.to_string();
+        self
+    }
+
+    /// Waits this long after reading the request before replying.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Waits this long between sending the headers and sending the body.
+    pub fn body_delay(mut self, delay: Duration) -> Self {
+        self.body_delay = delay;
+        self
+    }
+
+    /// Sends the body a byte at a time, waiting this long before each byte.
+    pub fn trickle(mut self, interval: Duration) -> Self {
+        self.trickle = interval;
+        self
+    }
+}
+
+/// A request as the mock server received it. Header names are lowercased.
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers.iter().find(|(existing, _)| *existing == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Serves one scripted response per connection, in order, then stops listening
+/// so that further connections are refused.
+pub struct MockServer {
+    url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl MockServer {
+    pub fn start(responses: Vec<MockResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = Arc::clone(&requests);
+        thread::spawn(move || {
+            for response in responses {
+                let Ok((stream, _)) = listener.accept() else { return };
+                reply(stream, &response, &recorded);
+            }
+        });
+
+        MockServer { url, requests }
+    }
+
+    /// The server's base URL, ending in `/`.
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.url.trim_start_matches("http://").trim_end_matches('/').parse().unwrap()
+    }
+
+    /// A localhost URL that nothing is listening on.
+    pub fn refused_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}/", listener.local_addr().unwrap())
+    }
+}
+
+fn reply(stream: TcpStream, response: &MockResponse, recorded: &Mutex<Vec<RecordedRequest>>) -> Option<()> {
+    let mut reader = BufReader::new(stream.try_clone().ok()?);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).ok()? <= 2 {
+            break;
+        }
+        let (name, value) = line.trim_end().split_once(':')?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+
+    // Recorded before replying, so a client that has its response can see its request
+    recorded.lock().unwrap().push(RecordedRequest { method, path, headers, body });
+
+    thread::sleep(response.delay);
+    let mut head = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    // The client may have given up already, so write errors are expected
+    let mut stream = stream;
+    let _ = stream.write_all(head.as_bytes()).and_then(|_| stream.flush());
+    thread::sleep(response.body_delay);
+    if response.trickle.is_zero() {
+        let _ = stream.write_all(response.body.as_bytes());
+        return Some(());
+    }
+    for byte in response.body.as_bytes() {
+        thread::sleep(response.trickle);
+        stream.write_all(&[*byte]).ok()?;
+    }
+    Some(())
+}
+
+/// A proxy that tunnels every connection to one upstream address, whatever the
+/// client asked for, and records what it asked for.
+pub struct MockProxy {
+    url: String,
+    destinations: Arc<Mutex<Vec<String>>>,
+    authorizations: Arc<Mutex<Vec<Option<String>>>>,
+    unreachable: Arc<Mutex<Vec<String>>>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl MockProxy {
+    /// An HTTP proxy that answers `CONNECT`.
+    pub fn http(upstream: SocketAddr) -> Self {
+        MockProxy::start("http", upstream, None)
+    }
+
+    /// A SOCKS5 proxy, requiring a username and password when `credentials` is set.
+    pub fn socks5(upstream: SocketAddr, credentials: Option<(&str, &str)>) -> Self {
+        let credentials = credentials.map(|(user, password)| (user.to_string(), password.to_string()));
+        MockProxy::start("socks5", upstream, credentials)
+    }
+
+    fn start(scheme: &str, upstream: SocketAddr, credentials: Option<(String, String)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("{}://{}", scheme, listener.local_addr().unwrap());
+        let proxy = MockProxy {
+            url,
+            destinations: Arc::default(),
+            authorizations: Arc::default(),
+            unreachable: Arc::default(),
+            connections: Arc::default(),
+        };
+
+        let (destinations, authorizations) = (Arc::clone(&proxy.destinations), Arc::clone(&proxy.authorizations));
+        let (unreachable, connections) = (Arc::clone(&proxy.unreachable), Arc::clone(&proxy.connections));
+        let socks = scheme == "socks5";
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { return };
+                connections.fetch_add(1, Ordering::SeqCst);
+                let (destinations, authorizations, unreachable, credentials) =
+                    (Arc::clone(&destinations), Arc::clone(&authorizations), Arc::clone(&unreachable), credentials.clone());
+                thread::spawn(move || {
+                    let accepted = if socks {
+                        accept_socks5(&stream, credentials.as_ref(), &destinations, &unreachable)
+                    } else {
+                        accept_connect(&stream, &destinations, &authorizations, &unreachable)
+                    };
+                    if accepted.is_ok() {
+                        tunnel(stream, upstream);
+                    }
+                });
+            }
+        });
+        proxy
+    }
+
+    /// Fails to connect to `destination`, given as `host:port`, as if it were down.
+    pub fn unreachable(self, destination: &str) -> Self {
+        self.unreachable.lock().unwrap().push(destination.to_string());
+        self
+    }
+
+    /// The proxy's URL, without credentials.
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// The destinations clients asked for, in order, as `host:port`.
+    pub fn destinations(&self) -> Vec<String> {
+        self.destinations.lock().unwrap().clone()
+    }
+
+    /// How many connections clients opened to the proxy.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// The `Proxy-Authorization` header of each `CONNECT`, in order.
+    pub fn authorizations(&self) -> Vec<Option<String>> {
+        self.authorizations.lock().unwrap().clone()
+    }
+}
+
+fn accept_connect(
+    stream: &TcpStream,
+    destinations: &Mutex<Vec<String>>,
+    authorizations: &Mutex<Vec<Option<String>>>,
+    unreachable: &Mutex<Vec<String>>,
+) -> io::Result<()> {
+    // Read a byte at a time so nothing sent after the request is swallowed
+    let mut reader = BufReader::with_capacity(1, stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut authorization = None;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        if let Some((name, value)) = header.trim_end().split_once(':') {
+            if name.eq_ignore_ascii_case("Proxy-Authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+        header.clear();
+    }
+
+    let mut parts = line.split_whitespace();
+    let (Some("CONNECT"), Some(destination)) = (parts.next(), parts.next()) else {
+        (&*stream).write_all(b"HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n")?;
+        return Err(io::ErrorKind::InvalidData.into());
+    };
+    destinations.lock().unwrap().push(destination.to_string());
+    authorizations.lock().unwrap().push(authorization);
+    if unreachable.lock().unwrap().iter().any(|down| down == destination) {
+        (&*stream).write_all(b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\n\r\n")?;
+        return Err(io::ErrorKind::ConnectionRefused.into());
+    }
+    (&*stream).write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+}
+
+fn accept_socks5(
+    stream: &TcpStream,
+    credentials: Option<&(String, String)>,
+    destinations: &Mutex<Vec<String>>,
+    unreachable: &Mutex<Vec<String>>,
+) -> io::Result<()> {
+    let mut stream = stream;
+    let read = |stream: &mut &TcpStream, len: usize| -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; len];
+        stream.read_exact(&mut buf)?;
+        Ok(buf)
+    };
+
+    let greeting = read(&mut stream, 2)?;
+    let methods = read(&mut stream, greeting[1] as usize)?;
+    let method = if credentials.is_some() { 0x02 } else { 0x00 };
+    if !methods.contains(&method) {
+        stream.write_all(&[0x05, 0xff])?;
+        return Err(io::ErrorKind::PermissionDenied.into());
+    }
+    stream.write_all(&[0x05, method])?;
+    if let Some((user, password)) = credentials {
+        let len = read(&mut stream, 2)?[1] as usize;
+        let given_user = read(&mut stream, len)?;
+        let len = read(&mut stream, 1)?[0] as usize;
+        let given_password = read(&mut stream, len)?;
+        let valid = given_user == user.as_bytes() && given_password == password.as_bytes();
+        stream.write_all(&[0x01, if valid { 0x00 } else { 0x01 }])?;
+        if !valid {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+    }
+
+    let head = read(&mut stream, 4)?;
+    let host = match head[3] {
+        0x01 => IpAddr::from(<[u8; 4]>::try_from(read(&mut stream, 4)?).unwrap()).to_string(),
+        0x04 => format!("[{}]", IpAddr::from(<[u8; 16]>::try_from(read(&mut stream, 16)?).unwrap())),
+        _ => {
+            let len = read(&mut stream, 1)?[0] as usize;
+            String::from_utf8_lossy(&read(&mut stream, len)?).into_owned()
+        }
+    };
+    let port = read(&mut stream, 2)?;
+    let port = u16::from_be_bytes([port[0], port[1]]);
+    let destination = format!("{}:{}", host, port);
+    destinations.lock().unwrap().push(destination.clone());
+    if unreachable.lock().unwrap().contains(&destination) {
+        // Host unreachable
+        stream.write_all(&[0x05, 0x04, 0x00, 0x01, 0, 0, 0, 0, 0, 0])?;
+        return Err(io::ErrorKind::ConnectionRefused.into());
+    }
+    stream.write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+}
+
+/// Copies bytes both ways until the upstream closes its side.
+fn tunnel(client: TcpStream, upstream: SocketAddr) {
+    let Ok(server) = TcpStream::connect(upstream) else { return };
+    let (Ok(mut client_reader), Ok(mut server_writer)) = (client.try_clone(), server.try_clone()) else { return };
+    thread::spawn(move || {
+        let _ = io::copy(&mut client_reader, &mut server_writer);
+    });
+    let (mut client, mut server) = (client, server);
+    let _ = io::copy(&mut server, &mut client);
+    let _ = client.shutdown(Shutdown::Both);
+}
@@ -0,0 +1,428 @@
+//! TLS handshake and certificate inspection for HTTPS targets.
+//!
+//! The HTTP client does not expose the connection it used, so inspection makes a
+//! handshake of its own. Certificates are captured even when they would be
+//! rejected, so an expired or untrusted chain is reported rather than hidden.
+
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::http::Timings;
+use crate::network::NetworkOptions;
+use crate::transport::{system_resolver, Deadline};
+
+/// Warn when a certificate expires within this many days unless configured otherwise.
+pub const DEFAULT_EXPIRY_WARNING_DAYS: u32 = 14;
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Whether and how to inspect the TLS setup of HTTPS targets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsPolicy {
+    /// Certificates expiring within this many days produce a warning.
+    pub expiry_warning_days: u32,
+}
+
+impl Default for TlsPolicy {
+    fn default() -> Self {
+        TlsPolicy { expiry_warning_days: DEFAULT_EXPIRY_WARNING_DAYS }
+    }
+}
+
+/// One certificate from the chain a server presented.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    /// DNS names and IP addresses from the subject alternative name extension.
+    pub names: Vec<String>,
+    pub not_before: SystemTime,
+    pub not_after: SystemTime,
+}
+
+impl CertificateInfo {
+    /// Parses a DER-encoded X.509 certificate.
+    pub fn from_der(der: &[u8]) -> Result<Self, String> {
+        let (_, cert) = X509Certificate::from_der(der).map_err(|err| format!("invalid certificate: {}", err))?;
+
+        let names = match cert.subject_alternative_name() {
+            Ok(Some(san)) => san
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name) => Some(name.to_string()),
+                    GeneralName::IPAddress(ip) => ip_to_string(ip),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let validity = cert.validity();
+
+        Ok(CertificateInfo {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            names,
+            not_before: system_time(validity.not_before.timestamp()),
+            not_after: system_time(validity.not_after.timestamp()),
+        })
+    }
+
+    /// Whole days from `now` until the certificate expires; negative once it has.
+    pub fn days_until_expiry(&self, now: SystemTime) -> i64 {
+        let seconds = match self.not_after.duration_since(now) {
+            Ok(left) => left.as_secs() as i64,
+            Err(err) => -(err.duration().as_secs() as i64),
+        };
+        seconds.div_euclid(SECS_PER_DAY)
+    }
+}
+
+fn system_time(timestamp: i64) -> SystemTime {
+    match u64::try_from(timestamp) {
+        Ok(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+        Err(_) => UNIX_EPOCH - Duration::from_secs(timestamp.unsigned_abs()),
+    }
+}
+
+fn ip_to_string(bytes: &[u8]) -> Option<String> {
+    match bytes.len() {
+        4 => Some(std::net::Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?).to_string()),
+        16 => Some(std::net::Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?).to_string()),
+        _ => None,
+    }
+}
+
+/// What a TLS handshake with a server revealed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsInfo {
+    /// The negotiated protocol version, such as `TLSv1.3`.
+    pub version: String,
+    /// The negotiated cipher suite, such as `TLS13_AES_256_GCM_SHA384`.
+    pub cipher: String,
+    /// The certificates the server presented, its own first.
+    pub chain: Vec<CertificateInfo>,
+    /// Why the chain would not be trusted, if it would not be.
+    pub verify_error: Option<String>,
+}
+
+impl TlsInfo {
+    /// The server's own certificate.
+    pub fn leaf(&self) -> Option<&CertificateInfo> {
+        self.chain.first()
+    }
+
+    /// The certificate in the chain that expires first, since any of them expiring breaks it.
+    pub fn first_to_expire(&self) -> Option<&CertificateInfo> {
+        self.chain.iter().min_by_key(|cert| cert.not_after)
+    }
+
+    /// Days until the first certificate in the chain expires.
+    pub fn days_until_expiry(&self, now: SystemTime) -> Option<i64> {
+        self.first_to_expire().map(|cert| cert.days_until_expiry(now))
+    }
+
+    /// Describes a certificate that has expired, expires within the policy's window
+    /// or would not be trusted.
+    pub fn warning(&self, policy: &TlsPolicy, now: SystemTime) -> Option<String> {
+        if let Some(cert) = self.first_to_expire() {
+            let days = cert.days_until_expiry(now);
+            if days < 0 {
+                return Some(format!("certificate {} expired {} days ago", cert.subject, -days));
+            }
+            if days < i64::from(policy.expiry_warning_days) {
+                return Some(format!("certificate {} expires in {} days", cert.subject, days));
+            }
+        }
+        self.verify_error.as_ref().map(|err| format!("certificate not trusted: {}", err))
+    }
+}
+
+/// Whether `url` is one that TLS inspection applies to.
+pub fn is_https(url: &str) -> bool {
+    url::Url::parse(url).is_ok_and(|url| url.scheme() == "https")
+}
+
+/// Connects to the host of an `https` URL the way `network` says, completes a
+/// handshake and reports on it, giving up at `deadline`.
+pub fn inspect(url: &str, network: &NetworkOptions, deadline: Instant) -> Result<TlsInfo, String> {
+    let parsed = url::Url::parse(url).map_err(|err| format!("invalid URL `{}`: {}", url, err))?;
+    if parsed.scheme() != "https" {
+        return Err(format!("`{}` is not an https URL", url));
+    }
+    let server_name = server_name(&parsed)?;
+
+    let connection = network
+        .connect(&parsed, &system_resolver, deadline, &mut Timings::default())
+        .map_err(|err| format!("failed to connect to {}: {}", parsed.host_str().unwrap_or_default(), err))?;
+    let socket = connection.tcp.try_clone().map_err(|err| err.to_string())?;
+    inspect_stream(&mut Deadline::new(&connection.tcp, socket, deadline), server_name, roots())
+}
+
+/// The name a handshake with the host of `url` presents and checks the certificate against.
+pub(crate) fn server_name(url: &url::Url) -> Result<ServerName<'static>, String> {
+    match url.host() {
+        Some(url::Host::Domain(domain)) => ServerName::try_from(domain.to_string()).map_err(|err| err.to_string()),
+        Some(url::Host::Ipv4(ip)) => Ok(ServerName::IpAddress(std::net::IpAddr::V4(ip).into())),
+        Some(url::Host::Ipv6(ip)) => Ok(ServerName::IpAddress(std::net::IpAddr::V6(ip).into())),
+        None => Err(format!("missing host in `{}`", url)),
+    }
+}
+
+pub(crate) fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+pub(crate) fn roots() -> Arc<RootCertStore> {
+    static ROOTS: OnceLock<Arc<RootCertStore>> = OnceLock::new();
+    Arc::clone(ROOTS.get_or_init(|| Arc::new(RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() })))
+}
+
+/// Runs a handshake over `stream`, judging the chain against `roots`.
+fn inspect_stream(stream: &mut (impl Read + Write), server_name: ServerName<'static>, roots: Arc<RootCertStore>) -> Result<TlsInfo, String> {
+    let verifier = Arc::new(RecordingVerifier {
+        inner: WebPkiServerVerifier::builder_with_provider(roots, provider())
+            .build()
+            .map_err(|err| err.to_string())?,
+        error: Mutex::new(None),
+    });
+    let config = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|err| err.to_string())?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+
+    let mut conn = ClientConnection::new(Arc::new(config), server_name).map_err(|err| err.to_string())?;
+    while conn.is_handshaking() {
+        conn.complete_io(stream).map_err(|err| format!("TLS handshake failed: {}", err))?;
+    }
+
+    let chain = conn
+        .peer_certificates()
+        .unwrap_or_default()
+        .iter()
+        .map(|cert| CertificateInfo::from_der(cert))
+        .collect::<Result<_, _>>()?;
+    let version = conn.protocol_version().map_or("unknown".to_string(), |version| {
+        version.as_str().map_or(format!("{:?}", version), |name| name.replace('_', "."))
+    });
+    let cipher = conn
+        .negotiated_cipher_suite()
+        .map_or("unknown".to_string(), |suite| format!("{:?}", suite.suite()));
+
+    // Closing politely is a courtesy; the server may already have hung up
+    conn.send_close_notify();
+    let _ = conn.write_tls(stream).and_then(|_| stream.flush());
+
+    let verify_error = verifier.error.lock().unwrap_or_else(PoisonError::into_inner).take();
+    Ok(TlsInfo { version, cipher, chain, verify_error })
+}
+
+/// Accepts any certificate chain so that it can be inspected, remembering why the
+/// standard verifier would have rejected it. Handshake signatures are still checked.
+#[derive(Debug)]
+struct RecordingVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    error: Mutex<Option<String>>,
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Err(err) = self.inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now) {
+            *self.error.lock().unwrap_or_else(PoisonError::into_inner) = Some(err.to_string());
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use rcgen::{date_time_ymd, CertificateParams, KeyPair};
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+    use rustls::{ServerConfig, ServerConnection};
+
+    /// A self-signed certificate for `localhost` and 127.0.0.1, valid for the given years.
+    fn certificate(from: i32, until: i32) -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+        let mut params = CertificateParams::new(vec!["localhost".to_string(), "127.0.0.1".to_string()]).unwrap();
+        params.distinguished_name.push(rcgen::DnType::CommonName, "Test Server");
+        params.not_before = date_time_ymd(from, 1, 1);
+        params.not_after = date_time_ymd(until, 1, 1);
+        let key = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        (cert.der().clone(), PrivatePkcs8KeyDer::from(key.serialize_der()).into())
+    }
+
+    /// Completes one TLS handshake with `cert` on localhost and returns the port.
+    fn serve_once(cert: CertificateDer<'static>, key: PrivateKeyDer<'static>) -> u16 {
+        let config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut conn = ServerConnection::new(Arc::new(config)).unwrap();
+            while conn.is_handshaking() {
+                if conn.complete_io(&mut stream).is_err() {
+                    return;
+                }
+            }
+            let _ = conn.complete_io(&mut stream);
+        });
+        port
+    }
+
+    fn in_secs(secs: u64) -> Instant {
+        Instant::now() + Duration::from_secs(secs)
+    }
+
+    fn date(year: i32) -> SystemTime {
+        system_time(date_time_ymd(year, 1, 1).unix_timestamp())
+    }
+
+    #[test]
+    fn test_certificate_info() {
+        let (der, _) = certificate(2020, 2030);
+        let cert = CertificateInfo::from_der(&der).unwrap();
+
+        assert_eq!(cert.subject, "CN=Test Server");
+        assert_eq!(cert.issuer, "CN=Test Server");
+        assert_eq!(cert.names, vec!["localhost", "127.0.0.1"]);
+        assert_eq!(cert.not_after, date(2030));
+        assert_eq!(cert.days_until_expiry(date(2029)), 365);
+        assert_eq!(cert.days_until_expiry(date(2030) + Duration::from_secs(60)), -1);
+        assert!(CertificateInfo::from_der(b"not a certificate").is_err());
+    }
+
+    #[test]
+    fn test_expiry_warning() {
+        let (der, _) = certificate(2020, 2030);
+        let info = TlsInfo {
+            version: "TLSv1.3".to_string(),
+            cipher: "TLS13_AES_128_GCM_SHA256".to_string(),
+            chain: vec![CertificateInfo::from_der(&der).unwrap()],
+            verify_error: None,
+        };
+        let policy = TlsPolicy::default();
+
+        assert_eq!(info.warning(&policy, date(2029)), None);
+        let soon = date(2030) - Duration::from_secs(10 * SECS_PER_DAY as u64);
+        assert_eq!(info.warning(&policy, soon).unwrap(), "certificate CN=Test Server expires in 10 days");
+        let after = date(2030) + Duration::from_secs(3 * SECS_PER_DAY as u64);
+        assert_eq!(info.warning(&policy, after).unwrap(), "certificate CN=Test Server expired 3 days ago");
+
+        let untrusted = TlsInfo { verify_error: Some("invalid peer certificate: UnknownIssuer".to_string()), ..info };
+        assert!(untrusted.warning(&policy, date(2029)).unwrap().starts_with("certificate not trusted"));
+    }
+
+    #[test]
+    fn test_inspect_handshake() {
+        let (cert, key) = certificate(2020, 2100);
+        let port = serve_once(cert.clone(), key);
+
+        // Trusting the test certificate as a root, the chain verifies
+        let mut roots = RootCertStore::empty();
+        roots.add(cert).unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let server_name = ServerName::try_from("localhost").unwrap();
+        let info = inspect_stream(&mut stream, server_name, Arc::new(roots)).unwrap();
+
+        assert_eq!(info.version, "TLSv1.3");
+        assert!(info.cipher.starts_with("TLS13_"));
+        assert_eq!(info.chain.len(), 1);
+        assert_eq!(info.leaf().unwrap().names, vec!["localhost", "127.0.0.1"]);
+        assert_eq!(info.verify_error, None);
+    }
+
+    #[test]
+    fn test_inspect_captures_untrusted_and_expired_certificates() {
+        let (cert, key) = certificate(2000, 2010);
+        let port = serve_once(cert, key);
+
+        let info = inspect(&format!("https://127.0.0.1:{}/", port), &NetworkOptions::default(), in_secs(5)).unwrap();
+        assert_eq!(info.leaf().unwrap().subject, "CN=Test Server");
+        assert!(info.verify_error.is_some());
+        assert!(info.days_until_expiry(SystemTime::now()).unwrap() < 0);
+        assert!(info.warning(&TlsPolicy::default(), SystemTime::now()).unwrap().contains("expired"));
+    }
+
+    #[test]
+    fn test_trickled_handshake_cannot_outlast_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // The header of a 64-byte handshake record, whose body then arrives a byte at a time
+            let _ = stream.write_all(&[0x16, 0x03, 0x03, 0x00, 0x40]);
+            for _ in 0..64 {
+                thread::sleep(Duration::from_millis(100));
+                if stream.write_all(&[0]).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(350);
+        let err = inspect(&format!("https://127.0.0.1:{}/", port), &NetworkOptions::default(), deadline).unwrap_err();
+        assert!(start.elapsed() < Duration::from_millis(1000), "took {:?}", start.elapsed());
+        assert!(err.contains("timed out"), "{}", err);
+    }
+
+    #[test]
+    fn test_inspect_errors() {
+        assert!(inspect("http://example.com/", &NetworkOptions::default(), in_secs(1)).is_err());
+        let refused = crate::testing::MockServer::refused_url().replace("http://", "https://");
+        assert!(inspect(&refused, &NetworkOptions::default(), in_secs(1)).unwrap_err().contains("failed to connect"));
+        assert!(is_https("https://example.com/"));
+        assert!(!is_https("http://example.com/"));
+    }
+}
@@ -0,0 +1,805 @@
+//! An HTTP/1.1 client that times each phase of a request.
+//!
+//! ureq does not expose the connections it makes, so DNS, connect and first-byte
+//! times cannot be measured through it. This client makes one connection per
+//! request with `Connection: close`, which is what a periodic check wants anyway.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+use crate::http::{HttpClient, HttpError, HttpResponse, LimitedBody, Timings};
+use crate::network::{self, NetworkOptions, Proxy};
+use crate::redirect;
+use crate::request::{self, Method, RequestSpec};
+use crate::tls;
+
+/// Largest response head accepted.
+const MAX_HEAD_BYTES: u64 = 64 * 1024;
+/// Bodies nobody asked for are read this far to time their download, then abandoned.
+const MAX_DISCARD: u64 = 1024 * 1024;
+const USER_AGENT: &str = concat!("websiteStatusChecker/", env!("CARGO_PKG_VERSION"));
+
+/// Looks up the addresses of a host and port.
+pub type Resolver = dyn Fn(&str, u16) -> io::Result<Vec<SocketAddr>> + Send + Sync;
+
+pub(crate) fn system_resolver(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    (host, port).to_socket_addrs().map(Iterator::collect)
+}
+
+/// The default client: times DNS, connect, TLS, first byte and download for every
+/// request, and honours each request's proxy, pinned addresses and local address.
+pub struct TimedClient {
+    resolver: Arc<Resolver>,
+    tls: Arc<ClientConfig>,
+}
+
+impl Default for TimedClient {
+    fn default() -> Self {
+        TimedClient::with_roots(tls::roots())
+    }
+}
+
+impl TimedClient {
+    /// A client that trusts `roots` for HTTPS instead of the bundled web PKI roots.
+    pub fn with_roots(roots: Arc<RootCertStore>) -> Self {
+        let tls = ClientConfig::builder_with_provider(tls::provider())
+            .with_safe_default_protocol_versions()
+            .expect("the default protocol versions are supported")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        TimedClient { resolver: Arc::new(system_resolver), tls: Arc::new(tls) }
+    }
+
+    /// Looks hosts up with `resolver` instead of the system resolver.
+    pub fn resolver(mut self, resolver: impl Fn(&str, u16) -> io::Result<Vec<SocketAddr>> + Send + Sync + 'static) -> Self {
+        self.resolver = Arc::new(resolver);
+        self
+    }
+
+    /// Makes one request, without following redirects.
+    fn send_once(&self, hop: &Hop, deadline: Instant, body_limit: Option<u64>) -> Result<HttpResponse, HttpError> {
+        let url = &hop.url;
+        let mut timings = Timings::default();
+        let network::Connection { tcp, forwarding } = hop.network.connect(url, &*self.resolver, deadline, &mut timings)?;
+        let socket = tcp.try_clone().map_err(|err| io_error("connecting", err))?;
+
+        let stream = match url.scheme() {
+            "https" => {
+                let start = Instant::now();
+                let stream = self.handshake(url, tcp, deadline)?;
+                timings.tls = Some(start.elapsed());
+                Stream::Tls(Box::new(stream))
+            }
+            _ => Stream::Plain(tcp),
+        };
+
+        let mut stream = Deadline::new(stream, socket, deadline);
+        stream
+            .write_all(&hop.head(forwarding.as_ref()))
+            .and_then(|_| hop.body.as_deref().map_or(Ok(()), |body| stream.write_all(body.as_bytes())))
+            .and_then(|_| stream.flush())
+            .map_err(|err| io_error("sending request", err))?;
+
+        let sent = Instant::now();
+        let mut reader = BufReader::new(stream);
+        reader.fill_buf().map_err(|err| io_error("waiting for response", err))?;
+        timings.ttfb = Some(sent.elapsed());
+
+        let (status, headers) = read_head(&mut reader)?;
+        let head_end = Instant::now();
+
+        let framing = Framing::of(hop.method, status, &headers);
+        let mut body = framing.reader(&mut reader);
+        let kept = match body_limit {
+            Some(limit) => Some(LimitedBody::new(limit).read_from(&mut body, &mut timings)?),
+            None => {
+                // Only timed; a body that is huge or fails part way is not the check's
+                // concern, but one still arriving at the deadline is
+                match io::copy(&mut (&mut body).take(MAX_DISCARD + 1), &mut io::sink()) {
+                    Ok(read) if read <= MAX_DISCARD => timings.download = Some(head_end.elapsed()),
+                    Err(err) if is_timeout(&err) => return Err(io_error("reading body", err)),
+                    _ => {}
+                }
+                None
+            }
+        };
+
+        Ok(HttpResponse { status, headers, body: kept, timings, redirects: Vec::new() })
+    }
+
+    fn handshake(&self, url: &url::Url, tcp: TcpStream, deadline: Instant) -> Result<StreamOwned<ClientConnection, TcpStream>, HttpError> {
+        let server_name = tls::server_name(url).map_err(permanent)?;
+
+        let mut conn = ClientConnection::new(Arc::clone(&self.tls), server_name).map_err(|err| permanent(err.to_string()))?;
+        let socket = tcp.try_clone().map_err(|err| io_error("during TLS handshake", err))?;
+        let mut io = Deadline::new(&tcp, socket, deadline);
+        while conn.is_handshaking() {
+            conn.complete_io(&mut io).map_err(|err| io_error("during TLS handshake", err))?;
+        }
+        Ok(StreamOwned::new(conn, tcp))
+    }
+}
+
+impl HttpClient for TimedClient {
+    fn execute(&self, request: &RequestSpec, timeout: Duration, body_limit: Option<u64>) -> Result<HttpResponse, HttpError> {
+        self.execute_with(request, timeout, body_limit, &mut |_| Ok(()))
+    }
+
+    fn execute_with(
+        &self,
+        request: &RequestSpec,
+        timeout: Duration,
+        body_limit: Option<u64>,
+        before_redirect: &mut dyn FnMut(&str) -> Result<(), HttpError>,
+    ) -> Result<HttpResponse, HttpError> {
+        let start = Instant::now();
+        let mut deadline = start + timeout;
+        // Time spent in `before_redirect`, which is neither the server's nor the network's
+        let mut held = Duration::ZERO;
+        let mut chain = Vec::new();
+        let mut request = request.clone();
+        let mut followed = false;
+        loop {
+            let hop_start = Instant::now();
+            let mut response = self.send_once(&Hop::new(&request)?, deadline, body_limit)?;
+            match redirect::next_request(&request, &response, &request.redirects, &mut chain)? {
+                Some(next) => {
+                    let paused = Instant::now();
+                    before_redirect(&next.url)?;
+                    held += paused.elapsed();
+                    deadline = start + timeout + held;
+                    request = next;
+                    followed = true;
+                }
+                None => {
+                    if followed {
+                        response.timings.redirect = Some(hop_start - start - held);
+                    }
+                    response.redirects = chain;
+                    return Ok(response);
+                }
+            }
+        }
+    }
+}
+
+/// A request ready to be written to the connection.
+struct Hop {
+    url: url::Url,
+    method: Method,
+    headers: Vec<(String, String)>,
+    body: Option<String>,
+    network: NetworkOptions,
+}
+
+impl Hop {
+    fn new(request: &RequestSpec) -> Result<Self, HttpError> {
+        let url = url::Url::parse(&request.url).map_err(|err| permanent(format!("Bad URL: {}", err)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(permanent(format!("Unknown Scheme: cannot make HTTP request to `{}`", url)));
+        }
+        // Headers are written into the head as they are, so one that could end its
+        // line is refused before anything is sent
+        for (name, value) in &request.headers {
+            request::validate_header(name, value).map_err(|err| permanent(format!("Bad Header: {}", err)))?;
+        }
+
+        let mut headers = request.headers.clone();
+        let body = request.body.as_ref().map(|body| {
+            if let (Some(content_type), None) = (body.content_type(), request.header("Content-Type")) {
+                headers.push(("Content-Type".to_string(), content_type.to_string()));
+            }
+            body.to_string()
+        });
+        Ok(Hop { url, method: request.method, headers, body, network: request.network.clone() })
+    }
+
+    /// The request line and headers. A forwarding proxy is sent the whole URL.
+    fn head(&self, forwarding: Option<&Proxy>) -> Vec<u8> {
+        let mut target = match forwarding {
+            Some(_) => self.url[..url::Position::AfterPath].to_string(),
+            None => self.url.path().to_string(),
+        };
+        if let Some(query) = self.url.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+
+        let mut head = format!("{} {} HTTP/1.1\r\n", self.method.as_str(), target);
+        let has = |name: &str| self.headers.iter().any(|(existing, _)| existing.eq_ignore_ascii_case(name));
+        if !has("Host") {
+            let host = self.url.host_str().unwrap_or_default();
+            match self.url.port() {
+                Some(port) => head.push_str(&format!("Host: {}:{}\r\n", host, port)),
+                None => head.push_str(&format!("Host: {}\r\n", host)),
+            }
+        }
+        if !has("User-Agent") {
+            head.push_str(&format!("User-Agent: {}\r\n", USER_AGENT));
+        }
+        if !has("Accept") {
+            head.push_str("Accept: */*\r\n");
+        }
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if let Some(authorization) = forwarding.and_then(network::proxy_authorization) {
+            head.push_str(&authorization);
+        }
+        if let Some(body) = &self.body {
+            head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        head.push_str("Connection: close\r\n\r\n");
+        head.into_bytes()
+    }
+}
+
+/// Reads the status line and headers, lowercasing the header names.
+pub(crate) fn read_head(reader: &mut impl BufRead) -> Result<(u16, Vec<(String, String)>), HttpError> {
+    let mut reader = reader.take(MAX_HEAD_BYTES);
+    let mut line = String::new();
+    let mut next_line = |line: &mut String| -> Result<(), HttpError> {
+        line.clear();
+        match reader.read_line(line) {
+            Ok(0) => Err(retryable("Bad Status: server closed the connection before responding".to_string())),
+            Ok(_) => Ok(()),
+            Err(err) => Err(io_error("reading response", err)),
+        }
+    };
+
+    // Interim 1xx responses precede the real one
+    let status = loop {
+        next_line(&mut line)?;
+        let status = line
+            .split_whitespace()
+            .nth(1)
+            .filter(|_| line.starts_with("HTTP/"))
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| permanent(format!("Bad Status: invalid status line `{}`", line.trim_end())))?;
+
+        let mut headers = Vec::new();
+        loop {
+            next_line(&mut line)?;
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let (name, value) = header.split_once(':').ok_or_else(|| permanent(format!("Bad Header: `{}`", header)))?;
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+
+        if !(100..200).contains(&status) || status == 101 {
+            break (status, headers);
+        }
+    };
+    Ok(status)
+}
+
+/// How the end of a response body is found.
+enum Framing {
+    Empty,
+    Length(u64),
+    Chunked,
+    UntilClose,
+}
+
+impl Framing {
+    fn of(method: Method, status: u16, headers: &[(String, String)]) -> Self {
+        let header = |name: &str| headers.iter().find(|(existing, _)| existing == name).map(|(_, value)| value.as_str());
+        if !method.has_response_body() || matches!(status, 100..=199 | 204 | 304) {
+            Framing::Empty
+        } else if header("transfer-encoding").is_some_and(|value| value.to_ascii_lowercase().contains("chunked")) {
+            Framing::Chunked
+        } else if let Some(length) = header("content-length").and_then(|value| value.parse().ok()) {
+            Framing::Length(length)
+        } else {
+            Framing::UntilClose
+        }
+    }
+
+    fn reader<'a, R: BufRead + 'a>(&self, reader: &'a mut R) -> Box<dyn Read + 'a> {
+        match self {
+            Framing::Empty => Box::new(io::empty()),
+            Framing::Length(length) => Box::new(reader.take(*length)),
+            Framing::Chunked => Box::new(Chunked { inner: reader, remaining: 0, done: false }),
+            Framing::UntilClose => Box::new(reader),
+        }
+    }
+}
+
+/// Decodes a `Transfer-Encoding: chunked` body.
+struct Chunked<R> {
+    inner: R,
+    remaining: u64,
+    done: bool,
+}
+
+impl<R: BufRead> Chunked<R> {
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.inner.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "body ended inside a chunk"));
+        }
+        Ok(line)
+    }
+}
+
+impl<R: BufRead> Read for Chunked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            let line = self.read_line()?;
+            let size = line.trim_end().split(';').next().unwrap_or_default();
+            self.remaining = u64::from_str_radix(size.trim(), 16)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("invalid chunk size `{}`", size.trim())))?;
+            if self.remaining == 0 {
+                // Skip any trailers up to the blank line that ends the body
+                while !self.read_line()?.trim_end().is_empty() {}
+                self.done = true;
+                return Ok(0);
+            }
+        }
+
+        let max = buf.len().min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let read = self.inner.read(&mut buf[..max])?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "body ended inside a chunk"));
+        }
+        self.remaining -= read as u64;
+        if self.remaining == 0 {
+            self.read_line()?;
+        }
+        Ok(read)
+    }
+}
+
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(tcp) => tcp.read(buf),
+            Stream::Tls(tls) => tls.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(tcp) => tcp.write(buf),
+            Stream::Tls(tls) => tls.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(tcp) => tcp.flush(),
+            Stream::Tls(tls) => tls.flush(),
+        }
+    }
+}
+
+/// Reads and writes `inner` with the socket timeouts reset to what is left of the
+/// deadline before each call, so the whole request shares one deadline however
+/// slowly the peer trickles bytes. `socket` is the connection under `inner`.
+pub(crate) struct Deadline<S> {
+    inner: S,
+    socket: TcpStream,
+    deadline: Instant,
+}
+
+impl<S> Deadline<S> {
+    pub(crate) fn new(inner: S, socket: TcpStream, deadline: Instant) -> Self {
+        Deadline { inner, socket, deadline }
+    }
+
+    fn arm(&self) -> io::Result<()> {
+        let remaining = remaining(self.deadline)?;
+        self.socket.set_read_timeout(Some(remaining))?;
+        self.socket.set_write_timeout(Some(remaining))
+    }
+}
+
+impl<S: Read> Read for Deadline<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.arm()?;
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Write> Write for Deadline<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.arm()?;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.arm()?;
+        self.inner.flush()
+    }
+}
+
+pub(crate) fn remaining(deadline: Instant) -> io::Result<Duration> {
+    match deadline.checked_duration_since(Instant::now()) {
+        Some(remaining) if !remaining.is_zero() => Ok(remaining),
+        _ => Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")),
+    }
+}
+
+pub(crate) fn is_timeout(err: &io::Error) -> bool {
+    matches!(err.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+}
+
+pub(crate) fn io_error(doing: &str, err: io::Error) -> HttpError {
+    if is_timeout(&err) {
+        return retryable(format!("timed out {}", doing));
+    }
+    // Certificate problems will not go away by trying again
+    let retryable = err.kind() != io::ErrorKind::InvalidData;
+    HttpError { message: format!("Network Error: {} {}", doing, err), retryable }
+}
+
+pub(crate) fn retryable(message: String) -> HttpError {
+    HttpError { message, retryable: true }
+}
+
+pub(crate) fn permanent(message: String) -> HttpError {
+    HttpError { message, retryable: false }
+}
+
+impl HttpError {
+    pub(crate) fn context(self, context: &str) -> Self {
+        HttpError { message: format!("{}: {}", context, self.message), ..self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::NetworkOptions;
+    use crate::testing::{MockProxy, MockResponse, MockServer};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn get(url: &str) -> RequestSpec {
+        RequestSpec {
+            method: Method::Get,
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+            redirects: Default::default(),
+            network: Default::default(),
+        }
+    }
+
+    fn execute(url: &str, body_limit: Option<u64>) -> Result<HttpResponse, HttpError> {
+        TimedClient::default().execute(&get(url), Duration::from_secs(5), body_limit)
+    }
+
+    /// Serves `raw` as the whole response to one connection.
+    fn serve_raw(raw: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 2 {
+                line.clear();
+            }
+            let _ = stream.write_all(raw);
+        });
+        url
+    }
+
+    #[test]
+    fn test_response_and_request() {
+        let server = MockServer::start(vec![MockResponse::new(200).header("X-Build", "42").body("0123456789")]);
+        let request = RequestSpec {
+            method: Method::Post,
+            url: format!("{}health?deep=1", server.url()),
+            headers: vec![("X-Token".to_string(), "secret".to_string())],
+            body: Some(crate::request::Body::Json(serde_json::json!({ "ping": true }))),
+            redirects: Default::default(),
+            network: Default::default(),
+        };
+
+        let response = TimedClient::default().execute(&request, Duration::from_secs(5), Some(4)).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(&b"01234"[..]));
+        assert!(response.headers.contains(&("x-build".to_string(), "42".to_string())));
+
+        let recorded = &server.requests()[0];
+        assert_eq!(recorded.method, "POST");
+        assert_eq!(recorded.path, "/health?deep=1");
+        assert_eq!(recorded.header("x-token"), Some("secret"));
+        assert_eq!(recorded.header("content-type"), Some("application/json"));
+        assert_eq!(recorded.body, br#"{"ping":true}"#);
+    }
+
+    #[test]
+    fn test_refuses_headers_that_break_the_head() {
+        let server = MockServer::start(vec![MockResponse::new(200)]);
+        for (name, value) in [("X-Trace", "a\r\nHost: evil"), ("X-Trace", "a\nb"), ("Bad Name", "a"), ("X-Trace", "a\0")] {
+            let request = RequestSpec { headers: vec![(name.to_string(), value.to_string())], ..get(&server.url()) };
+            let err = TimedClient::default().execute(&request, Duration::from_secs(5), None).unwrap_err();
+            assert!(err.message.starts_with("Bad Header: "), "{}", err);
+            assert!(!err.retryable);
+        }
+        assert!(server.requests().is_empty());
+    }
+
+    #[test]
+    fn test_phase_timings() {
+        let server = MockServer::start(vec![MockResponse::new(200)
+            .body("slow body")
+            .delay(Duration::from_millis(150))
+            .body_delay(Duration::from_millis(100))]);
+        let client = TimedClient::default().resolver(|host, port| {
+            thread::sleep(Duration::from_millis(50));
+            system_resolver(host, port)
+        });
+
+        let url = server.url().replace("127.0.0.1", "localhost");
+        let response = client.execute(&get(&url), Duration::from_secs(5), None).unwrap();
+        let timings = response.timings;
+        assert!(timings.dns.unwrap() >= Duration::from_millis(50));
+        assert!(timings.connect.unwrap() < Duration::from_millis(50));
+        assert_eq!(timings.tls, None);
+        assert!(timings.ttfb.unwrap() >= Duration::from_millis(150));
+        assert!(timings.download.unwrap() >= Duration::from_millis(100));
+        assert_eq!(timings.redirect, None);
+    }
+
+    #[test]
+    fn test_ip_hosts_skip_dns() {
+        let server = MockServer::start(vec![MockResponse::new(204)]);
+        let response = execute(&server.url(), None).unwrap();
+        assert_eq!(response.status, 204);
+        assert_eq!(response.timings.dns, None);
+        assert!(response.timings.connect.is_some());
+        assert!(response.timings.download.is_some());
+    }
+
+    #[test]
+    fn test_follows_redirects() {
+        let target = MockServer::start(vec![MockResponse::new(200).body("done")]);
+        let first = MockServer::start(vec![MockResponse::new(302).header("Location", &format!("{}final", target.url()))]);
+
+        let response = execute(&first.url(), Some(100)).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(&b"done"[..]));
+        assert!(response.timings.redirect.is_some());
+        assert_eq!(target.requests()[0].path, "/final");
+        assert_eq!(response.redirects.len(), 1);
+        assert_eq!(response.redirects[0].location, format!("{}final", target.url()));
+    }
+
+    #[test]
+    fn test_stops_at_redirect_limit() {
+        let server = MockServer::start(vec![MockResponse::new(301).header("Location", "/again"); 6]);
+        let response = execute(&server.url(), None).unwrap();
+        assert_eq!(response.status, 301);
+        assert_eq!(response.redirects.len(), 6);
+        assert!(response.timings.redirect.is_some());
+    }
+
+    #[test]
+    fn test_chunked_body() {
+        let url = serve_raw(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\nX-Trailer: 1\r\n\r\n");
+        let response = execute(&url, Some(100)).unwrap();
+        assert_eq!(response.body.as_deref(), Some(&b"Wikipedia"[..]));
+        assert!(response.timings.download.is_some());
+    }
+
+    #[test]
+    fn test_body_until_close_and_interim_responses() {
+        let url = serve_raw(b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.0 200 OK\r\n\r\nno length");
+        let response = execute(&url, Some(100)).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(&b"no length"[..]));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = execute(&MockServer::refused_url(), None).unwrap_err();
+        assert!(err.retryable);
+        assert!(err.message.contains("Connection Failed"));
+
+        let err = execute("not a url", None).unwrap_err();
+        assert!(!err.retryable);
+
+        let err = execute(&serve_raw(b"garbage\r\n\r\n"), None).unwrap_err();
+        assert!(err.message.contains("Bad Status"));
+
+        let server = MockServer::start(vec![MockResponse::new(200).delay(Duration::from_millis(500))]);
+        let err = TimedClient::default().execute(&get(&server.url()), Duration::from_millis(100), None).unwrap_err();
+        assert!(err.retryable);
+        assert!(err.message.contains("timed out"));
+    }
+
+    #[test]
+    fn test_trickled_body_cannot_outlast_timeout() {
+        // Each byte comes well within the timeout, but the body as a whole does not
+        let trickle = MockResponse::new(200).body("0123456789").trickle(Duration::from_millis(100));
+        let server = MockServer::start(vec![trickle.clone(), trickle]);
+        for body_limit in [None, Some(100)] {
+            let start = Instant::now();
+            let err = TimedClient::default().execute(&get(&server.url()), Duration::from_millis(350), body_limit).unwrap_err();
+            assert!(start.elapsed() < Duration::from_millis(600), "{:?}", start.elapsed());
+            assert!(err.retryable);
+            assert!(err.message.contains("timed out"), "{}", err.message);
+        }
+    }
+
+    fn routed(url: &str, network: NetworkOptions) -> Result<HttpResponse, HttpError> {
+        let client = TimedClient::default().resolver(|host, _| Err(io::Error::other(format!("unexpected lookup of {}", host))));
+        client.execute(&RequestSpec { network, ..get(url) }, Duration::from_secs(5), Some(100))
+    }
+
+    fn proxy(url: &str) -> NetworkOptions {
+        NetworkOptions { proxy: Some(url.parse().unwrap()), ..NetworkOptions::default() }
+    }
+
+    #[test]
+    fn test_http_proxy_forwards_plain_http() {
+        let proxy_server = MockServer::start(vec![MockResponse::new(200).body("via proxy")]);
+        let response = routed("http://service.test/health?full=1", proxy(&format!("http://user:secret@{}", proxy_server.addr()))).unwrap();
+        assert_eq!(response.body.as_deref(), Some(&b"via proxy"[..]));
+        assert_eq!(response.timings.dns, None);
+
+        let recorded = &proxy_server.requests()[0];
+        assert_eq!(recorded.path, "http://service.test/health?full=1");
+        assert_eq!(recorded.header("Host"), Some("service.test"));
+        assert_eq!(recorded.header("Proxy-Authorization"), Some("Basic dXNlcjpzZWNyZXQ="));
+    }
+
+    #[test]
+    fn test_http_proxy_tunnels_pinned_hosts() {
+        let server = MockServer::start(vec![MockResponse::new(200).body("tunnelled")]);
+        let proxy = MockProxy::http(server.addr());
+        let network = NetworkOptions { resolve: vec!["service.test:80:10.0.0.7".parse().unwrap()], ..self::proxy(&proxy.url()) };
+
+        let response = routed("http://service.test/", network).unwrap();
+        assert_eq!(response.body.as_deref(), Some(&b"tunnelled"[..]));
+        assert_eq!(proxy.destinations(), vec!["10.0.0.7:80"]);
+        assert_eq!(proxy.authorizations(), vec![None]);
+        // Tunnelled requests are in origin form, as the host expects
+        assert_eq!(server.requests()[0].path, "/");
+        assert_eq!(server.requests()[0].header("Host"), Some("service.test"));
+    }
+
+    #[test]
+    fn test_proxies_try_every_pinned_address() {
+        let server = MockServer::start(vec![MockResponse::new(200), MockResponse::new(200)]);
+        let pins = vec!["service.test:80:10.0.0.7".parse().unwrap(), "service.test:80:10.0.0.8".parse().unwrap()];
+
+        let proxy = MockProxy::http(server.addr()).unreachable("10.0.0.7:80");
+        let network = NetworkOptions { resolve: pins.clone(), ..self::proxy(&proxy.url()) };
+        assert_eq!(routed("http://service.test/", network).unwrap().status, 200);
+        assert_eq!(proxy.destinations(), vec!["10.0.0.7:80", "10.0.0.8:80"]);
+
+        let proxy = MockProxy::socks5(server.addr(), None).unreachable("10.0.0.7:80");
+        let network = NetworkOptions { resolve: pins.clone(), ..self::proxy(&proxy.url()) };
+        assert_eq!(routed("http://service.test/", network).unwrap().status, 200);
+        assert_eq!(proxy.destinations(), vec!["10.0.0.7:80", "10.0.0.8:80"]);
+
+        // With every address down, the last failure is reported
+        let proxy = MockProxy::socks5(server.addr(), None).unreachable("10.0.0.7:80").unreachable("10.0.0.8:80");
+        let err = routed("http://service.test/", NetworkOptions { resolve: pins.clone(), ..self::proxy(&proxy.url()) }).unwrap_err();
+        assert!(err.message.contains("could not connect to 10.0.0.8:80"), "{}", err.message);
+
+        // Refused credentials fail every address alike, so the first ends the attempt
+        let proxy = MockProxy::socks5(server.addr(), Some(("user", "secret")));
+        let url = proxy.url().replace("socks5://", "socks5://user:wrong@");
+        let err = routed("http://service.test/", NetworkOptions { resolve: pins, ..self::proxy(&url) }).unwrap_err();
+        assert!(err.message.contains("rejected the credentials"), "{}", err.message);
+        assert_eq!(proxy.connections(), 1);
+    }
+
+    #[test]
+    fn test_socks5_proxies() {
+        let server = MockServer::start(vec![MockResponse::new(200), MockResponse::new(200)]);
+        let proxy = MockProxy::socks5(server.addr(), Some(("user", "secret")));
+        let url = proxy.url().replace("socks5://", "socks5h://user:secret@");
+        routed("http://service.test/", self::proxy(&url)).unwrap();
+
+        // socks5:// looks the host up itself
+        let client = TimedClient::default().resolver(|_, port| Ok(vec![SocketAddr::from(([192, 0, 2, 1], port))]));
+        let url = proxy.url().replace("socks5://", "socks5://user:secret@");
+        let request = RequestSpec { network: self::proxy(&url), ..get("http://service.test:8080/") };
+        let response = client.execute(&request, Duration::from_secs(5), None).unwrap();
+        assert!(response.timings.dns.is_some());
+        assert_eq!(proxy.destinations(), vec!["service.test:80", "192.0.2.1:8080"]);
+
+        let url = proxy.url().replace("socks5://", "socks5h://user:wrong@");
+        let err = routed("http://service.test/", self::proxy(&url)).unwrap_err();
+        assert!(!err.retryable);
+        assert!(err.message.contains("rejected the credentials"), "{}", err.message);
+        let err = routed("http://service.test/", self::proxy(&proxy.url().replace("socks5://", "socks5h://"))).unwrap_err();
+        assert!(err.message.contains("authentication methods"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_resolve_overrides_and_local_address() {
+        let server = MockServer::start(vec![MockResponse::new(204)]);
+        let pin = format!("service.test:{}:127.0.0.1", server.addr().port());
+        let network = NetworkOptions {
+            resolve: vec![pin.parse().unwrap()],
+            local_address: Some("127.0.0.1".parse().unwrap()),
+            ..NetworkOptions::default()
+        };
+        let url = format!("http://service.test:{}/", server.addr().port());
+        let response = routed(&url, network.clone()).unwrap();
+        assert_eq!(response.status, 204);
+        assert_eq!(response.timings.dns, None);
+        assert_eq!(server.requests()[0].header("Host"), Some(&url[7..url.len() - 1]));
+
+        // Pins only apply to their port
+        let err = routed("http://service.test:1/", network.clone()).unwrap_err();
+        assert!(err.message.contains("unexpected lookup of service.test"), "{}", err.message);
+
+        let network = NetworkOptions { local_address: Some("::1".parse().unwrap()), ..network };
+        let err = routed(&url, network).unwrap_err();
+        assert!(!err.retryable);
+        assert!(err.message.contains("reached from ::1"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_tls_timings() {
+        use rcgen::{CertificateParams, KeyPair};
+        use rustls::pki_types::PrivatePkcs8KeyDer;
+        use rustls::{ServerConfig, ServerConnection};
+
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["localhost".to_string()]).unwrap().self_signed(&key).unwrap();
+        let config = ServerConfig::builder_with_provider(tls::provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.der().clone()], PrivatePkcs8KeyDer::from(key.serialize_der()).into())
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = Arc::new(config);
+        thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                // A slow TLS terminator
+                thread::sleep(Duration::from_millis(100));
+                let mut tls = StreamOwned::new(ServerConnection::new(Arc::clone(&config)).unwrap(), stream.unwrap());
+                let mut reader = BufReader::new(&mut tls);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 2 {
+                    line.clear();
+                }
+                let _ = tls.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+                tls.conn.send_close_notify();
+                let _ = tls.flush();
+            }
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.der().clone()).unwrap();
+        let client = TimedClient::with_roots(Arc::new(roots));
+        let response = client
+            .execute(&get(&format!("https://localhost:{}/", port)), Duration::from_secs(5), Some(10))
+            .unwrap();
+        assert_eq!(response.body.as_deref(), Some(&b"ok"[..]));
+        assert!(response.timings.tls.unwrap() >= Duration::from_millis(100));
+
+        // The bundled roots do not trust the test certificate
+        let err = execute(&format!("https://localhost:{}/", port), None).unwrap_err();
+        assert!(!err.retryable, "{}", err);
+    }
+}